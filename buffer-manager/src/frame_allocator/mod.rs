@@ -1,28 +1,27 @@
-pub struct FrameAllocator<const PAGE_SIZE: usize, const CAPACITY: usize> {
+pub struct FrameAllocator {
+    frame_count: usize,
     bitmap: Vec<u8>,
 }
 
-impl<const PAGE_SIZE: usize, const CAPACITY: usize> FrameAllocator<PAGE_SIZE, CAPACITY> {
-    pub fn size() -> usize {
-        (CAPACITY / PAGE_SIZE) / 8
-            + if (CAPACITY / PAGE_SIZE) % 8 == 0 {
-                0
-            } else {
-                1
-            }
+impl FrameAllocator {
+    pub fn size(&self) -> usize {
+        self.bitmap.len()
     }
 
-    pub fn init() -> Self {
-        let allocator = Self {
-            bitmap: vec![0; Self::size()],
-        };
-        allocator
+    pub fn init(frame_count: usize) -> Self {
+        Self {
+            frame_count,
+            bitmap: vec![0; frame_count.div_ceil(8)],
+        }
     }
 
     pub unsafe fn allocate_frame(&mut self) -> Option<u32> {
-        for i in 0..Self::size() {
+        for i in 0..self.size() {
             let byte = self.bitmap[i];
             for j in 0..8 {
+                if i * 8 + j >= self.frame_count {
+                    return None;
+                }
                 if byte & (1 << j) == 0 {
                     self.bitmap[i] |= 1 << j;
                     return Some((i * 8 + j) as u32);
@@ -31,6 +30,17 @@ impl<const PAGE_SIZE: usize, const CAPACITY: usize> FrameAllocator<PAGE_SIZE, CA
         }
         None
     }
+
+    /// ### Safety: The frame must not be mapped to any page
+    pub unsafe fn free_frame(&mut self, frame_number: u32) {
+        let frame_number = frame_number as usize;
+        assert!(
+            frame_number < self.frame_count,
+            "Frame {} out of bound",
+            frame_number
+        );
+        self.bitmap[frame_number / 8] &= !(1 << (frame_number % 8));
+    }
 }
 
 #[cfg(test)]
@@ -39,8 +49,32 @@ mod tests {
 
     #[test]
     fn test_allocate_frame() {
-        let mut allocator = FrameAllocator::<1024, 4096>::init();
+        let mut allocator = FrameAllocator::init(4);
         assert_eq!(unsafe { allocator.allocate_frame() }, Some(0));
         assert_eq!(unsafe { allocator.allocate_frame() }, Some(1));
     }
+
+    #[test]
+    fn allocate_up_to_frame_count() {
+        let mut allocator = FrameAllocator::init(3);
+        assert_eq!(unsafe { allocator.allocate_frame() }, Some(0));
+        assert_eq!(unsafe { allocator.allocate_frame() }, Some(1));
+        assert_eq!(unsafe { allocator.allocate_frame() }, Some(2));
+        assert_eq!(unsafe { allocator.allocate_frame() }, None);
+    }
+
+    #[test]
+    fn reuse_freed_frame() {
+        let mut allocator = FrameAllocator::init(10);
+        for i in 0..10 {
+            assert_eq!(unsafe { allocator.allocate_frame() }, Some(i));
+        }
+        unsafe {
+            allocator.free_frame(9);
+            allocator.free_frame(3);
+        }
+        assert_eq!(unsafe { allocator.allocate_frame() }, Some(3));
+        assert_eq!(unsafe { allocator.allocate_frame() }, Some(9));
+        assert_eq!(unsafe { allocator.allocate_frame() }, None);
+    }
 }
@@ -0,0 +1,124 @@
+use std::{
+    alloc::{self, Layout},
+    cell::UnsafeCell,
+    ptr::NonNull,
+};
+
+const PAGE_ALIGNMENT: usize = 4096;
+const HUGE_PAGE_ALIGNMENT: usize = 2 * 1024 * 1024;
+
+/// A frame is mutated through shared references to the pool,
+/// which pages coordinate by pinning, so it lives inside an `UnsafeCell`
+pub(crate) type Frame<const BLOCK_SIZE: usize> = UnsafeCell<[u8; BLOCK_SIZE]>;
+
+/// Page aligned memory holding every frame of a buffer pool
+pub(crate) struct FrameMemory<const BLOCK_SIZE: usize> {
+    frames: NonNull<Frame<BLOCK_SIZE>>,
+    frame_count: usize,
+    layout: Layout,
+}
+
+// Frames are only handed out through `UnsafeCell`, access to them
+// is synchronized by the buffer manager.
+unsafe impl<const BLOCK_SIZE: usize> Send for FrameMemory<BLOCK_SIZE> {}
+unsafe impl<const BLOCK_SIZE: usize> Sync for FrameMemory<BLOCK_SIZE> {}
+
+impl<const BLOCK_SIZE: usize> FrameMemory<BLOCK_SIZE> {
+    /// Allocate `frame_count` zeroed frames.
+    /// With `huge_pages` the memory is aligned and advised for transparent huge pages,
+    /// which is only a hint to the operating system.
+    pub fn new(frame_count: usize, huge_pages: bool) -> Self {
+        assert!(frame_count > 0, "Buffer pool needs at least one frame");
+        assert!(BLOCK_SIZE > 0, "Block size must not be zero");
+        let alignment = if huge_pages {
+            HUGE_PAGE_ALIGNMENT
+        } else {
+            PAGE_ALIGNMENT
+        };
+        let layout = Layout::from_size_align(frame_count * BLOCK_SIZE, alignment)
+            .expect("Buffer pool is too large")
+            .pad_to_align();
+        let memory = unsafe { alloc::alloc_zeroed(layout) };
+        let frames = match NonNull::new(memory as *mut Frame<BLOCK_SIZE>) {
+            Some(frames) => frames,
+            None => alloc::handle_alloc_error(layout),
+        };
+        if huge_pages {
+            advise_huge_pages(memory, layout.size());
+        }
+        Self {
+            frames,
+            frame_count,
+            layout,
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    pub fn frame(&self, frame_number: u32) -> &Frame<BLOCK_SIZE> {
+        assert!(
+            (frame_number as usize) < self.frame_count,
+            "Frame {} out of bound",
+            frame_number
+        );
+        unsafe { &*self.frames.as_ptr().add(frame_number as usize) }
+    }
+}
+
+impl<const BLOCK_SIZE: usize> Drop for FrameMemory<BLOCK_SIZE> {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.frames.as_ptr() as *mut u8, self.layout) }
+    }
+}
+
+#[cfg(all(target_os = "linux", not(miri)))]
+fn advise_huge_pages(memory: *mut u8, len: usize) {
+    let rs = unsafe { libc::madvise(memory as *mut libc::c_void, len, libc::MADV_HUGEPAGE) };
+    if rs != 0 {
+        log::info!(
+            "Huge pages unavailable: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(all(target_os = "linux", not(miri))))]
+fn advise_huge_pages(_memory: *mut u8, _len: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_aligned() {
+        let memory = FrameMemory::<512>::new(3, false);
+        assert_eq!(memory.frame_count(), 3);
+        assert_eq!(memory.frame(0).get() as usize % PAGE_ALIGNMENT, 0);
+        assert_eq!(
+            memory.frame(1).get() as usize - memory.frame(0).get() as usize,
+            512
+        );
+        let memory = FrameMemory::<4096>::new(2, true);
+        assert_eq!(memory.frame(0).get() as usize % HUGE_PAGE_ALIGNMENT, 0);
+    }
+
+    #[test]
+    fn frames_are_independent() {
+        let memory = FrameMemory::<512>::new(2, false);
+        unsafe {
+            (*memory.frame(0).get()).fill(1);
+            (*memory.frame(1).get()).fill(2);
+            assert!((*memory.frame(0).get()).iter().all(|b| *b == 1));
+            assert!((*memory.frame(1).get()).iter().all(|b| *b == 2));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn frame_out_of_bound() {
+        let memory = FrameMemory::<512>::new(2, false);
+        memory.frame(2);
+    }
+}
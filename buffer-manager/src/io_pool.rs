@@ -0,0 +1,64 @@
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed set of threads running blocking disk I/O for asynchronous callers
+pub(crate) struct IoPool {
+    sender: Mutex<Sender<Job>>,
+}
+
+impl IoPool {
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "I/O pool needs at least one thread");
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("buffer-io-{}", i))
+                .spawn(move || loop {
+                    // The lock is released before running the job
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        // Every sender is gone, the pool has been dropped
+                        Err(_) => return,
+                    }
+                })
+                .expect("Failed to spawn I/O thread");
+        }
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    /// Run `job` on one of the threads of the pool.
+    /// Threads are not joined when the pool is dropped, they exit once the queue is empty.
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        self.sender
+            .lock()
+            .unwrap()
+            .send(Box::new(job))
+            .expect("I/O threads exited");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_jobs() {
+        let pool = IoPool::new(2);
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..10 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(i).unwrap());
+        }
+        let mut results: Vec<_> = receiver.iter().take(10).collect();
+        results.sort();
+        assert_eq!(results, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+/// Page numbers currently latched, with the thread holding the latch.
+/// A latch is independent from pinning: it only keeps other latchers of the page waiting,
+/// so a structure spanning several calls can be modified by one thread at a time.
+#[derive(Default)]
+pub(super) struct PageLatches {
+    latched: Mutex<HashMap<u32, ThreadId>>,
+    released: Condvar,
+}
+
+impl PageLatches {
+    /// Panics if the current thread already holds the latch, which would wait for itself
+    pub(super) fn acquire(&self, page_number: u32) -> PageLatch<'_> {
+        let current = thread::current().id();
+        let mut latched = self.latched.lock().unwrap();
+        while let Some(holder) = latched.get(&page_number) {
+            if *holder == current {
+                drop(latched);
+                panic!("Page {} is already latched by this thread", page_number);
+            }
+            latched = self.released.wait(latched).unwrap();
+        }
+        latched.insert(page_number, current);
+        PageLatch {
+            page_number,
+            latches: self,
+        }
+    }
+}
+
+/// Exclusive latch on a page, released when dropped. See `BufferManager::latch_page`.
+pub struct PageLatch<'a> {
+    page_number: u32,
+    latches: &'a PageLatches,
+}
+
+impl<'a> PageLatch<'a> {
+    pub fn page_number(&self) -> u32 {
+        self.page_number
+    }
+}
+
+impl<'a> Drop for PageLatch<'a> {
+    fn drop(&mut self) {
+        self.latches
+            .latched
+            .lock()
+            .unwrap()
+            .remove(&self.page_number);
+        self.latches.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::PageLatches;
+
+    #[test]
+    fn exclusive() {
+        let latches = PageLatches::default();
+        let in_section = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..20 {
+                        let _latch = latches.acquire(3);
+                        assert!(!in_section.swap(true, Ordering::SeqCst));
+                        thread::sleep(Duration::from_micros(50));
+                        in_section.store(false, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        // Latches on other pages do not wait for each other
+        let _first = latches.acquire(1);
+        let _second = latches.acquire(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "already latched by this thread")]
+    fn latched_twice_by_one_thread() {
+        let latches = PageLatches::default();
+        let _latch = latches.acquire(3);
+        latches.acquire(3);
+    }
+}
@@ -1,111 +1,616 @@
 mod frame_allocator;
+mod frame_memory;
+mod io_pool;
+mod latch;
+mod metrics;
 mod page;
+mod page_future;
 mod page_table;
+mod shadow_page;
 
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use disk::Disk;
+use disk::{Disk, DiskError};
 
 use frame_allocator::FrameAllocator;
+use frame_memory::FrameMemory;
+use io_pool::IoPool;
+pub use latch::PageLatch;
+use latch::PageLatches;
+use metrics::Metrics;
+pub use metrics::{Event, LatencyHistogram, MetricsSnapshot, Observer, LATENCY_BUCKETS};
 pub use page::Page;
+use page_future::LoadState;
+pub use page_future::PageFuture;
+use page_table::Borrow;
 pub use page_table::PageTable;
+pub use shadow_page::ShadowPage;
 
-#[derive(Clone)]
-pub struct BufferManager<
-    const BLOCK_SIZE: usize,
-    const DISK_CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
-> {
-    page_table: PageTable<BLOCK_SIZE, DISK_CAPACITY>,
-    frame_allocator: Arc<Mutex<FrameAllocator<BLOCK_SIZE, MEMORY_CAPACITY>>>,
-    memory: Arc<Box<[u8; MEMORY_CAPACITY]>>,
+#[derive(Debug)]
+pub enum BufferManagerError {
+    PagePinned(u32),
+    /// The page was written to since a shadow of it was taken, see `BufferManager::publish_all`
+    Conflict(u32),
+    DiskError(DiskError),
+    IoError(std::io::Error),
+    /// Every frame stayed pinned while waiting for one to load the page
+    NoFreeFrame(u32),
+}
+
+/// How long `get_page` waits for a frame to be unpinned when every frame is pinned,
+/// and a page handle waits for other handles to stop accessing its frame
+const PIN_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of threads loading pages for `get_page_async`
+const IO_THREADS: usize = 4;
+
+/// State of a page resident in the buffer pool, as reported by `BufferManager::snapshot`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageInfo {
+    pub page_number: u32,
+    pub frame_number: u32,
+    pub pin_count: u8,
+    pub dirty: bool,
+    /// Last time the page was pinned, relative to the creation of the pool
+    pub last_access: Duration,
+}
+
+/// Pages whose frame is read from or written to the disk without holding the allocator lock
+#[derive(Default)]
+struct InFlight {
+    pages: HashSet<u32>,
+    /// Evicted pages which are still being written back
+    write_backs: usize,
+}
+
+/// State shared by every handle of a `BufferManager`.
+/// Dropping the last handle flushes every dirty page back to the disk.
+struct Pool<const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> {
+    page_table: PageTable,
+    frame_allocator: Mutex<FrameAllocator>,
+    /// Only changed while holding the allocator lock, so a page found in neither
+    /// the page table nor this set is not being loaded or evicted
+    in_flight: Mutex<InFlight>,
+    /// Notified when a page leaves `in_flight`
+    landed: Condvar,
+    memory: FrameMemory<BLOCK_SIZE>,
     disk: Disk<BLOCK_SIZE, DISK_CAPACITY>,
+    metrics: Metrics,
+    observer: RwLock<Option<Arc<dyn Observer>>>,
+    /// Started on the first asynchronous request
+    io: OnceLock<IoPool>,
+    latches: PageLatches,
+}
+
+impl<const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> Pool<BLOCK_SIZE, DISK_CAPACITY> {
+    /// ### Safety: The frame must not be mutated while the returned slice is alive
+    unsafe fn frame(&self, frame_number: u32) -> &[u8] {
+        &*self.memory.frame(frame_number).get()
+    }
+
+    /// ### Safety: The frame must not be accessed while the returned slice is alive
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn frame_mut(&self, frame_number: u32) -> &mut [u8] {
+        &mut *self.memory.frame(frame_number).get()
+    }
+
+    fn emit(&self, event: Event) {
+        self.metrics.record(&event, BLOCK_SIZE);
+        if let Some(observer) = self.observer.read().unwrap().as_ref() {
+            observer.on_event(&event);
+        }
+    }
+
+    fn read_block(&self, page_number: u32) -> Result<Box<[u8; BLOCK_SIZE]>, DiskError> {
+        let start = Instant::now();
+        let data = self.disk.read_block(page_number as usize)?;
+        self.emit(Event::DiskRead {
+            page_number,
+            latency: start.elapsed(),
+        });
+        Ok(data)
+    }
+
+    /// Write a frame which is not being modified to the disk
+    fn write_frame(&self, page_number: u32, frame_number: u32) -> Result<(), DiskError> {
+        let start = Instant::now();
+        self.disk
+            .write_block(page_number as usize, unsafe { self.frame(frame_number) })?;
+        self.emit(Event::DiskWrite {
+            page_number,
+            latency: start.elapsed(),
+        });
+        self.emit(Event::WriteBack(page_number));
+        Ok(())
+    }
+
+    /// Pin a page if it is already in the pool
+    fn try_pin(&self, page_number: u32) -> Option<u32> {
+        let frame = self.page_table.pin_page(page_number)?;
+        self.emit(Event::Hit(page_number));
+        Some(frame)
+    }
+
+    /// Pin a page, reading it from the disk if it is not in the pool.
+    /// Return the frame holding the page, or the error of the disk if it can not be read.
+    fn load_page(&self, page_number: u32) -> Result<u32, DiskError> {
+        match self.try_load_page(page_number, PIN_WAIT_TIMEOUT)? {
+            Some(frame) => Ok(frame),
+            None => panic!("Every frame of the buffer pool is pinned"),
+        }
+    }
+
+    /// Same as `load_page`, but give up if no frame is released within `timeout`
+    fn try_load_page(&self, page_number: u32, timeout: Duration) -> Result<Option<u32>, DiskError> {
+        let waiting_since = Instant::now();
+        let mut waiting_for_frame = false;
+        loop {
+            // Frames are only handed out while holding the allocator lock,
+            // and the pages they are handed out for are marked as in flight before it is released,
+            // so a page can not be loaded twice or reloaded before its eviction is written back
+            let mut frame_allocator = self.frame_allocator.lock().unwrap();
+            if let Some(frame) = self.try_pin(page_number) {
+                return Ok(Some(frame));
+            }
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if in_flight.pages.contains(&page_number) {
+                // Another thread is loading the page or writing it back, wait for it to land
+                drop(frame_allocator);
+                let Some(remaining) = timeout.checked_sub(waiting_since.elapsed()) else {
+                    return Ok(None);
+                };
+                drop(self.landed.wait_timeout(in_flight, remaining).unwrap());
+                continue;
+            }
+            let (frame, evicted) = match unsafe { frame_allocator.allocate_frame() } {
+                Some(frame) => {
+                    log::info!("New frame allocated: {}", frame);
+                    (frame, None)
+                }
+                None => match self.page_table.evict_oldest_page() {
+                    Some((page_to_evict, entry)) => {
+                        log::info!("Evicting page {}", page_to_evict);
+                        self.emit(Event::Eviction {
+                            page_number: page_to_evict,
+                            dirty: entry.is_dirty(),
+                        });
+                        log::info!("Page {} unmapped", page_to_evict);
+                        // The page was not pinned, so nobody holds a reference to its frame
+                        let evicted = entry.is_dirty().then(|| {
+                            in_flight.pages.insert(page_to_evict);
+                            in_flight.write_backs += 1;
+                            page_to_evict
+                        });
+                        (entry.get_frame_number(), evicted)
+                    }
+                    None => {
+                        // Every frame is pinned, wait for another thread to release one
+                        drop(in_flight);
+                        drop(frame_allocator);
+                        if !waiting_for_frame {
+                            waiting_for_frame = true;
+                            self.emit(Event::PinWait(page_number));
+                        }
+                        if waiting_since.elapsed() >= timeout {
+                            return Ok(None);
+                        }
+                        thread::yield_now();
+                        continue;
+                    }
+                },
+            };
+            in_flight.pages.insert(page_number);
+            drop(in_flight);
+            drop(frame_allocator);
+
+            // Neither page is mapped to the frame, so nobody else can reach it during the I/O
+            let written = match evicted {
+                Some(page_to_evict) => self.write_frame(page_to_evict, frame),
+                None => Ok(()),
+            };
+            let loaded = written.and_then(|()| {
+                self.emit(Event::Miss(page_number));
+                self.read_block(page_number)
+            });
+
+            let mut frame_allocator = self.frame_allocator.lock().unwrap();
+            let result = match (evicted, loaded) {
+                (Some(page_to_evict), Err(e)) => {
+                    // The frame still holds the evicted page, which may not have been written,
+                    // so it is mapped back rather than lost
+                    self.page_table.map_to_frame(page_to_evict, frame);
+                    self.page_table.set_dirty(page_to_evict);
+                    Err(e)
+                }
+                (_, Err(e)) => {
+                    // The frame is not mapped, it is given back unused
+                    unsafe { frame_allocator.free_frame(frame) };
+                    Err(e)
+                }
+                (_, Ok(data)) => {
+                    unsafe { self.frame_mut(frame) }.copy_from_slice(data.as_slice());
+                    self.page_table.map_to_frame(page_number, frame);
+                    log::info!("Page {} mapped to frame {}", page_number, frame);
+                    Ok(self.page_table.pin_page(page_number))
+                }
+            };
+            let mut in_flight = self.in_flight.lock().unwrap();
+            in_flight.pages.remove(&page_number);
+            if let Some(page_to_evict) = evicted {
+                in_flight.pages.remove(&page_to_evict);
+                in_flight.write_backs -= 1;
+            }
+            drop(in_flight);
+            drop(frame_allocator);
+            self.landed.notify_all();
+            return result;
+        }
+    }
+
+    fn io(&self) -> &IoPool {
+        self.io.get_or_init(|| IoPool::new(IO_THREADS))
+    }
+
+    /// Load a page on the I/O threads and report the pinned frame, or the error, through `state`
+    fn load_page_async(self: &Arc<Self>, page_number: u32, state: Arc<Mutex<LoadState>>) {
+        let pool = self.clone();
+        self.io().execute(move || {
+            let loaded = match panic::catch_unwind(AssertUnwindSafe(|| {
+                pool.try_load_page(page_number, PIN_WAIT_TIMEOUT)
+            })) {
+                Ok(Ok(Some(frame))) => Ok(frame),
+                Ok(Ok(None)) => Err(BufferManagerError::NoFreeFrame(page_number)),
+                Ok(Err(e)) => Err(BufferManagerError::DiskError(e)),
+                Err(_) => Err(BufferManagerError::IoError(std::io::Error::other(
+                    "loading the page panicked",
+                ))),
+            };
+            let mut state = state.lock().unwrap();
+            let waker = match std::mem::replace(&mut *state, LoadState::Taken) {
+                LoadState::Pending(waker) => waker,
+                LoadState::Cancelled => {
+                    if loaded.is_ok() {
+                        pool.page_table.drop_page(page_number);
+                    }
+                    *state = LoadState::Cancelled;
+                    return;
+                }
+                _ => unreachable!(),
+            };
+            *state = match loaded {
+                Ok(frame) => LoadState::Loaded(frame),
+                Err(error) => LoadState::Failed(Some(error)),
+            };
+            drop(state);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        });
+    }
+
+    /// Write a page back to the disk if it is dirty.
+    /// Fails with `PagePinned` if a handle keeps writing to the page.
+    fn write_back(&self, page_number: u32) -> Result<(), BufferManagerError> {
+        // Pinned pages are not evicted, so the frame can not be handed to another page
+        // during the write. A page which is not mapped may still be written back by its eviction.
+        if self.page_table.pin_page(page_number).is_none() {
+            self.wait_in_flight(|in_flight| in_flight.pages.contains(&page_number));
+            return Ok(());
+        }
+        let written = self.write_pinned(page_number);
+        self.page_table.drop_page(page_number);
+        written
+    }
+
+    fn write_pinned(&self, page_number: u32) -> Result<(), BufferManagerError> {
+        if self.page_table.is_dirty(page_number) != Some(true) {
+            return Ok(());
+        }
+        let frame_number = self.page_table.get_frame(page_number).unwrap();
+        // The page may have been pinned since the caller checked,
+        // a handle writing to the frame is waited for
+        if !self
+            .page_table
+            .try_borrow_frame(page_number, Borrow::Shared, false)
+        {
+            return Err(BufferManagerError::PagePinned(page_number));
+        }
+        let written = self.write_frame(page_number, frame_number);
+        if written.is_ok() {
+            // Cleared before the borrow is released, so a write made after is not lost
+            self.page_table.clear_dirty(page_number);
+        }
+        self.page_table.release_frame(page_number, Borrow::Shared);
+        written.map_err(BufferManagerError::DiskError)
+    }
+
+    /// Wait until no page in flight matches `waiting`
+    fn wait_in_flight(&self, waiting: impl Fn(&InFlight) -> bool) {
+        let in_flight = self.in_flight.lock().unwrap();
+        drop(
+            self.landed
+                .wait_while(in_flight, |in_flight| waiting(in_flight))
+                .unwrap(),
+        );
+    }
+
+    /// Write back every dirty page which is not pinned, then sync the disk.
+    /// Fail with the first dirty page which is still pinned, if any.
+    fn flush(&self) -> Result<(), BufferManagerError> {
+        let mut pinned = None;
+        for page_number in self.page_table.dirty_pages() {
+            if self.page_table.is_pinned(page_number) == Some(true) {
+                pinned.get_or_insert(page_number);
+                continue;
+            }
+            self.write_back(page_number)?;
+        }
+        // Evicted pages are no longer dirty pages of the table, but may not be on the disk yet
+        self.wait_in_flight(|in_flight| in_flight.write_backs > 0);
+        self.disk.sync().map_err(BufferManagerError::IoError)?;
+        match pinned {
+            Some(page_number) => Err(BufferManagerError::PagePinned(page_number)),
+            None => Ok(()),
+        }
+    }
 }
 
-impl<const BLOCK_SIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    BufferManager<BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>
-{
-    pub fn init(memory: &[u8], disk: &Disk<BLOCK_SIZE, DISK_CAPACITY>) -> Self {
-        let frame_allocator = Arc::new(Mutex::new(FrameAllocator::init()));
-        let page_table = PageTable::init();
+impl<const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> Drop for Pool<BLOCK_SIZE, DISK_CAPACITY> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("Failed to flush buffer pool: {:?}", e);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BufferManager<const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> {
+    pool: Arc<Pool<BLOCK_SIZE, DISK_CAPACITY>>,
+}
+
+impl<const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> BufferManager<BLOCK_SIZE, DISK_CAPACITY> {
+    /// Create a buffer pool of `frames` pages backed by `disk`
+    pub fn init(frames: usize, disk: &Disk<BLOCK_SIZE, DISK_CAPACITY>) -> Self {
+        Self::with_memory(FrameMemory::new(frames, false), disk)
+    }
+
+    /// Same as `init`, but ask the operating system to back the pool with huge pages
+    pub fn init_with_huge_pages(frames: usize, disk: &Disk<BLOCK_SIZE, DISK_CAPACITY>) -> Self {
+        Self::with_memory(FrameMemory::new(frames, true), disk)
+    }
+
+    fn with_memory(
+        memory: FrameMemory<BLOCK_SIZE>,
+        disk: &Disk<BLOCK_SIZE, DISK_CAPACITY>,
+    ) -> Self {
+        let frame_allocator = Mutex::new(FrameAllocator::init(memory.frame_count()));
+        let page_table = PageTable::init(memory.frame_count());
         BufferManager {
-            page_table,
-            frame_allocator,
-            memory: Arc::new(Box::new(memory.try_into().unwrap())),
-            disk: disk.clone(),
+            pool: Arc::new(Pool {
+                page_table,
+                frame_allocator,
+                in_flight: Mutex::default(),
+                landed: Condvar::new(),
+                memory,
+                disk: disk.clone(),
+                metrics: Metrics::default(),
+                observer: RwLock::new(None),
+                io: OnceLock::new(),
+                latches: PageLatches::default(),
+            }),
         }
     }
 
-    pub fn save_page<'a>(&'a self, page_number: u32) -> Result<(), String> {
-        if self.page_table.is_pinned(page_number).unwrap() {
-            return Err(format!("Page {} is pinned", page_number).to_string());
+    /// Number of pages the pool can hold at once
+    pub fn frame_count(&self) -> usize {
+        self.pool.memory.frame_count()
+    }
+
+    pub fn save_page(&self, page_number: u32) -> Result<(), BufferManagerError> {
+        if self.pool.page_table.is_pinned(page_number) == Some(true) {
+            return Err(BufferManagerError::PagePinned(page_number));
         }
-        if self.page_table.is_dirty(page_number).unwrap() {
-            let frame_number = self.page_table.get_frame(page_number).unwrap();
-            let page = Page::init(page_number, frame_number, self);
-            self.disk.write_block(page_number as usize, &page).unwrap();
+        self.pool.write_back(page_number)
+    }
+
+    /// Write every dirty page back to the disk and sync it.
+    /// Pages which are still pinned are skipped and reported as an error.
+    pub fn flush(&self) -> Result<(), BufferManagerError> {
+        self.pool.flush()
+    }
+
+    /// Flush the buffer pool and release this handle.
+    /// Unlike dropping the handle, errors are reported to the caller.
+    pub fn close(self) -> Result<(), BufferManagerError> {
+        self.pool.flush()
+    }
+
+    /// Current value of the buffer pool counters
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.pool.metrics.snapshot()
+    }
+
+    /// Report every event of the buffer pool to `observer`, replacing the previous one
+    pub fn set_observer(&self, observer: impl Observer + 'static) {
+        *self.pool.observer.write().unwrap() = Some(Arc::new(observer));
+    }
+
+    pub fn remove_observer(&self) {
+        *self.pool.observer.write().unwrap() = None;
+    }
+
+    /// List every page currently held by the pool, ordered by page number
+    pub fn snapshot(&self) -> Vec<PageInfo> {
+        self.pool
+            .page_table
+            .iter()
+            .map(|(page_number, entry)| PageInfo {
+                page_number,
+                frame_number: entry.get_frame_number(),
+                pin_count: entry.get_pin(),
+                dirty: entry.is_dirty(),
+                last_access: Duration::from_secs_f32(entry.timestamp()),
+            })
+            .collect()
+    }
+
+    /// Drop a page from the pool without writing it back, freeing its frame.
+    /// Use this when the block behind the page has been deallocated,
+    /// so stale data is never written over a reused block.
+    pub fn discard_page(&self, page_number: u32) -> Result<(), BufferManagerError> {
+        let pool = &self.pool;
+        let mut frame_allocator = pool.frame_allocator.lock().unwrap();
+        if let Some(entry) = pool.page_table.unmap_page(page_number)? {
+            log::info!("Page {} discarded", page_number);
+            // The page was not pinned and is unmapped now, nobody can reach the frame
+            unsafe { frame_allocator.free_frame(entry.get_frame_number()) };
         }
         Ok(())
     }
 
     // TODO: How about create a new page?
-    pub fn get_page<'a>(
+    /// Panics if the page can not be read from the disk, see `try_get_page`
+    pub fn get_page<'a>(&'a self, page_number: u32) -> Page<'a, BLOCK_SIZE, DISK_CAPACITY> {
+        self.try_get_page(page_number).unwrap()
+    }
+
+    /// Same as `get_page`, but fail with the error of the disk if the page can not be read,
+    /// such as a block of an encrypted disk which was changed outside of it
+    pub fn try_get_page<'a>(
         &'a self,
         page_number: u32,
-    ) -> Page<'a, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> {
-        match self.page_table.get_frame(page_number) {
-            Some(frame) => {
-                let page = Page::init(page_number, frame, self);
-                page
-            }
-            None => {
-                let new_frame = unsafe { self.frame_allocator.lock().unwrap().allocate_frame() };
-                let frame = match new_frame {
-                    Some(frame) => {
-                        assert!((frame as usize) < MEMORY_CAPACITY / BLOCK_SIZE);
-                        log::info!("New frame allocated: {}", frame);
-                        frame
-                    }
-                    None => {
-                        let page_to_evict = self.page_table.get_oldest_page().unwrap();
-                        self.save_page(page_to_evict).unwrap();
-                        log::info!("Evicting page {}", page_to_evict);
-                        let frame_to_evict = self.page_table.get_frame(page_to_evict).unwrap();
-                        self.page_table.unmap_page(page_to_evict);
-                        log::info!("Page {} unmapped", page_to_evict);
-                        frame_to_evict
+    ) -> Result<Page<'a, BLOCK_SIZE, DISK_CAPACITY>, BufferManagerError> {
+        let frame = self.pin(page_number)?;
+        Ok(Page::init(page_number, frame, self))
+    }
+
+    fn pin(&self, page_number: u32) -> Result<u32, BufferManagerError> {
+        match self.pool.try_pin(page_number) {
+            Some(frame) => Ok(frame),
+            None => self
+                .pool
+                .load_page(page_number)
+                .map_err(BufferManagerError::DiskError),
+        }
+    }
+
+    /// Wait until nobody else holds the latch of a page, and take it.
+    /// Latching does not pin the page, it lets callers agree on who modifies
+    /// a structure whose state lives in that page.
+    /// Panics if the calling thread already holds the latch, rather than waiting for itself.
+    pub fn latch_page(&self, page_number: u32) -> PageLatch<'_> {
+        self.pool.latches.acquire(page_number)
+    }
+
+    /// Get a private copy of a page, see `ShadowPage`
+    pub fn shadow_page<'a>(
+        &'a self,
+        page_number: u32,
+    ) -> ShadowPage<'a, BLOCK_SIZE, DISK_CAPACITY> {
+        let frame = self.pin(page_number).unwrap();
+        ShadowPage::init(page_number, frame, self)
+    }
+
+    /// Copy every shadow back to its page at once: a reader pinning any of the pages
+    /// afterwards sees all of the changes, a reader before sees none of them.
+    /// Fails with `Conflict` if a page was written to since its shadow was taken,
+    /// including by the publication of another shadow, or if two shadows are of the same page.
+    /// Waits for the handles reading the pages to release them,
+    /// and fails with `PagePinned` if they do not in time.
+    /// Nothing is published when it fails, the shadows keep their changes for another try.
+    /// Once published, a shadow is current again and can be changed and published anew.
+    pub fn publish_all(
+        &self,
+        shadows: &mut [ShadowPage<'_, BLOCK_SIZE, DISK_CAPACITY>],
+    ) -> Result<(), BufferManagerError> {
+        let pages: Vec<(u32, u32)> = shadows
+            .iter()
+            .map(|shadow| (shadow.page_number(), shadow.version()))
+            .collect();
+        let mut page_numbers: Vec<u32> =
+            pages.iter().map(|(page_number, _)| *page_number).collect();
+        page_numbers.sort_unstable();
+        if let Some(page) = page_numbers.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(BufferManagerError::Conflict(page[0]));
+        }
+        let waiting_since = Instant::now();
+        loop {
+            // Shards of the pages are locked, nobody else can pin them and reach the frames
+            let published = self.pool.page_table.update_exclusive(&pages, || {
+                for shadow in shadows.iter() {
+                    unsafe { shadow.write_to_frame() };
+                }
+            });
+            match published {
+                Ok(versions) => {
+                    for (shadow, version) in shadows.iter_mut().zip(versions) {
+                        shadow.set_version(version);
                     }
-                };
-                unsafe {
-                    let mut memory_ptr = self.memory.as_ptr() as *mut u8;
-                    memory_ptr = memory_ptr.add(frame as usize * BLOCK_SIZE);
-                    let memory_slice = std::slice::from_raw_parts_mut(memory_ptr, BLOCK_SIZE);
-                    let data = self.disk.read_block(page_number as usize).unwrap();
-                    memory_slice.copy_from_slice(data.as_slice());
+                    return Ok(());
+                }
+                Err(BufferManagerError::PagePinned(_))
+                    if waiting_since.elapsed() < PIN_WAIT_TIMEOUT =>
+                {
+                    thread::yield_now();
                 }
-                self.page_table.map_to_frame(page_number, frame);
-                log::info!("Page {} mapped to frame {}", page_number, frame);
-                let frame = self.page_table.get_frame(page_number).unwrap();
-                let page = Page::init(page_number, frame, self);
-                page
+                Err(e) => return Err(e),
             }
         }
     }
+
+    /// Start reading pages which will be needed soon, without waiting for them.
+    /// Pages are loaded on the I/O threads and left unpinned, so they can still be evicted.
+    /// Pages which are already in the pool, or which can not get a frame right away, are skipped.
+    pub fn prefetch(&self, page_numbers: &[u32]) {
+        for &page_number in page_numbers {
+            if self.pool.page_table.get_frame(page_number).is_some() {
+                continue;
+            }
+            // Queued prefetches do not keep the pool alive
+            let pool = Arc::downgrade(&self.pool);
+            self.pool.io().execute(move || {
+                let pool = match pool.upgrade() {
+                    Some(pool) => pool,
+                    None => return,
+                };
+                if pool.page_table.get_frame(page_number).is_some() {
+                    return;
+                }
+                // Pages which can not be read are left for `get_page` to report
+                if let Ok(Some(_)) = pool.try_load_page(page_number, Duration::ZERO) {
+                    pool.page_table.drop_page(page_number);
+                }
+            });
+        }
+    }
+
+    /// Same as `get_page`, but a page which is not in the pool is read on a
+    /// background I/O thread instead of blocking the caller
+    pub fn get_page_async<'a>(
+        &'a self,
+        page_number: u32,
+    ) -> PageFuture<'a, BLOCK_SIZE, DISK_CAPACITY> {
+        PageFuture::init(page_number, self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::BufferManager;
+    use std::sync::Arc;
+
+    use super::{BufferManager, BufferManagerError};
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
-    const MEMORY_CAPACITY: usize = 4096 * 16;
+    const FRAMES: usize = 16;
 
     #[test]
     fn multithreaded() {
         let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("multithreaded").unwrap();
-        let memory = [0u8; 4096 * 16];
-        let buffer_manager: BufferManager<4096, DISK_CAPACITY, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
         let mut handles = vec![];
         for i in 0..32 {
             let buffer_manager = buffer_manager.clone();
@@ -138,9 +643,8 @@ mod tests {
     fn write_reload() {
         let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("write_reload").unwrap();
         {
-            let memory = [0u8; 4096 * 16];
-            let buffer_manager: BufferManager<4096, DISK_CAPACITY, MEMORY_CAPACITY> =
-                BufferManager::init(&memory, &disk);
+            let buffer_manager: BufferManager<4096, DISK_CAPACITY> =
+                BufferManager::init(FRAMES, &disk);
             let mut page1 = buffer_manager.get_page(5);
             page1.copy_from_slice(&[1u8; 4096]);
             let mut page2 = buffer_manager.get_page(14);
@@ -151,9 +655,8 @@ mod tests {
             buffer_manager.save_page(14).unwrap();
         }
         {
-            let memory = [0u8; 4096 * 16];
-            let buffer_manager: BufferManager<4096, DISK_CAPACITY, MEMORY_CAPACITY> =
-                BufferManager::init(&memory, &disk);
+            let buffer_manager: BufferManager<4096, DISK_CAPACITY> =
+                BufferManager::init(FRAMES, &disk);
             let page1 = buffer_manager.get_page(5);
             assert_eq!(page1[0], 1u8);
             let page2 = buffer_manager.get_page(14);
@@ -161,23 +664,484 @@ mod tests {
         }
     }
 
+    #[test]
+    fn flush_on_drop() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("flush_on_drop").unwrap();
+        {
+            let buffer_manager: BufferManager<4096, DISK_CAPACITY> =
+                BufferManager::init(FRAMES, &disk);
+            let mut page = buffer_manager.get_page(7);
+            page.copy_from_slice(&[7u8; 4096]);
+            drop(page);
+            let other_handle = buffer_manager.clone();
+            drop(buffer_manager);
+            let mut page = other_handle.get_page(8);
+            page.copy_from_slice(&[8u8; 4096]);
+        }
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::connect("flush_on_drop").unwrap();
+        assert_eq!(disk.read_block(7).unwrap()[0], 7u8);
+        assert_eq!(disk.read_block(8).unwrap()[0], 8u8);
+    }
+
+    #[test]
+    fn flush_and_close() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("flush_and_close").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        let mut page1 = buffer_manager.get_page(3);
+        page1.copy_from_slice(&[3u8; 4096]);
+        let mut page2 = buffer_manager.get_page(4);
+        page2.copy_from_slice(&[4u8; 4096]);
+        drop(page1);
+        match buffer_manager.flush() {
+            Err(BufferManagerError::PagePinned(4)) => {}
+            other => panic!("Expected page 4 to be reported as pinned, got {:?}", other),
+        }
+        assert_eq!(disk.read_block(3).unwrap()[0], 3u8);
+        drop(page2);
+        buffer_manager.close().unwrap();
+        assert_eq!(disk.read_block(4).unwrap()[0], 4u8);
+    }
+
+    #[test]
+    fn discard_page() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("discard_page").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        let mut page = buffer_manager.get_page(6);
+        page.copy_from_slice(&[6u8; 4096]);
+        match buffer_manager.discard_page(6) {
+            Err(BufferManagerError::PagePinned(6)) => {}
+            other => panic!("Expected page 6 to be reported as pinned, got {:?}", other),
+        }
+        drop(page);
+        buffer_manager.discard_page(6).unwrap();
+        // Discarding a page which is not cached is a no-op
+        buffer_manager.discard_page(6).unwrap();
+        buffer_manager.flush().unwrap();
+        assert_eq!(disk.read_block(6).unwrap()[0], 0u8);
+        assert_eq!(buffer_manager.get_page(6)[0], 0u8);
+
+        // Freed frames are reused before anything gets evicted
+        let pages: Vec<_> = (0..FRAMES as u32)
+            .map(|i| buffer_manager.get_page(i))
+            .collect();
+        drop(pages);
+        buffer_manager.discard_page(3).unwrap();
+        let _page = buffer_manager.get_page(20);
+        for i in (0..FRAMES as u32).filter(|i| *i != 3) {
+            assert!(buffer_manager.pool.page_table.get_frame(i).is_some());
+        }
+    }
+
+    #[test]
+    fn snapshot() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("snapshot").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        assert!(buffer_manager.snapshot().is_empty());
+        let mut page1 = buffer_manager.get_page(9);
+        page1.copy_from_slice(&[9u8; 4096]);
+        drop(page1);
+        let page2 = buffer_manager.get_page(2);
+        let snapshot = buffer_manager.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].page_number, 2);
+        assert_eq!(snapshot[0].pin_count, 1);
+        assert!(!snapshot[0].dirty);
+        assert_eq!(snapshot[1].page_number, 9);
+        assert_eq!(snapshot[1].pin_count, 0);
+        assert!(snapshot[1].dirty);
+        assert_ne!(snapshot[0].frame_number, snapshot[1].frame_number);
+        assert!(snapshot[0].last_access >= snapshot[1].last_access);
+        drop(page2);
+        buffer_manager.flush().unwrap();
+        assert!(buffer_manager.snapshot().iter().all(|page| !page.dirty));
+    }
+
+    #[test]
+    fn metrics() {
+        use super::{Event, Observer};
+        use std::sync::Mutex;
+
+        struct Recorder(Arc<Mutex<Vec<Event>>>);
+        impl Observer for Recorder {
+            fn on_event(&self, event: &Event) {
+                self.0.lock().unwrap().push(*event);
+            }
+        }
+
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("metrics").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(2, &disk);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        buffer_manager.set_observer(Recorder(events.clone()));
+
+        let mut page = buffer_manager.get_page(0);
+        page.copy_from_slice(&[1u8; 4096]);
+        drop(page);
+        drop(buffer_manager.get_page(0));
+        drop(buffer_manager.get_page(1));
+        // Evicts page 0, which is dirty
+        drop(buffer_manager.get_page(2));
+
+        let metrics = buffer_manager.metrics();
+        assert_eq!(metrics.hits, 1);
+        assert_eq!(metrics.misses, 3);
+        assert_eq!(metrics.evictions, 1);
+        assert_eq!(metrics.dirty_writebacks, 1);
+        assert_eq!(metrics.pin_waits, 0);
+        assert_eq!(metrics.bytes_read, 3 * 4096);
+        assert_eq!(metrics.bytes_written, 4096);
+        assert_eq!(metrics.read_latency.count, 3);
+        assert_eq!(metrics.write_latency.count, 1);
+        assert_eq!(metrics.hit_ratio(), Some(0.25));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events[0], Event::Miss(0));
+        assert_eq!(events.iter().filter(|e| **e == Event::Hit(0)).count(), 1);
+        assert!(events.contains(&Event::Eviction {
+            page_number: 0,
+            dirty: true
+        }));
+        assert!(events.contains(&Event::WriteBack(0)));
+    }
+
+    #[test]
+    fn wait_for_pinned_frame() {
+        let disk =
+            disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("wait_for_pinned_frame").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(1, &disk);
+        let page = buffer_manager.get_page(0);
+        let other = buffer_manager.clone();
+        let handle = std::thread::spawn(move || {
+            let page = other.get_page(1);
+            assert_eq!(page[0], 0);
+        });
+        while buffer_manager.metrics().pin_waits == 0 {
+            std::thread::yield_now();
+        }
+        drop(page);
+        handle.join().unwrap();
+        assert_eq!(buffer_manager.metrics().pin_waits, 1);
+    }
+
+    /// Drive a future to completion on the current thread
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    /// Poll every future each time one of them is woken, until all of them are ready
+    async fn join_all<F: std::future::Future>(futures: impl Iterator<Item = F>) -> Vec<F::Output> {
+        let mut futures: Vec<_> = futures.map(Box::pin).collect();
+        let mut outputs: Vec<_> = futures.iter().map(|_| None).collect();
+        std::future::poll_fn(|context| {
+            for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+                if output.is_none() {
+                    if let std::task::Poll::Ready(ready) = future.as_mut().poll(context) {
+                        *output = Some(ready);
+                    }
+                }
+            }
+            if outputs.iter().all(Option::is_some) {
+                std::task::Poll::Ready(())
+            } else {
+                std::task::Poll::Pending
+            }
+        })
+        .await;
+        outputs.into_iter().map(Option::unwrap).collect()
+    }
+
+    #[test]
+    fn get_page_async() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("get_page_async").unwrap();
+        disk.write_block(3, &[3u8; 4096]).unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        let page = block_on(buffer_manager.get_page_async(3)).unwrap();
+        assert_eq!(page[0], 3u8);
+        let again = block_on(buffer_manager.get_page_async(3)).unwrap();
+        assert_eq!(again[4095], 3u8);
+        drop(page);
+        drop(again);
+        let metrics = buffer_manager.metrics();
+        assert_eq!(metrics.misses, 1);
+        assert_eq!(metrics.hits, 1);
+        assert!(!buffer_manager.pool.page_table.is_pinned(3).unwrap());
+
+        // Many pending loads at once, more than the pool has frames
+        let futures: Vec<_> = (0..32).map(|i| buffer_manager.get_page_async(i)).collect();
+        let pages = block_on(join_all(futures.into_iter().zip(0..).map(
+            |(future, i)| async move {
+                let mut page = future.await?;
+                page[0] = i as u8;
+                Ok::<_, BufferManagerError>(page[0] as u32)
+            },
+        )));
+        let pages: Vec<u32> = pages.into_iter().map(Result::unwrap).collect();
+        assert_eq!(pages, (0..32).collect::<Vec<_>>());
+        buffer_manager.flush().unwrap();
+        assert_eq!(disk.read_block(31).unwrap()[0], 31u8);
+
+        // A page which can not be read resolves to the error of the disk
+        assert!(matches!(
+            block_on(buffer_manager.get_page_async(32)),
+            Err(BufferManagerError::DiskError(disk::DiskError::OverCapacity))
+        ));
+    }
+
+    #[test]
+    fn drop_pending_page_future() {
+        let disk =
+            disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("drop_pending_page_future").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        use std::future::Future;
+
+        for i in 0..8 {
+            let mut future = std::pin::pin!(buffer_manager.get_page_async(i));
+            let waker = std::task::Waker::noop();
+            let _ = future
+                .as_mut()
+                .poll(&mut std::task::Context::from_waker(waker));
+        }
+        // Loads finish in the background and give up their pins
+        let start = std::time::Instant::now();
+        while buffer_manager
+            .snapshot()
+            .iter()
+            .any(|page| page.pin_count > 0)
+            || buffer_manager.snapshot().len() < 8
+        {
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn prefetch() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("prefetch").unwrap();
+        disk.write_block(12, &[12u8; 4096]).unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(4, &disk);
+        buffer_manager.prefetch(&[10, 11, 12]);
+        let start = std::time::Instant::now();
+        // Prefetched pages end up in the pool without being pinned
+        while buffer_manager.snapshot().len() < 3
+            || buffer_manager
+                .snapshot()
+                .iter()
+                .any(|page| page.pin_count > 0)
+        {
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            std::thread::yield_now();
+        }
+        let misses = buffer_manager.metrics().misses;
+        assert_eq!(buffer_manager.get_page(12)[0], 12u8);
+        assert_eq!(buffer_manager.metrics().misses, misses);
+
+        // Prefetching never waits for pinned frames
+        let pages: Vec<_> = (0..4).map(|i| buffer_manager.get_page(i)).collect();
+        buffer_manager.prefetch(&[20]);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(buffer_manager.pool.page_table.get_frame(20).is_none());
+        drop(pages);
+    }
+
+    #[test]
+    fn borrowed_frames() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("borrowed_frames").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        // Handles of a page read it together
+        let reader = buffer_manager.get_page(1);
+        let other_reader = buffer_manager.get_page(1);
+        assert_eq!(reader[0], other_reader[0]);
+        drop(other_reader);
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| buffer_manager.get_page(1)[0] = 1);
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            // The writer waits for the reader, which keeps seeing the page unchanged
+            assert!(!writer.is_finished());
+            assert_eq!(reader[0], 0);
+            drop(reader);
+            writer.join().unwrap();
+        });
+
+        let mut page = buffer_manager.get_page(1);
+        page[1] = 2;
+        assert_eq!(page[..2], [1, 2]);
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(|| buffer_manager.get_page(1)[1]);
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            // Readers wait for the handle which wrote to the page to be dropped
+            assert!(!reader.is_finished());
+            page[1] = 3;
+            drop(page);
+            assert_eq!(reader.join().unwrap(), 3);
+        });
+    }
+
+    #[test]
+    fn shadow_page() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("shadow_page").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        buffer_manager.get_page(1).fill(1);
+
+        let mut shadow = buffer_manager.shadow_page(1);
+        assert_eq!(shadow[0], 1);
+        shadow.fill(2);
+        assert_eq!(buffer_manager.get_page(1)[0], 1);
+        shadow.publish().unwrap();
+        assert_eq!(buffer_manager.get_page(1)[0], 2);
+        drop(shadow);
+
+        // Dropping a shadow discards its changes
+        let mut shadow = buffer_manager.shadow_page(1);
+        shadow.fill(3);
+        drop(shadow);
+        assert_eq!(buffer_manager.get_page(1)[0], 2);
+        assert!(!buffer_manager.pool.page_table.is_pinned(1).unwrap());
+
+        buffer_manager.flush().unwrap();
+        assert_eq!(disk.read_block(1).unwrap()[0], 2);
+    }
+
+    #[test]
+    fn publish_all() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("publish_all").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        let mut shadows: Vec<_> = (0..4)
+            .map(|i| {
+                let mut shadow = buffer_manager.shadow_page(i);
+                shadow.fill(i as u8 + 10);
+                shadow
+            })
+            .collect();
+        let reader = buffer_manager.get_page(2);
+        assert_eq!(reader[0], 0);
+        std::thread::scope(|scope| {
+            let publisher = scope.spawn(|| buffer_manager.publish_all(&mut shadows));
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            // The publisher waits for the reader, which still sees the old version
+            assert!(!publisher.is_finished());
+            assert_eq!(reader[0], 0);
+            assert_eq!(buffer_manager.get_page(0)[0], 0);
+            drop(reader);
+            publisher.join().unwrap().unwrap();
+        });
+        for i in 0..4 {
+            assert_eq!(buffer_manager.get_page(i)[0], i as u8 + 10);
+        }
+        assert!(buffer_manager.snapshot().iter().all(|page| page.dirty));
+    }
+
+    #[test]
+    fn conflicting_shadows() {
+        let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("conflicting_shadows").unwrap();
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
+        let mut first = buffer_manager.shadow_page(1);
+        let mut second = buffer_manager.shadow_page(1);
+        first.fill(1);
+        second.fill(2);
+        first.publish().unwrap();
+        // The second shadow was taken before the first one was published
+        assert!(matches!(
+            second.publish(),
+            Err(BufferManagerError::Conflict(1))
+        ));
+        assert_eq!(buffer_manager.get_page(1)[0], 1);
+        // A published shadow is current again
+        first.fill(3);
+        first.publish().unwrap();
+        assert_eq!(buffer_manager.get_page(1)[0], 3);
+
+        // Writing through a page handle makes the shadows of the page stale
+        let mut shadow = buffer_manager.shadow_page(2);
+        shadow.fill(4);
+        buffer_manager.get_page(2)[0] = 5;
+        assert!(matches!(
+            shadow.publish(),
+            Err(BufferManagerError::Conflict(2))
+        ));
+        assert_eq!(buffer_manager.get_page(2)[0], 5);
+
+        let mut shadows = vec![
+            buffer_manager.shadow_page(3),
+            buffer_manager.shadow_page(4),
+            buffer_manager.shadow_page(3),
+        ];
+        shadows[1].fill(6);
+        assert!(matches!(
+            buffer_manager.publish_all(&mut shadows),
+            Err(BufferManagerError::Conflict(3))
+        ));
+        assert_eq!(buffer_manager.get_page(4)[0], 0);
+        // The shadows keep their changes after a failure
+        shadows.remove(2);
+        buffer_manager.publish_all(&mut shadows).unwrap();
+        assert_eq!(buffer_manager.get_page(4)[0], 6);
+    }
+
     #[test]
     fn simple_get_page() {
-        let memory = [0u8; 4096 * 16];
         let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("simple_get_page").unwrap();
-        let buffer_manager: BufferManager<4096, DISK_CAPACITY, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
         let _page1 = buffer_manager.get_page(5);
         // let _page2 = buffer_manager.get_page(14);
     }
 
     #[test]
     fn get_lots_of_pages() {
-        let memory = [0u8; 4096 * 16];
         let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("get_lots_of_pages").unwrap();
-        let buffer_manager: BufferManager<4096, DISK_CAPACITY, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<4096, DISK_CAPACITY> = BufferManager::init(FRAMES, &disk);
         let _page1 = buffer_manager.get_page(5);
         let _page2 = buffer_manager.get_page(14);
     }
+
+    #[test]
+    fn corrupted_block() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        // A small disk, every block of it is encrypted when it is created
+        let disk = disk::Disk::<512, { 512 * 4 }>::create_encrypted(
+            "buffer_manager_corrupted_block",
+            &[7; 32],
+        )
+        .unwrap();
+        let buffer_manager: BufferManager<512, { 512 * 4 }> = BufferManager::init(1, &disk);
+        buffer_manager.get_page(0).copy_from_slice(&[5; 512]);
+        // The last byte of the image belongs to the last block
+        let mut image = std::fs::File::options()
+            .write(true)
+            .open(disk::make_name("buffer_manager_corrupted_block"))
+            .unwrap();
+        image.seek(SeekFrom::End(-1)).unwrap();
+        image.write_all(&[0xff]).unwrap();
+
+        assert!(matches!(
+            buffer_manager.try_get_page(3),
+            Err(BufferManagerError::DiskError(
+                disk::DiskError::CorruptedBlock
+            ))
+        ));
+        // The page evicted for the load is mapped back to its frame
+        assert_eq!(buffer_manager.snapshot()[0].page_number, 0);
+        assert_eq!(buffer_manager.try_get_page(0).unwrap()[0], 5);
+        // The frame taken for a page which can not be read is given back
+        buffer_manager.discard_page(0).unwrap();
+        assert!(buffer_manager.try_get_page(3).is_err());
+        assert!(buffer_manager.snapshot().is_empty());
+        assert_eq!(buffer_manager.try_get_page(1).unwrap()[0], 0);
+    }
 }
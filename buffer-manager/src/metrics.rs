@@ -0,0 +1,213 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Number of buckets of a latency histogram.
+/// Bucket `i` counts operations which took less than `2^i` microseconds,
+/// the last bucket also counts everything slower.
+pub const LATENCY_BUCKETS: usize = 24;
+
+/// Something which happened in the buffer pool, reported to an `Observer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The requested page was already in the pool
+    Hit(u32),
+    /// The requested page had to be read from the disk
+    Miss(u32),
+    /// A page was dropped from the pool to free its frame
+    Eviction {
+        page_number: u32,
+        dirty: bool,
+    },
+    /// A dirty page was written back to the disk
+    WriteBack(u32),
+    /// Every frame was pinned, so loading the page had to wait for one to be released
+    PinWait(u32),
+    DiskRead {
+        page_number: u32,
+        latency: Duration,
+    },
+    DiskWrite {
+        page_number: u32,
+        latency: Duration,
+    },
+}
+
+/// Receive every event of a buffer pool as it happens.
+/// Called on the thread which caused the event, possibly while holding pool locks,
+/// so implementations should return quickly and must not call back into the pool.
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+/// Latency distribution, see `LATENCY_BUCKETS` for the bucket bounds
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    pub buckets: [u64; LATENCY_BUCKETS],
+    pub count: u64,
+    pub total: Duration,
+}
+
+impl LatencyHistogram {
+    /// Upper bound of a bucket
+    pub fn bucket_bound(bucket: usize) -> Duration {
+        Duration::from_micros(1 << bucket)
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos(
+            (self.total.as_nanos() / self.count as u128) as u64,
+        ))
+    }
+}
+
+/// Counters of a buffer pool at one point in time, see `BufferManager::metrics`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub dirty_writebacks: u64,
+    pub pin_waits: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub read_latency: LatencyHistogram,
+    pub write_latency: LatencyHistogram,
+}
+
+impl MetricsSnapshot {
+    /// Fraction of page requests served without reading the disk
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let requests = self.hits + self.misses;
+        if requests == 0 {
+            return None;
+        }
+        Some(self.hits as f64 / requests as f64)
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn record(&self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        // Number of bits needed for `micros`, so that `micros < 2^bucket`
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(LATENCY_BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+            count: self.count.load(Ordering::Relaxed),
+            total: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Counters updated by the buffer pool as events happen
+#[derive(Default)]
+pub(crate) struct Metrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    dirty_writebacks: AtomicU64,
+    pin_waits: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    read_latency: Histogram,
+    write_latency: Histogram,
+}
+
+impl Metrics {
+    pub fn record(&self, event: &Event, block_size: usize) {
+        let counter = match event {
+            Event::Hit(_) => &self.hits,
+            Event::Miss(_) => &self.misses,
+            Event::Eviction { .. } => &self.evictions,
+            Event::WriteBack(_) => &self.dirty_writebacks,
+            Event::PinWait(_) => &self.pin_waits,
+            Event::DiskRead { latency, .. } => {
+                self.read_latency.record(*latency);
+                &self.bytes_read
+            }
+            Event::DiskWrite { latency, .. } => {
+                self.write_latency.record(*latency);
+                &self.bytes_written
+            }
+        };
+        let amount = match event {
+            Event::DiskRead { .. } | Event::DiskWrite { .. } => block_size as u64,
+            _ => 1,
+        };
+        counter.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            dirty_writebacks: self.dirty_writebacks.load(Ordering::Relaxed),
+            pin_waits: self.pin_waits.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            read_latency: self.read_latency.snapshot(),
+            write_latency: self.write_latency.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_buckets() {
+        let histogram = Histogram::default();
+        histogram.record(Duration::from_nanos(500));
+        histogram.record(Duration::from_micros(1));
+        histogram.record(Duration::from_micros(5));
+        histogram.record(Duration::from_secs(3600));
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 4);
+        assert_eq!(snapshot.buckets[0], 1);
+        assert_eq!(snapshot.buckets[1], 1);
+        assert_eq!(snapshot.buckets[3], 1);
+        assert_eq!(snapshot.buckets[LATENCY_BUCKETS - 1], 1);
+        assert!(Duration::from_micros(5) < LatencyHistogram::bucket_bound(3));
+    }
+
+    #[test]
+    fn count_events() {
+        let metrics = Metrics::default();
+        metrics.record(&Event::Hit(1), 512);
+        metrics.record(&Event::Hit(1), 512);
+        metrics.record(&Event::Miss(2), 512);
+        let latency = Duration::from_micros(10);
+        metrics.record(
+            &Event::DiskRead {
+                page_number: 2,
+                latency,
+            },
+            512,
+        );
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.hits, 2);
+        assert_eq!(snapshot.misses, 1);
+        assert_eq!(snapshot.bytes_read, 512);
+        assert_eq!(snapshot.read_latency.mean(), Some(latency));
+        assert_eq!(snapshot.write_latency.mean(), None);
+        assert_eq!(snapshot.hit_ratio(), Some(2.0 / 3.0));
+    }
+}
@@ -1,54 +1,66 @@
-use std::{ptr::slice_from_raw_parts_mut, ops::{Deref, DerefMut}};
+use std::cell::Cell;
+use std::ops::{Deref, DerefMut};
 
-use super::BufferManager;
+use super::{Borrow, BufferManager};
 
-pub struct Page<
-    'a,
-    const PAGE_SIZE: usize,
-    const DISK_CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
-> {
+/// A pinned page of the buffer pool.
+/// The page stays in its frame until every `Page` pointing to it is dropped.
+/// Several handles of a page can read it at once, but a handle which has written to it
+/// is the only one with access to it until it is dropped: other handles wait
+/// for it before reading or writing, and it waits for the others before writing.
+pub struct Page<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> {
     page_number: u32,
     frame_number: u32,
-    buffer_manager: &'a BufferManager<PAGE_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
+    buffer_manager: &'a BufferManager<PAGE_SIZE, DISK_CAPACITY>,
+    /// Access to the frame, taken on first use and held until the handle is dropped
+    borrow: Cell<Option<Borrow>>,
 }
 
-impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    Page<'a, PAGE_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>
-{
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> Page<'a, PAGE_SIZE, DISK_CAPACITY> {
     pub(super) fn init(
         page_number: u32,
         frame_number: u32,
-        buffer_manager: &'a BufferManager<PAGE_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<PAGE_SIZE, DISK_CAPACITY>,
     ) -> Self {
-        buffer_manager.page_table.pin_page(page_number);
         Page {
             page_number,
             frame_number,
             buffer_manager,
+            borrow: Cell::new(None),
         }
     }
 
     fn buffer(&self) -> &[u8] {
-        let frame_number = self.frame_number as usize;
-        &self.buffer_manager.memory[frame_number * PAGE_SIZE..(frame_number + 1) * PAGE_SIZE]
+        if self.borrow.get().is_none() {
+            self.buffer_manager.pool.page_table.borrow_frame(
+                self.page_number,
+                Borrow::Shared,
+                false,
+            );
+            self.borrow.set(Some(Borrow::Shared));
+        }
+        // The page is pinned, so its frame can not be reused while borrowed,
+        // and nobody writes to it while this handle reads it
+        unsafe { self.buffer_manager.pool.frame(self.frame_number) }
     }
 
-    fn buffer_mut(&self) -> &mut [u8] {
-        let frame_number = self.frame_number as usize;
-        let buffer_ptr = unsafe {
-            self.buffer_manager
-                .memory
-                .as_ptr()
-                .add(frame_number * PAGE_SIZE) as *mut u8
-        };
-        let s = slice_from_raw_parts_mut(buffer_ptr, PAGE_SIZE);
-        unsafe { s.as_mut().unwrap() }
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        if self.borrow.get() != Some(Borrow::Exclusive) {
+            let reading = self.borrow.get() == Some(Borrow::Shared);
+            self.buffer_manager.pool.page_table.borrow_frame(
+                self.page_number,
+                Borrow::Exclusive,
+                reading,
+            );
+            self.borrow.set(Some(Borrow::Exclusive));
+        }
+        // No other handle accesses the frame until this one is dropped
+        unsafe { self.buffer_manager.pool.frame_mut(self.frame_number) }
     }
 }
 
-impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACITY: usize> Deref
-    for Page<'a, PAGE_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> Deref
+    for Page<'a, PAGE_SIZE, DISK_CAPACITY>
 {
     type Target = [u8];
 
@@ -57,19 +69,26 @@ impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACI
     }
 }
 
-impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACITY: usize> DerefMut
-    for Page<'a, PAGE_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> DerefMut
+    for Page<'a, PAGE_SIZE, DISK_CAPACITY>
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.buffer_manager.page_table.set_dirty(self.page_number);
+        self.buffer_manager
+            .pool
+            .page_table
+            .set_dirty(self.page_number);
         self.buffer_mut()
     }
 }
 
-impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACITY: usize> Drop
-    for Page<'a, PAGE_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> Drop
+    for Page<'a, PAGE_SIZE, DISK_CAPACITY>
 {
     fn drop(&mut self) {
-        self.buffer_manager.page_table.drop_page(self.page_number);
+        let page_table = &self.buffer_manager.pool.page_table;
+        if let Some(borrow) = self.borrow.get() {
+            page_table.release_frame(self.page_number, borrow);
+        }
+        page_table.drop_page(self.page_number);
     }
 }
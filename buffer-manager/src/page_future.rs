@@ -0,0 +1,102 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::{BufferManager, BufferManagerError, Page};
+
+/// Progress of a page load running on the I/O pool
+pub(crate) enum LoadState {
+    Pending(Option<Waker>),
+    /// The page is loaded and pinned in the frame
+    Loaded(u32),
+    /// The future was dropped before the page was loaded
+    Cancelled,
+    /// The page could not be loaded, the error is handed to the future once
+    Failed(Option<BufferManagerError>),
+    /// The frame has been handed to a `Page`
+    Taken,
+}
+
+/// A page being loaded by `BufferManager::get_page_async`,
+/// resolving to the error of the load if the page can not be read or no frame is released
+pub struct PageFuture<'a, const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> {
+    page_number: u32,
+    buffer_manager: &'a BufferManager<BLOCK_SIZE, DISK_CAPACITY>,
+    state: Option<Arc<Mutex<LoadState>>>,
+}
+
+impl<'a, const BLOCK_SIZE: usize, const DISK_CAPACITY: usize>
+    PageFuture<'a, BLOCK_SIZE, DISK_CAPACITY>
+{
+    pub(crate) fn init(
+        page_number: u32,
+        buffer_manager: &'a BufferManager<BLOCK_SIZE, DISK_CAPACITY>,
+    ) -> Self {
+        Self {
+            page_number,
+            buffer_manager,
+            state: None,
+        }
+    }
+}
+
+impl<'a, const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> Future
+    for PageFuture<'a, BLOCK_SIZE, DISK_CAPACITY>
+{
+    type Output = Result<Page<'a, BLOCK_SIZE, DISK_CAPACITY>, BufferManagerError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let pool = &this.buffer_manager.pool;
+        if this.state.is_none() {
+            if let Some(frame) = pool.try_pin(this.page_number) {
+                return Poll::Ready(Ok(Page::init(this.page_number, frame, this.buffer_manager)));
+            }
+            let state = Arc::new(Mutex::new(LoadState::Pending(None)));
+            pool.load_page_async(this.page_number, state.clone());
+            this.state = Some(state);
+        }
+        let mut state = this.state.as_ref().unwrap().lock().unwrap();
+        match &mut *state {
+            LoadState::Pending(waker) => {
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            LoadState::Loaded(frame) => {
+                let frame = *frame;
+                *state = LoadState::Taken;
+                Poll::Ready(Ok(Page::init(this.page_number, frame, this.buffer_manager)))
+            }
+            LoadState::Failed(error) => match error.take() {
+                Some(error) => Poll::Ready(Err(error)),
+                None => panic!("PageFuture polled after completion"),
+            },
+            LoadState::Cancelled | LoadState::Taken => panic!("PageFuture polled after completion"),
+        }
+    }
+}
+
+impl<'a, const BLOCK_SIZE: usize, const DISK_CAPACITY: usize> Drop
+    for PageFuture<'a, BLOCK_SIZE, DISK_CAPACITY>
+{
+    fn drop(&mut self) {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return,
+        };
+        let mut state = state.lock().unwrap();
+        match *state {
+            // The page was pinned for this future, release it
+            LoadState::Loaded(_) => {
+                self.buffer_manager
+                    .pool
+                    .page_table
+                    .drop_page(self.page_number);
+                *state = LoadState::Taken;
+            }
+            LoadState::Pending(_) => *state = LoadState::Cancelled,
+            LoadState::Failed(_) | LoadState::Cancelled | LoadState::Taken => {}
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use super::PageTableEntry;
 
-pub struct PageTableIterator<const BLOCKSIZE: usize, const CAPACITY: usize> {
+/// Iterator over a snapshot of the pages mapped in a `PageTable`
+pub struct PageTableIterator {
     current: usize,
-    entries: Vec<Option<PageTableEntry>>,
+    entries: Vec<(u32, PageTableEntry)>,
 }
 
-impl<const BLOCKSIZE: usize, const CAPACITY: usize> PageTableIterator<BLOCKSIZE, CAPACITY> {
-    pub fn new(entries: Vec<Option<PageTableEntry>>) -> Self {
+impl PageTableIterator {
+    pub fn new(entries: Vec<(u32, PageTableEntry)>) -> Self {
         Self {
             current: 0,
             entries,
@@ -14,13 +15,17 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> PageTableIterator<BLOCKSIZE,
     }
 }
 
-impl<const BLOCKSIZE: usize, const CAPACITY: usize> Iterator
-    for PageTableIterator<BLOCKSIZE, CAPACITY>
-{
-    type Item = PageTableEntry;
+impl Iterator for PageTableIterator {
+    type Item = (u32, PageTableEntry);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let entry = self.entries.get(self.current)?.clone();
-        entry
+        let entry = *self.entries.get(self.current)?;
+        self.current += 1;
+        Some(entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.entries.len() - self.current;
+        (remaining, Some(remaining))
     }
 }
@@ -1,67 +1,249 @@
-mod page_table_entry;
 mod iter;
+mod page_table_entry;
 
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::time;
 
 pub use page_table_entry::PageTableEntry;
+use page_table_entry::WRITING;
 
 use self::iter::PageTableIterator;
+use crate::{BufferManagerError, PIN_WAIT_TIMEOUT};
+
+/// Number of independently locked shards, must be a power of two
+const SHARD_COUNT: usize = 16;
 
+type Shard = HashMap<u32, PageTableEntry>;
+
+/// Access to the frame of a page held by a handle, see `PageTable::borrow_frame`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Borrow {
+    Shared,
+    Exclusive,
+}
+
+/// Map from the pages held in the buffer pool to their frames.
+/// Only mapped pages have an entry, so the table grows with the pool rather than the disk.
+/// Entries are spread over several shards, each behind its own lock.
+/// Entries also track who accesses the frames, so a frame is never written
+/// through one handle while another handle reads or writes it.
 #[derive(Clone)]
-pub struct PageTable<const BLOCKSIZE: usize, const CAPACITY: usize> {
+pub struct PageTable {
     created_at: time::Instant,
-    entries: Arc<Mutex<Vec<Option<PageTableEntry>>>>,
+    shards: Arc<[Mutex<Shard>]>,
+    /// Notified when a borrow of a page of the shard with the same index is released
+    released: Arc<[Condvar]>,
 }
 
-impl<const BLOCKSIZE: usize, const CAPACITY: usize> PageTable<BLOCKSIZE, CAPACITY> {
-    pub fn init() -> Self {
-        let mut entries = Vec::with_capacity(CAPACITY / BLOCKSIZE);
-        for _ in 0..CAPACITY / BLOCKSIZE {
-            entries.push(None);
-        }
+impl PageTable {
+    /// Create a table for a pool of `frame_count` frames
+    pub fn init(frame_count: usize) -> Self {
+        let shard_capacity = frame_count.div_ceil(SHARD_COUNT);
+        let shards = (0..SHARD_COUNT)
+            .map(|_| Mutex::new(HashMap::with_capacity(shard_capacity)))
+            .collect();
         Self {
             created_at: time::Instant::now(),
-            entries: Arc::new(Mutex::new(entries)),
+            shards,
+            released: (0..SHARD_COUNT).map(|_| Condvar::new()).collect(),
+        }
+    }
+
+    fn shard_index(page_number: u32) -> usize {
+        page_number as usize & (SHARD_COUNT - 1)
+    }
+
+    fn shard(&self, page_number: u32) -> MutexGuard<'_, Shard> {
+        self.shards[Self::shard_index(page_number)].lock().unwrap()
+    }
+
+    /// Run `update` while no handle accesses the frames of `pages`,
+    /// then mark them dirty and return their new versions.
+    /// Pages are given with the version the caller copied them at, and must be mapped.
+    /// Fail without running `update` with `Conflict` if a page was written to since,
+    /// or with `PagePinned` if a handle is accessing its frame.
+    pub(crate) fn update_exclusive(
+        &self,
+        pages: &[(u32, u32)],
+        update: impl FnOnce(),
+    ) -> Result<Vec<u32>, BufferManagerError> {
+        // Shards are always locked in the same order, so concurrent updates can not deadlock
+        let mut shard_indexes: Vec<usize> = pages
+            .iter()
+            .map(|(page_number, _)| Self::shard_index(*page_number))
+            .collect();
+        shard_indexes.sort_unstable();
+        shard_indexes.dedup();
+        let mut shards: HashMap<usize, MutexGuard<'_, Shard>> = shard_indexes
+            .into_iter()
+            .map(|index| (index, self.shards[index].lock().unwrap()))
+            .collect();
+        for (page_number, version) in pages {
+            let shard = &shards[&Self::shard_index(*page_number)];
+            let entry = shard
+                .get(page_number)
+                .expect("Exclusively updated page must be mapped");
+            if entry.version() != *version {
+                return Err(BufferManagerError::Conflict(*page_number));
+            }
+        }
+        for (page_number, _) in pages {
+            let shard = &shards[&Self::shard_index(*page_number)];
+            if shard[page_number].borrow() != 0 {
+                return Err(BufferManagerError::PagePinned(*page_number));
+            }
         }
+        update();
+        let versions = pages
+            .iter()
+            .map(|(page_number, _)| {
+                let shard = shards.get_mut(&Self::shard_index(*page_number)).unwrap();
+                let entry = shard.get_mut(page_number).unwrap();
+                entry.set_dirty(true);
+                entry.bump_version();
+                entry.version()
+            })
+            .collect();
+        Ok(versions)
+    }
+
+    /// Version of a mapped page, see `PageTableEntry::version`
+    pub(crate) fn version(&self, page_number: u32) -> u32 {
+        self.shard(page_number)[&page_number].version()
+    }
+
+    /// Take a borrow of the frame of a mapped page, waiting for the conflicting borrows
+    /// of other handles to be released: any number of handles may read a frame at once,
+    /// a handle writing to it must be the only one accessing it.
+    /// `reading` tells whether the caller already holds a shared borrow,
+    /// which an exclusive borrow replaces.
+    /// Panics if the borrows are not released in time, which happens when the same thread
+    /// holds a conflicting handle.
+    pub(crate) fn borrow_frame(&self, page_number: u32, borrow: Borrow, reading: bool) {
+        if !self.try_borrow_frame(page_number, borrow, reading) {
+            panic!("Page {} is borrowed by another handle", page_number);
+        }
+    }
+
+    /// Same as `borrow_frame`, but return `false` instead of panicking
+    /// if the borrows are not released in time
+    pub(crate) fn try_borrow_frame(&self, page_number: u32, borrow: Borrow, reading: bool) -> bool {
+        let index = Self::shard_index(page_number);
+        let mut shard = self.shards[index].lock().unwrap();
+        let waiting_since = time::Instant::now();
+        loop {
+            let entry = shard
+                .get_mut(&page_number)
+                .expect("Borrowed page must be mapped");
+            let current = entry.borrow();
+            match borrow {
+                Borrow::Shared if current < WRITING - 1 => {
+                    entry.set_borrow(current + 1);
+                    return true;
+                }
+                Borrow::Exclusive if current == reading as u8 => {
+                    entry.set_borrow(WRITING);
+                    // The handle is about to write, copies taken before become stale
+                    entry.bump_version();
+                    return true;
+                }
+                _ => {}
+            }
+            let Some(timeout) = PIN_WAIT_TIMEOUT.checked_sub(waiting_since.elapsed()) else {
+                return false;
+            };
+            shard = self.released[index].wait_timeout(shard, timeout).unwrap().0;
+        }
+    }
+
+    /// Release a borrow taken with `borrow_frame`
+    pub(crate) fn release_frame(&self, page_number: u32, borrow: Borrow) {
+        let index = Self::shard_index(page_number);
+        let mut shard = self.shards[index].lock().unwrap();
+        let entry = shard.get_mut(&page_number).unwrap();
+        match borrow {
+            Borrow::Shared => entry.set_borrow(entry.borrow() - 1),
+            Borrow::Exclusive => entry.set_borrow(0),
+        }
+        drop(shard);
+        self.released[index].notify_all();
+    }
+
+    /// Time elapsed since the table was created, which entries use as timestamp
+    fn now(&self) -> f32 {
+        time::Instant::now()
+            .duration_since(self.created_at)
+            .as_secs_f32()
     }
 
-    pub fn pin_page(&self, page_number: u32) {
-        let mut entries = self.entries.lock().unwrap();
-        let mut entry = entries.get_mut(page_number as usize).unwrap().unwrap();
-        drop(entries);
+    /// Pin a page if it is mapped, returning the frame it is mapped to.
+    /// This counts as an access of the page.
+    pub fn pin_page(&self, page_number: u32) -> Option<u32> {
+        let now = self.now();
+        let mut shard = self.shard(page_number);
+        let entry = shard.get_mut(&page_number)?;
         entry.pin();
-        self.write_entry(page_number, entry);
+        entry.set_timestamp(now);
+        Some(entry.get_frame_number())
     }
 
     pub fn update_timestamp(&self, page_number: u32) {
-        let mut entry = self.get_entry(page_number).unwrap();
-        let duration = time::Instant::now().duration_since(self.created_at);
-        entry.set_timestamp(duration.as_secs_f32());
-        self.write_entry(page_number, entry);
+        let now = self.now();
+        let mut shard = self.shard(page_number);
+        let entry = shard.get_mut(&page_number).unwrap();
+        entry.set_timestamp(now);
     }
 
     pub fn get_oldest_page(&self) -> Option<u32> {
-        let entries = self.entries.lock().unwrap();
         let mut oldest_page = None;
         let mut oldest_timestamp = f32::MAX;
-        for (page_number, entry) in entries.iter().enumerate() {
-            if let Some(entry) = entry {
+        for shard in self.shards.iter() {
+            for (page_number, entry) in shard.lock().unwrap().iter() {
                 if entry.get_pin() > 0 {
                     continue;
                 }
                 if entry.timestamp() < oldest_timestamp {
                     oldest_timestamp = entry.timestamp();
-                    oldest_page = Some(page_number as u32);
+                    oldest_page = Some(*page_number);
                 }
             }
         }
         oldest_page
     }
 
+    /// Unmap the least recently used page which is not pinned
+    /// and return it with its last entry
+    pub(crate) fn evict_oldest_page(&self) -> Option<(u32, PageTableEntry)> {
+        loop {
+            let page_number = self.get_oldest_page()?;
+            let mut shard = self.shard(page_number);
+            // The page may have been pinned since the shards were scanned
+            match shard.get(&page_number) {
+                Some(entry) if entry.get_pin() == 0 => {
+                    let entry = shard.remove(&page_number)?;
+                    return Some((page_number, entry));
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Unmap a page which is not pinned and return its last entry,
+    /// or `None` if the page is not mapped
+    pub(crate) fn unmap_page(
+        &self,
+        page_number: u32,
+    ) -> Result<Option<PageTableEntry>, BufferManagerError> {
+        let mut shard = self.shard(page_number);
+        match shard.get(&page_number) {
+            Some(entry) if entry.get_pin() > 0 => Err(BufferManagerError::PagePinned(page_number)),
+            _ => Ok(shard.remove(&page_number)),
+        }
+    }
+
     fn get_entry(&self, page_number: u32) -> Option<PageTableEntry> {
-        let entries = self.entries.lock().unwrap();
-        *entries.get(page_number as usize)?
+        self.shard(page_number).get(&page_number).copied()
     }
 
     pub fn get_frame(&self, page_number: u32) -> Option<u32> {
@@ -70,14 +252,35 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> PageTable<BLOCKSIZE, CAPACIT
     }
 
     pub fn set_dirty(&self, page_number: u32) {
-        let mut entry = self.get_entry(page_number).unwrap();
-        entry.entry[9] = 1;
-        self.write_entry(page_number, entry);
+        let mut shard = self.shard(page_number);
+        let entry = shard.get_mut(&page_number).unwrap();
+        entry.set_dirty(true);
+    }
+
+    pub fn clear_dirty(&self, page_number: u32) {
+        if let Some(entry) = self.shard(page_number).get_mut(&page_number) {
+            entry.set_dirty(false);
+        }
+    }
+
+    /// Return every mapped page which has been modified since it was last written back
+    pub fn dirty_pages(&self) -> Vec<u32> {
+        let mut pages = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            pages.extend(
+                shard
+                    .iter()
+                    .filter(|(_, entry)| entry.is_dirty())
+                    .map(|(page_number, _)| *page_number),
+            );
+        }
+        pages
     }
 
     pub fn is_dirty(&self, page_number: u32) -> Option<bool> {
         let entry = self.get_entry(page_number)?;
-        Some(entry.entry[9] == 1)
+        Some(entry.is_dirty())
     }
 
     pub fn is_pinned(&self, page_number: u32) -> Option<bool> {
@@ -88,27 +291,46 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> PageTable<BLOCKSIZE, CAPACIT
     pub fn map_to_frame(&self, page_number: u32, frame_number: u32) {
         let mut entry = PageTableEntry::zero();
         entry.set_frame_number(frame_number);
+        entry.set_timestamp(self.now());
         self.write_entry(page_number, entry);
     }
 
     fn write_entry(&self, page_number: u32, entry: PageTableEntry) {
-        self.entries.lock().unwrap()[page_number as usize] = Some(entry);
+        self.shard(page_number).insert(page_number, entry);
     }
 
     pub fn drop_page(&self, page_number: u32) {
-        let mut entry = self.get_entry(page_number).unwrap();
+        let mut shard = self.shard(page_number);
+        let entry = shard.get_mut(&page_number).unwrap();
         entry.unpin();
-        self.write_entry(page_number, entry);
     }
 
-    pub(crate) fn unmap_page(&self, page_to_evict: u32) {
-        let mut entries = self.entries.lock().unwrap();
-        entries[page_to_evict as usize] = None;
+    /// Number of pages currently mapped
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    pub fn iter(&self) -> PageTableIterator<BLOCKSIZE, CAPACITY> {
-        let entries = self.entries.lock().unwrap();
-        PageTableIterator::<BLOCKSIZE, CAPACITY>::new(entries.clone())
+    /// Iterate over the mapped pages, ordered by page number.
+    /// Entries are copied first, so later changes to the table are not observed.
+    pub fn iter(&self) -> PageTableIterator {
+        let mut entries = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            entries.extend(
+                shard
+                    .iter()
+                    .map(|(page_number, entry)| (*page_number, *entry)),
+            );
+        }
+        entries.sort_unstable_by_key(|(page_number, _)| *page_number);
+        PageTableIterator::new(entries)
     }
 }
 
@@ -120,9 +342,7 @@ mod tests {
 
     #[test]
     fn create_mapping() {
-        const BLOCKSIZE: usize = 512;
-        const CAPACITY: usize = 512 * 64;
-        let table: PageTable<BLOCKSIZE, CAPACITY> = PageTable::init();
+        let table = PageTable::init(64);
         table.map_to_frame(12, 43);
         table.map_to_frame(4, 45);
         let entry = table.get_entry(12).unwrap();
@@ -137,9 +357,7 @@ mod tests {
 
     #[test]
     fn read_write_entry() {
-        const BLOCKSIZE: usize = 512;
-        const CAPACITY: usize = 512 * 64;
-        let table: PageTable<BLOCKSIZE, CAPACITY> = PageTable::init();
+        let table = PageTable::init(64);
         let mut entry = PageTableEntry::zero();
         entry.set_frame_number(43);
         table.write_entry(12, entry);
@@ -149,9 +367,65 @@ mod tests {
 
     #[test]
     fn create_mapping_and_get_page() {
-        let table: PageTable<512, 4096> = PageTable::init();
+        let table = PageTable::init(8);
         table.map_to_frame(1, 3);
         let page = table.get_frame(1).unwrap();
         assert_eq!(page, 3);
     }
+
+    #[test]
+    fn unmap_page() {
+        let table = PageTable::init(8);
+        table.map_to_frame(1, 3);
+        table.map_to_frame(2, 4);
+        table.pin_page(2);
+        assert_eq!(table.unmap_page(1).unwrap().unwrap().get_frame_number(), 3);
+        assert!(table.get_frame(1).is_none());
+        assert!(table.unmap_page(1).unwrap().is_none());
+        assert!(table.unmap_page(2).is_err());
+        assert_eq!(table.get_frame(2), Some(4));
+    }
+
+    #[test]
+    fn independent_of_disk_size() {
+        let table = PageTable::init(4);
+        table.map_to_frame(u32::MAX, 0);
+        table.map_to_frame(1 << 30, 1);
+        table.map_to_frame(17, 2);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get_frame(u32::MAX), Some(0));
+        assert_eq!(table.get_frame(1 << 30), Some(1));
+        assert_eq!(table.get_frame(1), None);
+        table.pin_page(17);
+        let (page_number, entry) = table.evict_oldest_page().unwrap();
+        assert_ne!(page_number, 17);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get_frame(page_number), None);
+        assert!(!entry.is_dirty());
+    }
+
+    #[test]
+    fn iterate_entries() {
+        let table = PageTable::init(8);
+        assert_eq!(table.iter().count(), 0);
+        for (frame_number, page_number) in [40, 3, 19].into_iter().enumerate() {
+            table.map_to_frame(page_number, frame_number as u32);
+        }
+        table.pin_page(19);
+        table.set_dirty(3);
+        let entries: Vec<_> = table.iter().collect();
+        assert_eq!(entries.len(), 3);
+        let pages: Vec<_> = entries
+            .iter()
+            .map(|(page_number, _)| *page_number)
+            .collect();
+        assert_eq!(pages, vec![3, 19, 40]);
+        let (_, entry) = entries[0];
+        assert_eq!(entry.get_frame_number(), 1);
+        assert!(entry.is_dirty());
+        let (_, entry) = entries[1];
+        assert_eq!(entry.get_pin(), 1);
+        assert!(!entry.is_dirty());
+        assert!(entry.timestamp() >= entries[2].1.timestamp());
+    }
 }
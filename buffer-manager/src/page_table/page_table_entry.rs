@@ -2,13 +2,17 @@
 /// Each entry represent a map from page ---> frame
 #[derive(Clone, Copy)]
 pub struct PageTableEntry {
-    /// | timestamp: f32 | frame number: u32 | pin: u8 | dirty: u8 |
-    pub(super) entry: [u8; 10],
+    /// | timestamp: f32 | frame number: u32 | pin: u8 | dirty: u8 | borrow: u8 | version: u32 |
+    pub(super) entry: [u8; 15],
 }
 
+/// Value of the borrow byte while a handle writes to the frame,
+/// otherwise it counts the handles reading it
+pub(super) const WRITING: u8 = u8::MAX;
+
 impl PageTableEntry {
     pub(super) fn zero() -> Self {
-        PageTableEntry { entry: [0; 10] }
+        PageTableEntry { entry: [0; 15] }
     }
 
     pub fn get_pin(&self) -> u8 {
@@ -34,6 +38,14 @@ impl PageTableEntry {
         self.entry[8] -= 1;
     }
 
+    pub fn is_dirty(&self) -> bool {
+        self.entry[9] == 1
+    }
+
+    pub(super) fn set_dirty(&mut self, dirty: bool) {
+        self.entry[9] = dirty as u8;
+    }
+
     pub fn get_frame_number(&self) -> u32 {
         u32::from_be_bytes(self.entry[4..8].try_into().unwrap())
     }
@@ -41,4 +53,23 @@ impl PageTableEntry {
     pub(super) fn set_frame_number(&mut self, frame_number: u32) {
         self.entry[4..8].copy_from_slice(&frame_number.to_be_bytes());
     }
+
+    pub(super) fn borrow(&self) -> u8 {
+        self.entry[10]
+    }
+
+    pub(super) fn set_borrow(&mut self, borrow: u8) {
+        self.entry[10] = borrow;
+    }
+
+    /// Changed every time the page is written to while mapped,
+    /// so a copy of the page can tell whether it is still current
+    pub(super) fn version(&self) -> u32 {
+        u32::from_be_bytes(self.entry[11..15].try_into().unwrap())
+    }
+
+    pub(super) fn bump_version(&mut self) {
+        let version = self.version().wrapping_add(1);
+        self.entry[11..15].copy_from_slice(&version.to_be_bytes());
+    }
 }
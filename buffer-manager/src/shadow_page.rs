@@ -0,0 +1,100 @@
+use std::ops::{Deref, DerefMut};
+
+use super::{Borrow, BufferManager, BufferManagerError};
+
+/// A private copy of a page.
+/// Changes are made to the copy only, everyone else keeps seeing the page
+/// as it was until the copy is published. Dropping it discards the changes.
+/// The page stays pinned, and so in its frame, while the copy exists.
+/// A copy of a page which has been written to since it was taken can not be published.
+/// B-tree splits in the file system still modify their pages in place,
+/// moving them to shadow pages is left for a later change.
+pub struct ShadowPage<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> {
+    page_number: u32,
+    frame_number: u32,
+    /// Version of the page the copy is based on
+    version: u32,
+    buffer: Box<[u8]>,
+    buffer_manager: &'a BufferManager<PAGE_SIZE, DISK_CAPACITY>,
+}
+
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize>
+    ShadowPage<'a, PAGE_SIZE, DISK_CAPACITY>
+{
+    /// Copy a page which has been pinned for this shadow
+    pub(super) fn init(
+        page_number: u32,
+        frame_number: u32,
+        buffer_manager: &'a BufferManager<PAGE_SIZE, DISK_CAPACITY>,
+    ) -> Self {
+        let page_table = &buffer_manager.pool.page_table;
+        page_table.borrow_frame(page_number, Borrow::Shared, false);
+        // Nobody writes to the page while it is borrowed, so the version matches the copy
+        let version = page_table.version(page_number);
+        let buffer = unsafe { buffer_manager.pool.frame(frame_number) }.into();
+        page_table.release_frame(page_number, Borrow::Shared);
+        Self {
+            page_number,
+            frame_number,
+            version,
+            buffer,
+            buffer_manager,
+        }
+    }
+
+    pub fn page_number(&self) -> u32 {
+        self.page_number
+    }
+
+    pub(super) fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub(super) fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    /// Make the changes visible to everyone, see `BufferManager::publish_all`
+    pub fn publish(&mut self) -> Result<(), BufferManagerError> {
+        let buffer_manager = self.buffer_manager;
+        buffer_manager.publish_all(std::slice::from_mut(self))
+    }
+
+    /// Copy the private buffer into the frame.
+    /// ### Safety: Nobody else may access the frame during the copy
+    pub(super) unsafe fn write_to_frame(&self) {
+        self.buffer_manager
+            .pool
+            .frame_mut(self.frame_number)
+            .copy_from_slice(&self.buffer);
+    }
+}
+
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> Deref
+    for ShadowPage<'a, PAGE_SIZE, DISK_CAPACITY>
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> DerefMut
+    for ShadowPage<'a, PAGE_SIZE, DISK_CAPACITY>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl<'a, const PAGE_SIZE: usize, const DISK_CAPACITY: usize> Drop
+    for ShadowPage<'a, PAGE_SIZE, DISK_CAPACITY>
+{
+    fn drop(&mut self) {
+        self.buffer_manager
+            .pool
+            .page_table
+            .drop_page(self.page_number);
+    }
+}
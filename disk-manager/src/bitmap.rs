@@ -1,4 +1,4 @@
-use disk::Disk;
+use disk::{Disk, DiskError};
 
 #[derive(Debug)]
 pub struct Bitmap<const BLOCKSIZE: usize, const CAPACITY: usize> {
@@ -39,6 +39,29 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> Bitmap<BLOCKSIZE, CAPACITY>
         None
     }
 
+    /// Allocate `count` blocks in a single scan of the bitmap, lowest first.
+    /// Nothing is allocated if there are not enough free blocks.
+    pub fn allocate_many(&mut self, count: usize) -> Option<Vec<usize>> {
+        let blocks: Vec<usize> = (0..self.bitmap.len() * 8)
+            .filter(|block| self.bitmap[block / 8] & (1 << (block % 8)) == 0)
+            .take(count)
+            .collect();
+        if blocks.len() < count {
+            return None;
+        }
+        for block in &blocks {
+            self.bitmap[block / 8] |= 1 << (block % 8);
+        }
+        Some(blocks)
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.bitmap
+            .iter()
+            .map(|byte| byte.count_zeros() as usize)
+            .sum()
+    }
+
     pub fn deallocate(&mut self, block: usize) {
         self.bitmap[block / 8] &= !(1 << (block % 8));
     }
@@ -65,21 +88,13 @@ pub fn read_bitmap_from_disk<const BLOCKSIZE: usize, const CAPACITY: usize>(
 pub fn write_bitmap_to_disk<const BLOCKSIZE: usize, const CAPACITY: usize>(
     disk: &Disk<BLOCKSIZE, CAPACITY>,
     bitmap: &Bitmap<BLOCKSIZE, CAPACITY>,
-) {
-    for i in 0..bitmap.bitmap.len() / (8 * BLOCKSIZE) {
-        println!("Writing block {}", i);
-        let mut block = [0; BLOCKSIZE];
-        println!("Copying from {} to {}", i * BLOCKSIZE, (i + 1) * BLOCKSIZE);
-        block.copy_from_slice(&bitmap.bitmap[i * BLOCKSIZE..(i + 1) * BLOCKSIZE]);
-        disk.write_block(i as usize, block.as_ref()).unwrap();
-    }
-    let remainder = bitmap.bitmap.len() % (8 * BLOCKSIZE);
-    if remainder != 0 {
+) -> Result<(), DiskError> {
+    for (i, chunk) in bitmap.bitmap.chunks(BLOCKSIZE).enumerate() {
         let mut block = [0; BLOCKSIZE];
-        block[0..remainder].copy_from_slice(&bitmap.bitmap[0..remainder]);
-        disk.write_block(bitmap.bitmap.len() / (8 * BLOCKSIZE), block.as_ref())
-            .unwrap();
+        block[0..chunk.len()].copy_from_slice(chunk);
+        disk.write_block(i, block.as_ref())?;
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -102,7 +117,7 @@ mod tests {
         bitmap.bitmap[2] = 0b00000100;
         bitmap.bitmap[3] = 0b00001000;
         println!("{:?}", bitmap);
-        write_bitmap_to_disk(&disk, &bitmap);
+        write_bitmap_to_disk(&disk, &bitmap).unwrap();
         let block = disk.read_block(0).unwrap();
         println!("{:?}", block);
         let bitmap = read_bitmap_from_disk(&disk);
@@ -48,13 +48,80 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> DiskManager<BLOCKSIZE, CAPAC
         }
     }
 
+    /// Allocate `count` blocks at once, the bitmap is only locked once.
+    /// Fail without allocating anything if the disk does not have enough free blocks.
+    pub fn allocate_many(&self, count: usize) -> Result<Vec<DiskAddress>, DiskManagerError> {
+        match self.bitmap.lock().unwrap().allocate_many(count) {
+            Some(blocks) => Ok(blocks.into_iter().map(|b| b as u32).collect()),
+            None => Err(DiskManagerError::DiskFull),
+        }
+    }
+
+    /// Number of blocks which are not allocated
+    pub fn free_blocks(&self) -> usize {
+        self.bitmap.lock().unwrap().free_count()
+    }
+
     pub fn deallocate(&self, block: DiskAddress) -> Result<(), DiskManagerError> {
         Ok(self.bitmap.lock().unwrap().deallocate(block as usize))
     }
+
+    /// Write the free space bitmap back to the disk and sync it.
+    pub fn flush(&self) -> Result<(), DiskManagerError> {
+        write_bitmap_to_disk(&self.disk, &self.bitmap.lock().unwrap())
+            .map_err(|_| DiskManagerError::DiskError)?;
+        self.disk.sync().map_err(|_| DiskManagerError::DiskError)
+    }
 }
 
 impl<const BLOCKSIZE: usize, const CAPACITY: usize> Drop for DiskManager<BLOCKSIZE, CAPACITY> {
     fn drop(&mut self) {
-        write_bitmap_to_disk(&self.disk, &self.bitmap.lock().unwrap());
+        if let Err(e) = self.flush() {
+            log::error!("Failed to persist disk bitmap: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use disk::Disk;
+
+    use super::DiskManager;
+
+    #[test]
+    fn flush_persists_bitmap() {
+        let disk = Disk::<512, 65536>::create("disk_manager_flush").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        assert_eq!(disk_manager.allocate().unwrap(), 1);
+        assert_eq!(disk_manager.allocate().unwrap(), 2);
+        disk_manager.flush().unwrap();
+
+        let disk_manager = DiskManager::open(&disk);
+        assert_eq!(disk_manager.allocate().unwrap(), 3);
+    }
+
+    #[test]
+    fn allocate_many() {
+        let disk = Disk::<512, 65536>::create("disk_manager_allocate_many").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        assert_eq!(disk_manager.allocate().unwrap(), 1);
+        assert_eq!(disk_manager.allocate().unwrap(), 2);
+        disk_manager.deallocate(1).unwrap();
+        assert_eq!(disk_manager.allocate_many(3).unwrap(), vec![1, 3, 4]);
+        // Nothing is allocated when the disk cannot hold all the blocks
+        assert!(disk_manager.allocate_many(1000).is_err());
+        assert_eq!(disk_manager.allocate().unwrap(), 5);
+    }
+
+    #[test]
+    fn free_blocks() {
+        let disk = Disk::<512, 65536>::create("disk_manager_free_blocks").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        // The bitmap takes the first block
+        assert_eq!(disk_manager.free_blocks(), 127);
+        let blocks = disk_manager.allocate_many(10).unwrap();
+        assert_eq!(disk_manager.free_blocks(), 117);
+        disk_manager.deallocate(blocks[0]).unwrap();
+        assert_eq!(disk_manager.free_blocks(), 118);
     }
 }
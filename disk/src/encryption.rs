@@ -0,0 +1,87 @@
+//! Blocks of an encrypted disk are sealed with ChaCha20-Poly1305.
+//! The nonce is made of the block number and of random bytes drawn for every write,
+//! which are stored in front of the ciphertext. A block is never encrypted twice with
+//! the same nonce, and a sealed block copied to another position does not open.
+
+use std::fmt;
+
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, AeadInPlace, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce, Tag,
+};
+
+pub type EncryptionKey = [u8; 32];
+
+const RANDOM_SIZE: usize = 8;
+const TAG_SIZE: usize = 16;
+
+/// Size added to a block when it is sealed
+pub const OVERHEAD: usize = RANDOM_SIZE + TAG_SIZE;
+
+/// Sealed in the disk header to tell a wrong key from a corrupted block.
+/// No block has the number it is sealed with.
+const KEY_CHECK: &[u8; 16] = b"my_database disk";
+const KEY_CHECK_BLOCK: u32 = u32::MAX;
+pub const KEY_CHECK_SIZE: usize = KEY_CHECK.len() + OVERHEAD;
+
+#[derive(Clone)]
+pub struct BlockCipher(ChaCha20Poly1305);
+
+impl fmt::Debug for BlockCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BlockCipher")
+    }
+}
+
+impl BlockCipher {
+    pub fn new(key: &EncryptionKey) -> Self {
+        Self(ChaCha20Poly1305::new(Key::from_slice(key)))
+    }
+
+    fn nonce(block_number: u32, random: &[u8]) -> Nonce {
+        let mut nonce = Nonce::default();
+        nonce[..4].copy_from_slice(&block_number.to_be_bytes());
+        nonce[4..].copy_from_slice(random);
+        nonce
+    }
+
+    /// Encrypt `block`, the result is `OVERHEAD` bytes longer
+    pub fn seal(&self, block_number: u32, block: &[u8]) -> Vec<u8> {
+        let mut sealed = vec![0; RANDOM_SIZE];
+        OsRng.fill_bytes(&mut sealed);
+        let nonce = Self::nonce(block_number, &sealed);
+        sealed.extend_from_slice(block);
+        let tag = self
+            .0
+            .encrypt_in_place_detached(&nonce, &[], &mut sealed[RANDOM_SIZE..])
+            .unwrap();
+        sealed.extend_from_slice(&tag);
+        sealed
+    }
+
+    /// Decrypt a block sealed at `block_number` into `block`.
+    /// Return false if it was sealed with another key or at another position, or was changed.
+    pub fn open(&self, block_number: u32, sealed: &[u8], block: &mut [u8]) -> bool {
+        let (random, rest) = sealed.split_at(RANDOM_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+        block.copy_from_slice(ciphertext);
+        self.0
+            .decrypt_in_place_detached(
+                &Self::nonce(block_number, random),
+                &[],
+                block,
+                Tag::from_slice(tag),
+            )
+            .is_ok()
+    }
+
+    pub fn key_check(&self) -> Vec<u8> {
+        self.seal(KEY_CHECK_BLOCK, KEY_CHECK)
+    }
+
+    /// Whether `key_check` was made with the key of this cipher
+    pub fn verify(&self, key_check: &[u8]) -> bool {
+        let mut check = [0; KEY_CHECK.len()];
+        self.open(KEY_CHECK_BLOCK, key_check, &mut check) && &check == KEY_CHECK
+    }
+}
@@ -1,24 +1,53 @@
 use std::{
     fs::{remove_file, File},
-    io::{Read, Seek, SeekFrom, Write},
+    io::{Error, ErrorKind, Read, Seek, SeekFrom, Write},
     mem::size_of,
     sync::{Arc, Mutex},
 };
 
 use log::info;
 
+mod encryption;
+
+pub use encryption::EncryptionKey;
+use encryption::{BlockCipher, KEY_CHECK_SIZE, OVERHEAD};
+
 #[derive(Debug, PartialEq)]
 pub enum DiskError {
     IncorrectBlockSize,
     OverCapacity,
+    /// A block of an encrypted disk does not decrypt, it was changed outside of the disk
+    CorruptedBlock,
 }
 
-const HEADER_SIZE: usize = size_of::<u32>() * 2;
+/// Flag of the header set when the blocks are encrypted
+const ENCRYPTED: u32 = 1 << 0;
+
+/// Number of blocks sealed and written at once when an encrypted disk is created
+const SEAL_BATCH: usize = 64;
+
+/// First bytes of every disk image
+const MAGIC: [u8; 4] = *b"DISK";
+
+/// Version of the layout of the image, changed whenever the header or the blocks change
+const VERSION: u32 = 1;
+
+/// Magic, version, block size, capacity, flags and the key check of encrypted disks
+const HEADER_SIZE: usize = MAGIC.len() + size_of::<u32>() * 4 + KEY_CHECK_SIZE;
+
+struct Header {
+    block_size: u32,
+    capacity: u32,
+    flags: u32,
+    key_check: [u8; KEY_CHECK_SIZE],
+}
 
 #[derive(Debug, Clone)]
 pub struct Disk<const BLOCKSIZE: usize, const CAPACITY: usize> {
     file_name: String,
     file: Arc<Mutex<File>>,
+    /// `Some` if the blocks are encrypted
+    cipher: Option<BlockCipher>,
 }
 
 pub fn make_name(name: &str) -> String {
@@ -28,33 +57,69 @@ pub fn make_name(name: &str) -> String {
     disk_name
 }
 
-fn write_header(file: &mut File, block_size: u32, capacity: u32) -> Result<(), std::io::Error> {
-    let block_size = block_size.to_be_bytes();
+fn write_header(file: &mut File, header: &Header) -> Result<(), std::io::Error> {
     file.seek(SeekFrom::Start(0))?;
-    file.write(&block_size)?;
-    let capacity = capacity.to_be_bytes();
-    file.seek(SeekFrom::Start(size_of::<u32>() as u64))?;
-    file.write(&capacity)?;
+    file.write_all(&MAGIC)?;
+    file.write_all(&VERSION.to_be_bytes())?;
+    file.write_all(&header.block_size.to_be_bytes())?;
+    file.write_all(&header.capacity.to_be_bytes())?;
+    file.write_all(&header.flags.to_be_bytes())?;
+    file.write_all(&header.key_check)?;
     Ok(())
 }
 
-fn read_header(file: &mut File) -> Result<(u32, u32), std::io::Error> {
-    let mut block_size: [u8; size_of::<u32>()] = [0; size_of::<u32>()];
-    let mut capacity = [0; size_of::<u32>()];
-    file.seek(SeekFrom::Start(0))?;
-    file.read_exact(&mut block_size)?;
-    let block_size = u32::from_be_bytes(block_size);
-
-    file.seek(SeekFrom::Start(size_of::<u32>() as u64))?;
-    file.read(&mut capacity)?;
+fn read_u32(file: &mut File) -> Result<u32, std::io::Error> {
+    let mut bytes = [0; size_of::<u32>()];
+    file.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
 
-    let capacity = u32::from_be_bytes(capacity);
+/// Fail with `InvalidData` if the image does not start with the magic,
+/// such as an image written before the header had one, or has another version.
+/// Such images are not migrated, their blocks are not where this version looks for them.
+fn read_header(file: &mut File) -> Result<Header, std::io::Error> {
+    let mut magic = [0; MAGIC.len()];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "not a disk image, or one written before disk images had a version",
+        ));
+    }
+    let version = read_u32(file)?;
+    if version != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("disk image of version {version}, version {VERSION} is expected"),
+        ));
+    }
+    let block_size = read_u32(file)?;
+    let capacity = read_u32(file)?;
+    let flags = read_u32(file)?;
+    let mut key_check = [0; KEY_CHECK_SIZE];
+    file.read_exact(&mut key_check)?;
 
-    Ok((block_size, capacity))
+    Ok(Header {
+        block_size,
+        capacity,
+        flags,
+        key_check,
+    })
 }
 
 impl<const BLOCKSIZE: usize, const CAPACITY: usize> Disk<BLOCKSIZE, CAPACITY> {
     pub fn create(name: &str) -> Result<Self, std::io::Error> {
+        Self::create_with(name, None)
+    }
+
+    /// Create a disk whose blocks are encrypted with `key`.
+    /// It can only be connected to again with the same key.
+    pub fn create_encrypted(name: &str, key: &EncryptionKey) -> Result<Self, std::io::Error> {
+        Self::create_with(name, Some(BlockCipher::new(key)))
+    }
+
+    fn create_with(name: &str, cipher: Option<BlockCipher>) -> Result<Self, std::io::Error> {
         assert_eq!(
             CAPACITY % BLOCKSIZE,
             0,
@@ -66,15 +131,47 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> Disk<BLOCKSIZE, CAPACITY> {
             .read(true)
             .create(true)
             .open(make_name(name))?;
-        file.set_len(CAPACITY as u64)?;
-        write_header(&mut file, BLOCKSIZE as u32, CAPACITY as u32)?;
+        file.set_len((HEADER_SIZE + CAPACITY) as u64)?;
+        let header = Header {
+            block_size: BLOCKSIZE as u32,
+            capacity: CAPACITY as u32,
+            flags: if cipher.is_some() { ENCRYPTED } else { 0 },
+            key_check: match &cipher {
+                Some(cipher) => cipher.key_check().try_into().unwrap(),
+                None => [0; KEY_CHECK_SIZE],
+            },
+        };
+        write_header(&mut file, &header)?;
+        // Every block is sealed, so blocks which were never written decrypt as well.
+        // They are written a batch at a time to keep the memory used bounded.
+        if let Some(cipher) = &cipher {
+            let zeroes = [0; BLOCKSIZE];
+            let block_count = CAPACITY / BLOCKSIZE;
+            for first in (0..block_count).step_by(SEAL_BATCH) {
+                let blocks: Vec<u8> = (first..block_count.min(first + SEAL_BATCH))
+                    .flat_map(|block_number| cipher.seal(block_number as u32, &zeroes))
+                    .collect();
+                file.write_all(&blocks)?;
+            }
+        }
         Ok(Self {
             file_name: String::from(name),
             file: Arc::new(Mutex::new(file)),
+            cipher,
         })
     }
 
     pub fn connect(name: &str) -> Result<Self, std::io::Error> {
+        Self::connect_with(name, None)
+    }
+
+    /// Connect to a disk created by `create_encrypted`.
+    /// Fail if the disk is not encrypted or `key` is not the one it was created with.
+    pub fn connect_encrypted(name: &str, key: &EncryptionKey) -> Result<Self, std::io::Error> {
+        Self::connect_with(name, Some(BlockCipher::new(key)))
+    }
+
+    fn connect_with(name: &str, cipher: Option<BlockCipher>) -> Result<Self, std::io::Error> {
         assert_eq!(
             CAPACITY % BLOCKSIZE,
             0,
@@ -84,27 +181,74 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> Disk<BLOCKSIZE, CAPACITY> {
             .write(true)
             .read(true)
             .open(make_name(name))?;
-        let (block_size, capacity) = read_header(&mut file)?;
-        assert_eq!(BLOCKSIZE, block_size as usize, "Incorrect disk block size");
-        assert_eq!(CAPACITY, capacity as usize, "Incorrect disk capacity");
+        let header = read_header(&mut file)?;
+        assert_eq!(
+            BLOCKSIZE, header.block_size as usize,
+            "Incorrect disk block size"
+        );
+        assert_eq!(
+            CAPACITY, header.capacity as usize,
+            "Incorrect disk capacity"
+        );
+        match (&cipher, header.flags & ENCRYPTED != 0) {
+            (None, true) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "the disk is encrypted, a key is needed",
+                ))
+            }
+            (Some(_), false) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "the disk is not encrypted",
+                ))
+            }
+            (Some(cipher), true) if !cipher.verify(&header.key_check) => {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    "wrong encryption key",
+                ))
+            }
+            _ => {}
+        }
         Ok(Self {
             file_name: String::from(name),
             file: Arc::new(Mutex::new(file)),
+            cipher,
         })
     }
 
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Position of a block in the image, sealed blocks take `OVERHEAD` more bytes
+    fn offset(&self, block_number: usize) -> u64 {
+        let stride = match self.cipher {
+            Some(_) => BLOCKSIZE + OVERHEAD,
+            None => BLOCKSIZE,
+        };
+        (HEADER_SIZE + block_number * stride) as u64
+    }
+
     pub fn read_block(&self, block_number: usize) -> Result<Box<[u8; BLOCKSIZE]>, DiskError> {
         let mut file = self.file.lock().unwrap();
         info!("Start reading block[{}]", block_number);
         if block_number >= CAPACITY / BLOCKSIZE {
             return Err(DiskError::OverCapacity);
         }
-        file.seek(SeekFrom::Start(
-            HEADER_SIZE as u64 + (block_number * BLOCKSIZE) as u64,
-        ))
-        .unwrap();
+        file.seek(SeekFrom::Start(self.offset(block_number)))
+            .unwrap();
         let mut buf = Box::new([0; BLOCKSIZE]);
-        file.read(&mut *buf).unwrap();
+        if let Some(cipher) = &self.cipher {
+            let mut sealed = vec![0; BLOCKSIZE + OVERHEAD];
+            file.read_exact(&mut sealed).unwrap();
+            if !cipher.open(block_number as u32, &sealed, &mut *buf) {
+                return Err(DiskError::CorruptedBlock);
+            }
+        } else {
+            file.read_exact(&mut *buf).unwrap();
+        }
         info!("Done reading block[{}]", block_number);
         Ok(buf)
     }
@@ -117,14 +261,25 @@ impl<const BLOCKSIZE: usize, const CAPACITY: usize> Disk<BLOCKSIZE, CAPACITY> {
         } else if block_number >= CAPACITY / BLOCKSIZE {
             return Err(DiskError::OverCapacity);
         }
-        file.seek(SeekFrom::Start(
-            HEADER_SIZE as u64 + (block_number * BLOCKSIZE) as u64,
-        ))
-        .unwrap();
-        file.write(block).unwrap();
+        file.seek(SeekFrom::Start(self.offset(block_number)))
+            .unwrap();
+        match &self.cipher {
+            Some(cipher) => file
+                .write_all(&cipher.seal(block_number as u32, block))
+                .unwrap(),
+            None => {
+                file.write_all(block).unwrap();
+            }
+        }
         info!("Done writing block[{}]", block_number);
         Ok(())
     }
+
+    /// Flush every written block to the underlying device.
+    pub fn sync(&self) -> Result<(), std::io::Error> {
+        let file = self.file.lock().unwrap();
+        file.sync_all()
+    }
 }
 
 #[cfg(test)]
@@ -145,6 +300,29 @@ mod test {
         remove_file(make_name("test_connect")).unwrap();
     }
 
+    #[test]
+    fn test_connect_old_image() {
+        // Images written before the magic start with the block size and the capacity
+        let mut image = (512u32).to_be_bytes().to_vec();
+        image.extend_from_slice(&1024u32.to_be_bytes());
+        image.resize(8 + 1024, 0);
+        std::fs::write(make_name("test_connect_old_image"), &image).unwrap();
+        let error = Disk::<512, 1024>::connect("test_connect_old_image").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        // Images of another version are not read either
+        Disk::<512, 1024>::create("test_connect_old_image").unwrap();
+        let mut file = File::options()
+            .write(true)
+            .open(make_name("test_connect_old_image"))
+            .unwrap();
+        file.seek(SeekFrom::Start(MAGIC.len() as u64)).unwrap();
+        file.write_all(&(VERSION + 1).to_be_bytes()).unwrap();
+        let error = Disk::<512, 1024>::connect("test_connect_old_image").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        remove_file(make_name("test_connect_old_image")).unwrap();
+    }
+
     #[test]
     fn test_read_write() {
         let disk = Disk::<512, 1024>::create("test_read_write").unwrap();
@@ -153,9 +331,21 @@ mod test {
         disk.write_block(0, &*block).unwrap();
         let block = disk.read_block(0).unwrap();
         assert_eq!(block[0], 1);
+        // The last block is in the image even if it was never written
+        assert_eq!(*disk.read_block(1).unwrap(), [0; 512]);
         remove_file(make_name("test_read_write")).unwrap();
     }
 
+    #[test]
+    fn test_sync() {
+        let disk = Disk::<512, 1024>::create("test_sync").unwrap();
+        disk.write_block(1, &[7; 512]).unwrap();
+        disk.sync().unwrap();
+        let disk = Disk::<512, 1024>::connect("test_sync").unwrap();
+        assert_eq!(disk.read_block(1).unwrap()[0], 7);
+        remove_file(make_name("test_sync")).unwrap();
+    }
+
     #[test]
     fn test_read_write_over_capacity() {
         let disk = Disk::<512, 1024>::create("test_read_write_over_capacity").unwrap();
@@ -191,7 +381,87 @@ mod test {
     }
 
     #[test]
-    #[should_panic]
+    fn test_encrypted_read_write() {
+        let key = [7; 32];
+        let disk = Disk::<512, 1024>::create_encrypted("test_encrypted_read_write", &key).unwrap();
+        assert!(disk.is_encrypted());
+        assert_eq!(*disk.read_block(1).unwrap(), [0; 512]);
+        disk.write_block(0, &[0xab; 512]).unwrap();
+        disk.sync().unwrap();
+
+        let disk = Disk::<512, 1024>::connect_encrypted("test_encrypted_read_write", &key).unwrap();
+        assert_eq!(*disk.read_block(0).unwrap(), [0xab; 512]);
+        // The image does not hold the content of the block
+        let mut image = Vec::new();
+        File::open(make_name("test_encrypted_read_write"))
+            .unwrap()
+            .read_to_end(&mut image)
+            .unwrap();
+        assert!(!image.windows(16).any(|window| window == [0xab; 16]));
+
+        let error = Disk::<512, 1024>::connect_encrypted("test_encrypted_read_write", &[8; 32])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        let error = Disk::<512, 1024>::connect("test_encrypted_read_write").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        remove_file(make_name("test_encrypted_read_write")).unwrap();
+
+        let _ = Disk::<512, 1024>::create("test_encrypted_read_write").unwrap();
+        let error =
+            Disk::<512, 1024>::connect_encrypted("test_encrypted_read_write", &key).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        remove_file(make_name("test_encrypted_read_write")).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_create_in_batches() {
+        const BLOCKS: usize = SEAL_BATCH * 2 + 3;
+        let disk = Disk::<512, { 512 * BLOCKS }>::create_encrypted(
+            "test_encrypted_create_in_batches",
+            &[7; 32],
+        )
+        .unwrap();
+        for block_number in [0, SEAL_BATCH - 1, SEAL_BATCH, BLOCKS - 1] {
+            assert_eq!(*disk.read_block(block_number).unwrap(), [0; 512]);
+        }
+        let len = File::open(make_name("test_encrypted_create_in_batches"))
+            .unwrap()
+            .metadata()
+            .unwrap()
+            .len();
+        assert_eq!(len, disk.offset(BLOCKS));
+        remove_file(make_name("test_encrypted_create_in_batches")).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_corruption() {
+        let disk =
+            Disk::<512, 1024>::create_encrypted("test_encrypted_corruption", &[7; 32]).unwrap();
+        disk.write_block(0, &[1; 512]).unwrap();
+        disk.write_block(1, &[2; 512]).unwrap();
+        let mut file = File::options()
+            .write(true)
+            .read(true)
+            .open(make_name("test_encrypted_corruption"))
+            .unwrap();
+
+        // A block copied to another position does not decrypt
+        let mut sealed = vec![0; 512 + OVERHEAD];
+        file.seek(SeekFrom::Start(disk.offset(0))).unwrap();
+        file.read_exact(&mut sealed).unwrap();
+        file.seek(SeekFrom::Start(disk.offset(1))).unwrap();
+        file.write_all(&sealed).unwrap();
+        assert_eq!(disk.read_block(1), Err(DiskError::CorruptedBlock));
+
+        // Neither does a changed block
+        sealed[100] ^= 1;
+        file.seek(SeekFrom::Start(disk.offset(0))).unwrap();
+        file.write_all(&sealed).unwrap();
+        assert_eq!(disk.read_block(0), Err(DiskError::CorruptedBlock));
+        remove_file(make_name("test_encrypted_corruption")).unwrap();
+    }
+
+    #[test]
     fn test_invalid_header() {
         let _ = Disk::<512, 1024>::create("test_invalid_header").unwrap();
         let mut file = File::options()
@@ -200,8 +470,9 @@ mod test {
             .open(make_name("test_invalid_header"))
             .unwrap();
         file.seek(SeekFrom::Start(0)).unwrap();
-        file.write(&[0; 8]).unwrap();
-        Disk::<512, 1024>::connect("test_invalid_header").unwrap_err();
+        file.write_all(&[0; 8]).unwrap();
+        let error = Disk::<512, 1024>::connect("test_invalid_header").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
         remove_file(make_name("test_invalid_header")).unwrap();
     }
 }
@@ -0,0 +1,46 @@
+//! Compare appending records with repeated `File::insert` calls and with `File::insert_many`.
+//! Run with `cargo bench --bench bulk_insert`.
+
+use std::time::{Duration, Instant};
+
+use buffer_manager::BufferManager;
+use disk::Disk;
+use disk_manager::DiskManager;
+use file_system::unordered_file::File;
+
+const BLOCKSIZE: usize = 4096;
+const CAPACITY: usize = BLOCKSIZE * 32768;
+const RECORD_COUNT: usize = 200_000;
+const RECORD_SIZE: usize = 100;
+
+fn run(name: &str, load: impl FnOnce(&File<BLOCKSIZE, CAPACITY>, &[Vec<u8>])) -> Duration {
+    let disk = Disk::<BLOCKSIZE, CAPACITY>::create(name).unwrap();
+    let disk_manager = DiskManager::init(&disk);
+    let buffer_manager: BufferManager<BLOCKSIZE, CAPACITY> = BufferManager::init(256, &disk);
+    let file = File::init(&disk_manager, &buffer_manager);
+    let records: Vec<Vec<u8>> = (0..RECORD_COUNT)
+        .map(|i| vec![i as u8; RECORD_SIZE])
+        .collect();
+
+    let start = Instant::now();
+    load(&file, &records);
+    buffer_manager.flush().unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(file.cursor().count(), RECORD_COUNT);
+    elapsed
+}
+
+fn main() {
+    let repeated = run("bench_repeated_insert", |file, records| {
+        for record in records {
+            file.insert(record);
+        }
+    });
+    let bulk = run("bench_insert_many", |file, records| {
+        file.insert_many(records.iter().map(|record| record.as_slice()))
+            .unwrap();
+    });
+    println!("{RECORD_COUNT} records of {RECORD_SIZE} bytes");
+    println!("repeated insert: {repeated:?}");
+    println!("insert_many:     {bulk:?}");
+}
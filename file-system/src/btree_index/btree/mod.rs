@@ -1,9 +1,11 @@
 mod node;
 
+use std::collections::HashMap;
 use std::fmt::Formatter;
+use std::ops::Bound;
 
 use buffer_manager::BufferManager;
-use disk_manager::DiskManager;
+use disk_manager::{DiskManager, DiskManagerError};
 
 use self::node::{InsertResult, Node, NodePointer, NodeType};
 
@@ -30,14 +32,14 @@ impl RowAddress {
     }
 }
 
-pub struct BTree<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> {
+pub struct BTree<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
     root_ptr: NodePointer,
     disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
-    buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+    buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    std::fmt::Debug for BTree<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> std::fmt::Debug
+    for BTree<'a, BLOCKSIZE, CAPACITY>
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let root = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
@@ -48,14 +50,13 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
 #[derive(Debug)]
 pub struct KeyExistedError;
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    BTree<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> BTree<'a, BLOCKSIZE, CAPACITY> {
     pub fn init(
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
     ) -> Self {
-        let root = Node::new(NodeType::Leaf, buffer_manager, disk_manager);
+        let mut root = Node::new(NodeType::Leaf, buffer_manager, disk_manager);
+        root.set_tree_counts(0, 1);
         Self {
             root_ptr: root.page_number,
             disk_manager,
@@ -63,32 +64,169 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         }
     }
 
+    /// Open the tree whose root node is at page `root`
+    pub fn open(
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+        disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
+        root: u32,
+    ) -> Self {
+        Self {
+            root_ptr: root,
+            disk_manager,
+            buffer_manager,
+        }
+    }
+
+    /// Page of the root node, which never changes
+    pub fn root(&self) -> u32 {
+        self.root_ptr
+    }
+
     pub fn find_row_address(&self, key: &[u8]) -> Option<RowAddress> {
         let root = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
         root.find_row_address(key)
     }
 
+    /// Return every key between `start` and `end` with its row address, in key order
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, RowAddress)> {
+        let root = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
+        let mut entries = Vec::new();
+        root.range_scan(start, end, &mut entries);
+        entries
+    }
+
+    /// Largest key the tree can hold
+    pub const fn max_key_size() -> usize {
+        Node::<BLOCKSIZE, CAPACITY>::max_key_size()
+    }
+
+    /// Map `key` to `row_address`.
+    /// Panics if the key is longer than `max_key_size`, keys do not overflow to other pages.
     pub fn insert(&mut self, key: &[u8], row_address: RowAddress) -> Result<(), KeyExistedError> {
+        assert!(
+            key.len() <= Self::max_key_size(),
+            "Key of {} bytes is longer than the maximum key size",
+            key.len()
+        );
         let root = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
-        self.root_ptr = {
+        let (key_count, node_count) = root.tree_counts();
+        let (root_ptr, new_nodes) = {
             let result = root.node_insert(key, row_address);
             match result {
-                InsertResult::Normal(node) => node.page_number,
-                InsertResult::Splitted(key, left, right) => {
-                    let new_node =
-                        Node::new(NodeType::Interior, self.buffer_manager, self.disk_manager);
-                    new_node.set_right_child(right.page_number);
-                    let node = match new_node.interior_insert(&key, left.page_number, None) {
+                InsertResult::Normal(node) => (node.page_number, node.new_nodes),
+                InsertResult::Splitted(key, left, mut right) => {
+                    // The root was split in place, it keeps its page so the tree can always
+                    // be opened from the page it was created at: its right half moves to
+                    // a new page and the root becomes the parent of both halves.
+                    let moved =
+                        Node::new(right.node_type(), self.buffer_manager, self.disk_manager);
+                    moved.page().copy_from_slice(&right.page());
+                    right.clear(NodeType::Interior);
+                    right.set_right_child(moved.page_number);
+                    let new_nodes = right.new_nodes + 2;
+                    let node = match right.interior_insert(&key, left.page_number, None) {
                         InsertResult::Normal(node) => node,
                         _ => unreachable!(),
                     };
-                    node.page_number
+                    // The left half and the moved right half are new nodes
+                    (node.page_number, new_nodes)
                 }
                 InsertResult::KeyExisted(_key) => return Err(KeyExistedError),
             }
         };
+        self.root_ptr = root_ptr;
+        let mut root = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
+        root.set_tree_counts(key_count + 1, node_count + new_nodes);
         Ok(())
     }
+
+    /// Remove `key` from the tree, returning the row address it was mapped to
+    pub fn delete(&mut self, key: &[u8]) -> Option<RowAddress> {
+        let mut root = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
+        let row_address = root.delete(key)?;
+        let (key_count, node_count) = root.tree_counts();
+        root.set_tree_counts(key_count - 1, node_count);
+        Some(row_address)
+    }
+
+    /// Call `visit` with every node of the tree
+    fn for_each_node(&self, mut visit: impl FnMut(&Node<'a, BLOCKSIZE, CAPACITY>)) {
+        let mut pages = vec![self.root_ptr];
+        while let Some(page_number) = pages.pop() {
+            let node = Node::from(self.buffer_manager, self.disk_manager, page_number);
+            if node.node_type() == NodeType::Interior {
+                pages.extend(node.child_pages());
+            }
+            visit(&node);
+        }
+    }
+
+    /// Write every node of the tree back to the disk
+    pub fn save(&self) {
+        let mut pages = Vec::new();
+        self.for_each_node(|node| pages.push(node.page_number));
+        for page_number in pages {
+            self.buffer_manager.save_page(page_number).unwrap();
+        }
+    }
+
+    /// Copy the tree node by node, the copy maps its keys to the same row addresses.
+    /// Fail with `DiskFull`, allocating nothing, if the disk has no room for every node.
+    pub fn copy(&self) -> Result<Self, DiskManagerError> {
+        let mut pages = Vec::new();
+        self.for_each_node(|node| pages.push(node.page_number));
+        let copies = self.disk_manager.allocate_many(pages.len())?;
+        let page_of: HashMap<NodePointer, NodePointer> =
+            pages.iter().copied().zip(copies.iter().copied()).collect();
+        for (page_number, copy_number) in pages.iter().zip(&copies) {
+            let node = Node::from(self.buffer_manager, self.disk_manager, *page_number);
+            let mut copy = Node::from(self.buffer_manager, self.disk_manager, *copy_number);
+            copy.page().copy_from_slice(&node.page());
+            if copy.node_type() == NodeType::Interior {
+                copy.map_children(|child| page_of[&child]);
+            }
+        }
+        // The root is visited first
+        Ok(Self::open(
+            self.buffer_manager,
+            self.disk_manager,
+            copies[0],
+        ))
+    }
+
+    /// Give every node of the tree back to the disk manager
+    pub fn free(self) {
+        let mut pages = Vec::new();
+        self.for_each_node(|node| pages.push(node.page_number));
+        for page_number in pages {
+            self.buffer_manager.discard_page(page_number).unwrap();
+            self.disk_manager.deallocate(page_number).unwrap();
+        }
+    }
+
+    /// Number of levels of the tree, a tree made of its root alone has one
+    pub fn height(&self) -> u32 {
+        let mut node = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
+        let mut height = 1;
+        while node.node_type() == NodeType::Interior {
+            node = Node::from(self.buffer_manager, self.disk_manager, node.right_child());
+            height += 1;
+        }
+        height
+    }
+
+    /// Number of keys in the tree, kept in the root
+    pub fn key_count(&self) -> u64 {
+        Node::from(self.buffer_manager, self.disk_manager, self.root_ptr)
+            .tree_counts()
+            .0
+    }
+
+    /// Number of nodes of the tree, kept in the root
+    pub fn page_count(&self) -> u64 {
+        let root = Node::from(self.buffer_manager, self.disk_manager, self.root_ptr);
+        root.tree_counts().1 as u64
+    }
 }
 
 #[test]
@@ -101,11 +239,9 @@ fn basic_insert() {
     const BLOCK_SIZE: usize = 512;
     const DISK_CAPACITY: usize = 512 * 512;
     const MEMORY_CAPACITY: usize = 512 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("btree_basic_insert").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
     let mut btree = BTree::init(&buffer_manager, &disk_manager);
     let mut keys = Vec::new();
@@ -126,3 +262,188 @@ fn basic_insert() {
         assert_eq!(row.offset(), i as u32);
     }
 }
+
+#[test]
+fn range_scan() {
+    use rand::seq::SliceRandom;
+
+    const BLOCK_SIZE: usize = 512;
+    const DISK_CAPACITY: usize = 512 * 512;
+    let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("btree_range_scan").unwrap();
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> = BufferManager::init(16, &disk);
+    let disk_manager = DiskManager::init(&disk);
+    let mut btree = BTree::init(&buffer_manager, &disk_manager);
+    let key = |i: u32| format!("{:0100}", i).into_bytes();
+    let mut numbers: Vec<u32> = (0..300).collect();
+    numbers.shuffle(&mut rand::thread_rng());
+    for i in numbers {
+        btree.insert(&key(i), RowAddress::new(i, 0)).unwrap();
+    }
+
+    let scan = |start: Bound<&[u8]>, end: Bound<&[u8]>| -> Vec<u32> {
+        btree
+            .range(start, end)
+            .into_iter()
+            .map(|(k, row)| {
+                assert_eq!(k, key(row.page_number()));
+                row.page_number()
+            })
+            .collect()
+    };
+    assert_eq!(
+        scan(Bound::Unbounded, Bound::Unbounded),
+        (0..300).collect::<Vec<_>>()
+    );
+    let (from, to) = (key(42), key(250));
+    assert_eq!(
+        scan(Bound::Included(&from), Bound::Excluded(&to)),
+        (42..250).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        scan(Bound::Excluded(&from), Bound::Included(&to)),
+        (43..=250).collect::<Vec<_>>()
+    );
+    assert!(scan(Bound::Included(&to), Bound::Excluded(&from)).is_empty());
+    // Bounds which are not keys of the tree
+    let (from, to) = (key(42)[..99].to_vec(), key(100)[..98].to_vec());
+    assert_eq!(
+        scan(Bound::Included(&from), Bound::Excluded(&to)),
+        (40..100).collect::<Vec<_>>()
+    );
+    assert!(buffer_manager.metrics().misses > 0);
+}
+
+#[test]
+fn stable_root() {
+    const BLOCK_SIZE: usize = 512;
+    const DISK_CAPACITY: usize = 512 * 512;
+    let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("btree_stable_root").unwrap();
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> = BufferManager::init(16, &disk);
+    let disk_manager = DiskManager::init(&disk);
+    let mut btree = BTree::init(&buffer_manager, &disk_manager);
+    let root = btree.root();
+    let key = |i: u32| format!("{:080}", i).into_bytes();
+    for i in 0..500 {
+        btree.insert(&key(i), RowAddress::new(i, 0)).unwrap();
+        assert_eq!(btree.root(), root);
+    }
+    assert!(btree.page_count() > 10);
+    assert_eq!(btree.key_count(), 500);
+
+    let btree = BTree::open(&buffer_manager, &disk_manager, root);
+    for i in (0..500).step_by(7) {
+        assert_eq!(btree.find_row_address(&key(i)).unwrap().page_number(), i);
+    }
+    assert_eq!(btree.range(Bound::Unbounded, Bound::Unbounded).len(), 500);
+}
+
+#[test]
+fn delete() {
+    const BLOCK_SIZE: usize = 512;
+    const DISK_CAPACITY: usize = 512 * 512;
+    let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("btree_delete").unwrap();
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> = BufferManager::init(16, &disk);
+    let disk_manager = DiskManager::init(&disk);
+    let mut btree = BTree::init(&buffer_manager, &disk_manager);
+    let key = |i: u32| format!("{:080}", i).into_bytes();
+    for i in 0..300 {
+        btree.insert(&key(i), RowAddress::new(i, 0)).unwrap();
+    }
+    for i in (0..300).filter(|i| i % 3 == 0) {
+        assert_eq!(btree.delete(&key(i)), Some(RowAddress::new(i, 0)));
+    }
+    assert_eq!(btree.delete(&key(3)), None);
+    assert_eq!(btree.key_count(), 200);
+    for i in 0..300 {
+        assert_eq!(btree.find_row_address(&key(i)).is_some(), i % 3 != 0);
+    }
+
+    // The space of deleted keys is reused
+    let pages = btree.page_count();
+    for i in (0..300).filter(|i| i % 3 == 0) {
+        btree.insert(&key(i), RowAddress::new(i, 1)).unwrap();
+    }
+    assert_eq!(btree.page_count(), pages);
+    assert_eq!(
+        btree.find_row_address(&key(42)),
+        Some(RowAddress::new(42, 1))
+    );
+    assert_eq!(btree.range(Bound::Unbounded, Bound::Unbounded).len(), 300);
+}
+
+#[test]
+fn counts() {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    const BLOCK_SIZE: usize = 512;
+    const DISK_CAPACITY: usize = 512 * 1024;
+    let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("btree_counts").unwrap();
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> = BufferManager::init(16, &disk);
+    let disk_manager = DiskManager::init(&disk);
+    let mut btree = BTree::init(&buffer_manager, &disk_manager);
+    assert_eq!((btree.key_count(), btree.page_count()), (0, 1));
+    // Keys of different sizes inserted in random order split nodes at every level
+    let key = |i: u32| format!("{i:0width$}", width = 20 + i as usize % 60).into_bytes();
+    let mut order: Vec<u32> = (0..800).collect();
+    order.shuffle(&mut StdRng::seed_from_u64(0));
+    for &i in &order {
+        btree.insert(&key(i), RowAddress::new(i, 0)).unwrap();
+    }
+    for &i in &order[..100] {
+        btree.delete(&key(i)).unwrap();
+    }
+
+    // The counts kept in the root match the tree
+    let mut nodes = 0;
+    btree.for_each_node(|_| nodes += 1);
+    assert_eq!(btree.page_count(), nodes);
+    let keys = btree.range(Bound::Unbounded, Bound::Unbounded).len() as u64;
+    assert_eq!(btree.key_count(), keys);
+    assert_eq!(keys, 700);
+}
+
+#[test]
+fn copy_free() {
+    const BLOCK_SIZE: usize = 512;
+    const DISK_CAPACITY: usize = 512 * 256;
+    let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("btree_copy_free").unwrap();
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> = BufferManager::init(16, &disk);
+    let disk_manager = DiskManager::init(&disk);
+    let mut btree = BTree::init(&buffer_manager, &disk_manager);
+    for i in 0..300u32 {
+        btree
+            .insert(&i.to_be_bytes(), RowAddress::new(i, 0))
+            .unwrap();
+    }
+    assert!(btree.height() > 1);
+    let free_blocks = disk_manager.free_blocks();
+
+    let mut copy = btree.copy().unwrap();
+    let all =
+        |tree: &BTree<BLOCK_SIZE, DISK_CAPACITY>| tree.range(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(all(&copy), all(&btree));
+    assert_eq!(copy.page_count(), btree.page_count());
+    assert_eq!(
+        free_blocks - disk_manager.free_blocks(),
+        btree.page_count() as usize
+    );
+    // The copy is independent of the tree
+    copy.insert(&1000u32.to_be_bytes(), RowAddress::new(1000, 0))
+        .unwrap();
+    copy.delete(&0u32.to_be_bytes()).unwrap();
+    assert_eq!(btree.find_row_address(&1000u32.to_be_bytes()), None);
+    assert_eq!(
+        btree.find_row_address(&0u32.to_be_bytes()),
+        Some(RowAddress::new(0, 0))
+    );
+    copy.free();
+    assert_eq!(disk_manager.free_blocks(), free_blocks);
+
+    // Nothing is allocated when the disk has no room for the copy
+    let taken = disk_manager.allocate_many(free_blocks - 1).unwrap();
+    assert!(matches!(btree.copy(), Err(DiskManagerError::DiskFull)));
+    assert_eq!(disk_manager.free_blocks(), 1);
+    for block in taken {
+        disk_manager.deallocate(block).unwrap();
+    }
+}
@@ -7,6 +7,8 @@ pub type CellPointerArray = u32;
 pub type CellPointer = u16;
 pub type CellSize = u16;
 pub type CellContentOffset = u16;
+pub type KeyCount = u64;
+pub type NodeCount = u32;
 
 /// Common Node Header Layout
 /// (<offset>, <size>)
@@ -22,9 +24,19 @@ const RIGHT_MOST_CHILD_POINTER: (usize, usize) = (
     size_of::<NodePointer>(),
 );
 
-const CELL_POINTERS_ARRAY_OFFSET: usize = RIGHT_MOST_CHILD_POINTER.0 + RIGHT_MOST_CHILD_POINTER.1;
+/// Number of keys of the tree, only kept up to date in the root
+/// (<offset>, <size>)
+const KEY_COUNT: (usize, usize) = (
+    RIGHT_MOST_CHILD_POINTER.0 + RIGHT_MOST_CHILD_POINTER.1,
+    size_of::<KeyCount>(),
+);
+/// Number of nodes of the tree, only kept up to date in the root
+/// (<offset>, <size>)
+const NODE_COUNT: (usize, usize) = (KEY_COUNT.0 + KEY_COUNT.1, size_of::<NodeCount>());
+
+pub(super) const CELL_POINTERS_ARRAY_OFFSET: usize = NODE_COUNT.0 + NODE_COUNT.1;
 
-const CELL_POINTER_SIZE: usize = size_of::<CellPointer>() + size_of::<CellSize>();
+pub(super) const CELL_POINTER_SIZE: usize = size_of::<CellPointer>() + size_of::<CellSize>();
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 #[non_exhaustive]
@@ -100,6 +112,14 @@ impl NodeHeaderReader {
             *(self.start.add(RIGHT_MOST_CHILD_POINTER.0) as *const [u8; 4])
         })
     }
+
+    pub fn key_count(&self) -> KeyCount {
+        KeyCount::from_be_bytes(unsafe { *(self.start.add(KEY_COUNT.0) as *const [u8; 8]) })
+    }
+
+    pub fn node_count(&self) -> NodeCount {
+        NodeCount::from_be_bytes(unsafe { *(self.start.add(NODE_COUNT.0) as *const [u8; 4]) })
+    }
 }
 
 pub struct NodeHeaderWriter {
@@ -157,6 +177,18 @@ impl NodeHeaderWriter {
                 right_most_child_pointer.to_be_bytes();
         }
     }
+
+    pub fn set_key_count(&mut self, key_count: KeyCount) {
+        unsafe {
+            *(self.start.add(KEY_COUNT.0) as *mut [u8; 8]) = key_count.to_be_bytes();
+        }
+    }
+
+    pub fn set_node_count(&mut self, node_count: NodeCount) {
+        unsafe {
+            *(self.start.add(NODE_COUNT.0) as *mut [u8; 4]) = node_count.to_be_bytes();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -173,6 +205,8 @@ mod tests {
         writer.set_num_cells(0x12345678);
         writer.set_cell_content_start(0x1234);
         writer.set_right_most_child(0x12345678);
+        writer.set_key_count(0x123456789a);
+        writer.set_node_count(0x1234);
         writer.set_cell_pointer_and_size(0, 12, 34);
         writer.set_cell_pointer_and_size(1, 34, 12);
         println!("{:?}", buf);
@@ -182,6 +216,8 @@ mod tests {
         assert_eq!(reader.num_cells(), 0x12345678);
         assert_eq!(reader.cell_content_start(), 0x1234);
         assert_eq!(reader.right_most_child(), 0x12345678);
+        assert_eq!(reader.key_count(), 0x123456789a);
+        assert_eq!(reader.node_count(), 0x1234);
         assert_eq!(reader.cell_pointer_and_size(0), (12, 34));
         assert_eq!(reader.cell_pointer_and_size(1), (34, 12));
     }
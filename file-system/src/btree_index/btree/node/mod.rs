@@ -9,6 +9,7 @@ use disk_manager::DiskManager;
 use std::{
     fmt::Debug,
     mem::size_of,
+    ops::Bound,
     ptr::{slice_from_raw_parts, slice_from_raw_parts_mut},
 };
 
@@ -20,16 +21,18 @@ use super::RowAddress;
 pub use header::NodePointer;
 pub use header::NodeType;
 
-/// Each node of the btree is contained inside 1 page
-pub struct Node<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> {
+/// Each node of the btree is contained inside 1 page.
+/// The page stays pinned while the node exists, so cells borrowed from it stay valid.
+pub struct Node<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
     pub(super) page_number: u32,
+    _pin: Page<'a, BLOCKSIZE, CAPACITY>,
     disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
-    buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+    buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+    /// Nodes allocated by splits while inserting into this node or below it
+    pub(super) new_nodes: NodeCount,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> Debug
-    for Node<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> Debug for Node<'a, BLOCKSIZE, CAPACITY> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.node_type() {
             NodeType::Leaf => {
@@ -88,34 +91,29 @@ pub enum Slot {
 }
 
 #[derive(Debug)]
-pub enum InsertResult<
-    'a,
-    const BLOCKSIZE: usize,
-    const CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
-> {
-    KeyExisted(Node<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>),
-    Normal(Node<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>),
+pub enum InsertResult<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    KeyExisted(Node<'a, BLOCKSIZE, CAPACITY>),
+    Normal(Node<'a, BLOCKSIZE, CAPACITY>),
     Splitted(
         Vec<u8>,
-        Node<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
-        Node<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        Node<'a, BLOCKSIZE, CAPACITY>,
+        Node<'a, BLOCKSIZE, CAPACITY>,
     ),
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    Node<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> Node<'a, BLOCKSIZE, CAPACITY> {
     pub fn new(
         node_type: NodeType,
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
     ) -> Self {
         let new_page = disk_manager.allocate().unwrap();
         let mut node = Node {
             page_number: new_page,
+            _pin: buffer_manager.get_page(new_page),
             buffer_manager,
             disk_manager,
+            new_nodes: 0,
         };
         if node.cell_content_start() == 0 {
             node.set_cell_content_start(BLOCKSIZE as CellContentOffset);
@@ -125,18 +123,20 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
     }
 
     pub fn from(
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
         page_num: u32,
     ) -> Self {
         Node {
             page_number: page_num,
+            _pin: buffer_manager.get_page(page_num),
             buffer_manager,
             disk_manager,
+            new_nodes: 0,
         }
     }
 
-    pub(crate) fn page(&self) -> Page<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> {
+    pub(crate) fn page(&self) -> Page<'a, BLOCKSIZE, CAPACITY> {
         self.buffer_manager.get_page(self.page_number)
     }
 
@@ -146,26 +146,40 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
     }
 
     pub fn set_node_type(&mut self, node_type: NodeType) {
-        let page = self.page().as_mut_ptr();
-        unsafe { NodeHeaderWriter::new(page).set_node_type(node_type) }
+        let mut page = self.page();
+        unsafe { NodeHeaderWriter::new(page.as_mut_ptr()).set_node_type(node_type) }
     }
 
     pub fn num_cells(&self) -> CellsCount {
-        let page = self.page().as_mut_ptr();
-        unsafe { NodeHeaderReader::new(page).num_cells() }
+        let page = self.page();
+        unsafe { NodeHeaderReader::new(page.as_ptr()).num_cells() }
     }
 
     pub fn set_num_cells(&mut self, num_cells: u32) {
-        let page = self.page().as_mut_ptr();
-        unsafe { NodeHeaderWriter::new(page).set_num_cells(num_cells) }
+        let mut page = self.page();
+        unsafe { NodeHeaderWriter::new(page.as_mut_ptr()).set_num_cells(num_cells) }
     }
 
-    fn right_child(&self) -> NodePointer {
+    pub(super) fn right_child(&self) -> NodePointer {
         assert_eq!(self.node_type(), NodeType::Interior);
         let page = self.page();
         unsafe { NodeHeaderReader::new(page.as_ptr()).right_most_child() }
     }
 
+    /// Number of keys and of nodes of the tree, only kept up to date in the root
+    pub(super) fn tree_counts(&self) -> (KeyCount, NodeCount) {
+        let page = self.page();
+        let reader = unsafe { NodeHeaderReader::new(page.as_ptr()) };
+        (reader.key_count(), reader.node_count())
+    }
+
+    pub(super) fn set_tree_counts(&mut self, key_count: KeyCount, node_count: NodeCount) {
+        let mut page = self.page();
+        let mut writer = unsafe { NodeHeaderWriter::new(page.as_mut_ptr()) };
+        writer.set_key_count(key_count);
+        writer.set_node_count(node_count);
+    }
+
     pub fn set_right_child(&self, child: NodePointer) {
         assert_eq!(
             self.node_type(),
@@ -249,6 +263,14 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         self.set_cell_pointer_and_size(hole, pointer, size);
     }
 
+    fn remove_cell_pointer(&mut self, cell_num: u32) {
+        for cell_i in cell_num + 1..self.num_cells() {
+            let (ptr, size) = self.cell_pointer_and_size(cell_i);
+            self.set_cell_pointer_and_size(cell_i - 1, ptr, size);
+        }
+        self.set_num_cells(self.num_cells() - 1);
+    }
+
     fn cell_pointers_array_start(&self) -> usize {
         unsafe { NodeHeaderReader::new(self.page().as_ptr()).cell_pointers_array_start() }
     }
@@ -282,6 +304,102 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         }
     }
 
+    /// Remove `key` from the leaf holding it and return its row address.
+    /// The space of its cell is reclaimed right away, but nodes are never merged,
+    /// so a leaf may be left with few keys or none.
+    pub fn delete(&mut self, key: &[u8]) -> Option<RowAddress> {
+        match self.node_type() {
+            NodeType::Leaf => {
+                let cell_num = match self.search(key) {
+                    Slot::Cell(cell_num) => cell_num,
+                    Slot::Hole(_) => return None,
+                };
+                let row_address = self.row_address_of_cell(cell_num);
+                self.remove_cell_pointer(cell_num);
+                self.clean_holes();
+                Some(row_address)
+            }
+            NodeType::Interior => {
+                let child = self.child_at(self.child_index(key));
+                Node::from(self.buffer_manager, self.disk_manager, child).delete(key)
+            }
+        }
+    }
+
+    /// Index of the child which would hold `key`, the right child has index `num_cells`
+    fn child_index(&self, key: &[u8]) -> u32 {
+        match self.search(key) {
+            Slot::Hole(hole) => hole,
+            Slot::Cell(cell_num) => cell_num + 1,
+        }
+    }
+
+    fn child_at(&self, index: u32) -> NodePointer {
+        if index >= self.num_cells() {
+            self.right_child()
+        } else {
+            self.child_pointer_of_cell(index)
+        }
+    }
+
+    /// Collect every key between `start` and `end` with its row address, in key order.
+    /// Before descending into a child of an interior node, the next child to visit
+    /// is prefetched, so it is read while the current one is scanned.
+    pub fn range_scan(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        entries: &mut Vec<(Vec<u8>, RowAddress)>,
+    ) {
+        match self.node_type() {
+            NodeType::Leaf => {
+                for cell_num in 0..self.num_cells() {
+                    let key = self.key_of_cell(cell_num);
+                    let after_start = match start {
+                        Bound::Included(start) => key.as_slice() >= start,
+                        Bound::Excluded(start) => key.as_slice() > start,
+                        Bound::Unbounded => true,
+                    };
+                    let before_end = match end {
+                        Bound::Included(end) => key.as_slice() <= end,
+                        Bound::Excluded(end) => key.as_slice() < end,
+                        Bound::Unbounded => true,
+                    };
+                    if !before_end {
+                        break;
+                    }
+                    if after_start {
+                        let row_address = self.row_address_of_cell(cell_num);
+                        entries.push((key, row_address));
+                    }
+                }
+            }
+            NodeType::Interior => {
+                let first = match start {
+                    Bound::Included(key) | Bound::Excluded(key) => self.child_index(key),
+                    Bound::Unbounded => 0,
+                };
+                let last = match end {
+                    Bound::Included(key) => self.child_index(key),
+                    // A key equal to the separator belongs to the child on its right,
+                    // which can not hold anything smaller than the bound
+                    Bound::Excluded(key) => match self.search(key) {
+                        Slot::Hole(hole) => hole,
+                        Slot::Cell(cell_num) => cell_num,
+                    },
+                    Bound::Unbounded => self.num_cells(),
+                };
+                for index in first..=last {
+                    if index < last {
+                        self.buffer_manager.prefetch(&[self.child_at(index + 1)]);
+                    }
+                    Node::from(self.buffer_manager, self.disk_manager, self.child_at(index))
+                        .range_scan(start, end, entries);
+                }
+            }
+        }
+    }
+
     /// Insert a payload into a leaf node
     /// Return a normal node if insert normally
     /// Return a pair of node if need split
@@ -290,7 +408,7 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         key: &[u8],
         row_address: RowAddress,
         overflow_head: Option<NodePointer>,
-    ) -> InsertResult<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> {
+    ) -> InsertResult<'a, BLOCKSIZE, CAPACITY> {
         if self.node_type() != NodeType::Leaf {
             panic!("Inserting into a non-leaf node");
         }
@@ -316,8 +434,7 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
                 todo!();
             }
             InsertDecision::Split => {
-                let mid = self.num_cells() / 2;
-                assert!(self.num_cells() > 0);
+                let mid = self.split_point().max(1);
                 let mut new_left_node =
                     Node::new(NodeType::Leaf, self.buffer_manager, self.disk_manager);
                 for i in 0..mid {
@@ -333,7 +450,6 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
                     };
                 }
                 let mid_key = self.key_of_cell(mid);
-                println!("free space: {:?}", self.cell_bounds());
                 let remain_cell = self.num_cells() - mid;
                 let remain_start = unsafe {
                     NodeHeaderReader::new(self.page().as_ptr()).cell_pointers_array_start()
@@ -407,7 +523,7 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
     pub fn find_holes(&self) -> Vec<(usize, usize)> {
         let mut holes = Vec::new();
         let cells = self.cell_bounds();
-        for i in 0..self.num_cells() - 1 {
+        for i in 0..self.num_cells().saturating_sub(1) {
             let (_, offset, size) = cells[i as usize];
             let (_, next_offset, _next_size) = cells[(i + 1) as usize];
             if offset + size != next_offset {
@@ -425,7 +541,7 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         key: &[u8],
         child: NodePointer,
         overflow_head: Option<NodePointer>,
-    ) -> InsertResult<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> {
+    ) -> InsertResult<'a, BLOCKSIZE, CAPACITY> {
         if self.node_type() != NodeType::Interior {
             panic!("Not interior node");
         }
@@ -452,7 +568,7 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
             InsertDecision::Split => {
                 let mut new_left_node =
                     Node::new(NodeType::Interior, self.buffer_manager, self.disk_manager);
-                let mid = self.num_cells() / 2;
+                let mid = self.split_point();
                 for i in 0..mid {
                     new_left_node = match new_left_node.interior_insert(
                         &self.key_of_cell(i),
@@ -510,6 +626,34 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         }
     }
 
+    /// Largest key a node can hold. Keys of this size leave room for 4 cells in a node,
+    /// so both halves of a split node have room for the key being inserted.
+    pub const fn max_key_size() -> usize {
+        let cell_header = if Cell::leaf_header_size() > Cell::interior_header_size() {
+            Cell::leaf_header_size()
+        } else {
+            Cell::interior_header_size()
+        };
+        (BLOCKSIZE - CELL_POINTERS_ARRAY_OFFSET) / 4 - cell_header - CELL_POINTER_SIZE
+    }
+
+    /// Index of the first cell of the right half of a split: the cells before it
+    /// take at most half of the space used by the cells, and the cells from it
+    /// at most half and one cell. The last cell is never moved left.
+    fn split_point(&self) -> u32 {
+        let sizes: Vec<usize> = (0..self.num_cells())
+            .map(|i| self.cell_pointer_and_size(i).1 as usize + CELL_POINTER_SIZE)
+            .collect();
+        let half = sizes.iter().sum::<usize>() / 2;
+        let mut mid = 0;
+        let mut left_size = 0;
+        while mid + 1 < sizes.len() && left_size + sizes[mid] <= half {
+            left_size += sizes[mid];
+            mid += 1;
+        }
+        mid as u32
+    }
+
     /// Return the cell pointer of the first cell
     fn clean_holes(&mut self) -> usize {
         let mut buf: Vec<u8> = Vec::new();
@@ -524,14 +668,36 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
             current_pos += cell_bounds[i as usize].2 as usize;
         }
         self.set_cell_content_start((BLOCKSIZE - buf.len()) as u16);
-        println!("{:?}", buf);
-        println!("{:?}", cell_bounds);
         assert!(self.find_holes().is_empty());
-        println!("{:?}", self.cell_bounds());
         BLOCKSIZE - buf.len()
     }
 
-    pub(crate) fn children(&self) -> Vec<Node<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>> {
+    /// Empty the node and give it the type `node_type`
+    pub(super) fn clear(&mut self, node_type: NodeType) {
+        self.page().fill(0);
+        self.set_cell_content_start(BLOCKSIZE as CellContentOffset);
+        self.set_node_type(node_type);
+    }
+
+    /// Pages of the children of an interior node, which are not pinned unlike `children`
+    pub(super) fn child_pages(&self) -> Vec<NodePointer> {
+        let mut pages: Vec<_> = (0..self.num_cells())
+            .map(|i| self.child_pointer_of_cell(i))
+            .collect();
+        pages.push(self.right_child());
+        pages
+    }
+
+    /// Point the children of an interior node to the pages given by `page_of`
+    pub(super) fn map_children(&mut self, page_of: impl Fn(NodePointer) -> NodePointer) {
+        for i in 0..self.num_cells() {
+            let child = page_of(self.child_pointer_of_cell(i));
+            self.cell_mut_at(i).set_child_pointer(child);
+        }
+        self.set_right_child(page_of(self.right_child()));
+    }
+
+    pub(crate) fn children(&self) -> Vec<Node<'a, BLOCKSIZE, CAPACITY>> {
         let mut children = Vec::new();
         for i in 0..self.num_cells() {
             children.push(self.child_pointer_of_cell(i));
@@ -575,6 +741,8 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         let cell_slice = page.as_mut_ptr().add(ptr as usize);
         let new_cell_slice = page.as_mut_ptr().add((ptr as isize + offset) as usize);
         cell_slice.copy_to(new_cell_slice, size as usize);
+        // Only one handle of a page may access it while one of them writes to it
+        drop(page);
         self.set_cell_pointer_and_size(idx as u32, (ptr as isize + offset) as u16, size);
     }
 
@@ -593,16 +761,13 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
         mut self,
         key: &[u8],
         row_address: RowAddress,
-    ) -> InsertResult<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> {
+    ) -> InsertResult<'a, BLOCKSIZE, CAPACITY> {
         match self.node_type() {
             NodeType::Leaf => return self.leaf_insert(key, row_address, None),
             NodeType::Interior => {
-                // Find the child to insert the payload into
-                let hole = self.search(key);
-                let hole = match hole {
-                    Slot::Hole(hole) => hole,
-                    Slot::Cell(cell) => cell,
-                };
+                // Find the child to insert the payload into, a key equal to a separator
+                // belongs to the child on its right, as in `find_row_address`
+                let hole = self.child_index(key);
                 let child = {
                     if hole >= self.num_cells() {
                         self.right_child()
@@ -616,7 +781,8 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
                     node_to_insert.node_insert(key, row_address)
                 };
                 match result {
-                    InsertResult::Normal(_node) => {
+                    InsertResult::Normal(node) => {
+                        self.new_nodes += node.new_nodes;
                         return InsertResult::Normal(self);
                     }
                     // if the under layer node is splitted, we need to update the child pointer
@@ -628,7 +794,17 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
                             let mut cell = self.cell_mut_at(hole);
                             cell.set_child_pointer(right.page_number);
                         }
-                        self.interior_insert(&returned_key, left.page_number, None)
+                        // The left half of the child is a new node
+                        let new_nodes = right.new_nodes + 1;
+                        let mut result =
+                            self.interior_insert(&returned_key, left.page_number, None);
+                        match &mut result {
+                            InsertResult::Normal(node) | InsertResult::Splitted(_, _, node) => {
+                                node.new_nodes += new_nodes
+                            }
+                            InsertResult::KeyExisted(_) => {}
+                        }
+                        result
                     }
                     InsertResult::KeyExisted(key) => InsertResult::KeyExisted(key),
                 }
@@ -694,7 +870,10 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
     }
 
     fn insert_decision(&self, payload_size: usize) -> InsertDecision {
-        let free_size = self.free_size();
+        // The new cell also takes a cell pointer
+        let free_size = self
+            .free_size()
+            .saturating_sub(size_of::<u16>() + size_of::<u16>());
         let node_type = self.node_type();
         match node_type {
             NodeType::Interior => {
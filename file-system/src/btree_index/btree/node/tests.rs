@@ -6,30 +6,25 @@ use crate::btree_index::btree::{
 use buffer_manager::BufferManager;
 use disk_manager::DiskManager;
 
-fn init<'a, const BLOCKSIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACITY: usize>(
+fn init<const BLOCKSIZE: usize, const DISK_CAPACITY: usize>(
     file_name: &str,
-    memory: &'a [u8; MEMORY_CAPACITY],
+    frames: usize,
 ) -> (
-    BufferManager<'a, BLOCKSIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
+    BufferManager<BLOCKSIZE, DISK_CAPACITY>,
     DiskManager<BLOCKSIZE, DISK_CAPACITY>,
 ) {
     let disk = disk::Disk::<BLOCKSIZE, DISK_CAPACITY>::create(file_name).unwrap();
-    let buffer_manager: BufferManager<'_, BLOCKSIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(memory, &disk);
+    let buffer_manager: BufferManager<BLOCKSIZE, DISK_CAPACITY> =
+        BufferManager::init(frames, &disk);
     let disk_manager = DiskManager::init(&disk);
     (buffer_manager, disk_manager)
 }
 
 use super::{header::NodePointer, InsertResult, Node};
 
-fn create_sample_tree<
-    'a,
-    const BLOCKSIZE: usize,
-    const CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
->(
+fn create_sample_tree<const BLOCKSIZE: usize, const CAPACITY: usize>(
     disk_manager: &DiskManager<BLOCKSIZE, CAPACITY>,
-    buffer_manager: &BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+    buffer_manager: &BufferManager<BLOCKSIZE, CAPACITY>,
 ) -> NodePointer {
     let node = Node::new(NodeType::Leaf, &buffer_manager, &disk_manager);
     let node = match node.node_insert(&['t' as u8; 100], RowAddress::new(3333, 8888)) {
@@ -67,10 +62,8 @@ fn shifting_cell() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let (buffer_manager, disk_manager) =
-        init::<BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>("shifting_cell", &memory);
+        init::<BLOCK_SIZE, DISK_CAPACITY>("shifting_cell", MEMORY_CAPACITY / BLOCK_SIZE);
 
     let node = Node::new(NodeType::Leaf, &buffer_manager, &disk_manager);
     let mut node = match node.node_insert(&[1, 2, 3], RowAddress::new(1, 2)) {
@@ -94,10 +87,8 @@ fn cleaning_holes() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let (buffer_manager, disk_manager) =
-        init::<BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>("cleaning_holes", &memory);
+        init::<BLOCK_SIZE, DISK_CAPACITY>("cleaning_holes", MEMORY_CAPACITY / BLOCK_SIZE);
 
     let node = Node::new(NodeType::Leaf, &buffer_manager, &disk_manager);
     let node = match node.node_insert(&[1, 2, 3], RowAddress::new(1, 2)) {
@@ -133,17 +124,15 @@ fn basic_header() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("basic_header").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let mut node = Node::new(NodeType::Leaf, &buffer_manager, &disk_manager);
     assert_eq!(node.node_type(), NodeType::Leaf);
     assert_eq!(node.num_cells(), 0);
-    assert_eq!(node.free_size(), 4085);
+    assert_eq!(node.free_size(), 4073);
     assert_eq!(node.cell_content_start(), 4096);
     node.set_num_cells(14);
     assert_eq!(node.num_cells(), 14);
@@ -161,11 +150,9 @@ fn insert_cell_pointer() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("insert_cell_pointer").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let mut node = Node::new(NodeType::Leaf, &buffer_manager, &disk_manager);
@@ -191,13 +178,11 @@ fn insert_and_search_in_interior_node() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk =
         disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("insert_and_search_in_interior_node")
             .unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let keys: Vec<i32> = vec![5, 56, 43, 67, 47, 2, 34, 2345, 235];
@@ -252,12 +237,10 @@ fn insert_and_search_in_leaf_node() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk =
         disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("insert_and_search_in_leaf_node").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let keys: Vec<i32> = vec![5, 56, 43, 67, 47, 2, 34, 2345, 235];
@@ -312,11 +295,9 @@ fn basic_interior_insert() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("basic_interior_insert").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let node = Node::new(NodeType::Interior, &buffer_manager, &disk_manager);
@@ -348,11 +329,9 @@ fn basic_leaf_insert() {
     const BLOCK_SIZE: usize = 4096;
     const DISK_CAPACITY: usize = 4096 * 32;
     const MEMORY_CAPACITY: usize = 4096 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("basic_leaf_insert").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let node = Node::new(NodeType::Leaf, &buffer_manager, &disk_manager);
@@ -389,8 +368,8 @@ fn basic_leaf_insert() {
 
 //     let memory = [0; MEMORY_CAPACITY];
 //     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("find_holes").unwrap();
-//     let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-//         BufferManager::init(&memory, &disk);
+//     let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+//         BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
 //     let disk_manager = DiskManager::init(&disk);
 
 //     let mut node = Node::new(&buffer_manager, &disk_manager);
@@ -425,8 +404,8 @@ fn basic_leaf_insert() {
 
 //     let memory = [0; MEMORY_CAPACITY];
 //     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("find_holes").unwrap();
-//     let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-//         BufferManager::init(&memory, &disk);
+//     let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+//         BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
 //     let disk_manager = DiskManager::init(&disk);
 
 //     let mut node = Node::new(&buffer_manager, &disk_manager);
@@ -481,11 +460,9 @@ fn leaf_insert_split() {
     const BLOCK_SIZE: usize = 512;
     const DISK_CAPACITY: usize = 512 * 32;
     const MEMORY_CAPACITY: usize = 512 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("leaf_insert_split").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let node = Node::new(NodeType::Leaf, &buffer_manager, &disk_manager);
@@ -530,11 +507,9 @@ fn interior_insert_split() {
     const BLOCK_SIZE: usize = 512;
     const DISK_CAPACITY: usize = 512 * 32;
     const MEMORY_CAPACITY: usize = 512 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("interior_insert_split").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let node = Node::new(NodeType::Interior, &buffer_manager, &disk_manager);
@@ -556,28 +531,18 @@ fn interior_insert_split() {
     };
 }
 
-fn handle_normal_insert<
-    'a,
-    const BLOCK_SIZE: usize,
-    const DISK_CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
->(
-    rs: InsertResult<'a, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
-) -> Node<'a, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> {
+fn handle_normal_insert<'a, const BLOCK_SIZE: usize, const DISK_CAPACITY: usize>(
+    rs: InsertResult<'a, BLOCK_SIZE, DISK_CAPACITY>,
+) -> Node<'a, BLOCK_SIZE, DISK_CAPACITY> {
     match rs {
         InsertResult::Normal(node) => node,
         _ => unreachable!(),
     }
 }
 
-fn handle_split_insert<
-    'a,
-    const BLOCK_SIZE: usize,
-    const DISK_CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
->(
-    rs: InsertResult<'a, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
-) -> Node<'a, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> {
+fn handle_split_insert<'a, const BLOCK_SIZE: usize, const DISK_CAPACITY: usize>(
+    rs: InsertResult<'a, BLOCK_SIZE, DISK_CAPACITY>,
+) -> Node<'a, BLOCK_SIZE, DISK_CAPACITY> {
     match rs {
         InsertResult::Splitted(key, left, right) => {
             let buffer_manager = left.buffer_manager;
@@ -600,11 +565,9 @@ fn node_insert_split() {
     const BLOCK_SIZE: usize = 512;
     const DISK_CAPACITY: usize = 512 * 32;
     const MEMORY_CAPACITY: usize = 512 * 16;
-
-    let memory = [0; MEMORY_CAPACITY];
     let disk = disk::Disk::<BLOCK_SIZE, DISK_CAPACITY>::create("node_insert_split").unwrap();
-    let buffer_manager: BufferManager<'_, BLOCK_SIZE, DISK_CAPACITY, MEMORY_CAPACITY> =
-        BufferManager::init(&memory, &disk);
+    let buffer_manager: BufferManager<BLOCK_SIZE, DISK_CAPACITY> =
+        BufferManager::init(MEMORY_CAPACITY / BLOCK_SIZE, &disk);
     let disk_manager = DiskManager::init(&disk);
 
     let node_ptr = create_sample_tree(&disk_manager, &buffer_manager);
@@ -617,8 +580,8 @@ fn node_insert_split() {
     let root = handle_normal_insert(root.node_insert(&['d' as u8; 97], RowAddress::new(111, 222)));
     let root = handle_normal_insert(root.node_insert(&['v' as u8; 95], RowAddress::new(111, 222)));
     let root = handle_normal_insert(root.node_insert(&['d' as u8; 110], RowAddress::new(111, 222)));
-    let root = handle_normal_insert(root.node_insert(&['k' as u8; 111], RowAddress::new(111, 222)));
-    let root = handle_split_insert(root.node_insert(&['z' as u8; 112], RowAddress::new(111, 222)));
+    let root = handle_split_insert(root.node_insert(&['k' as u8; 111], RowAddress::new(111, 222)));
+    let root = handle_normal_insert(root.node_insert(&['z' as u8; 112], RowAddress::new(111, 222)));
     let root = handle_normal_insert(root.node_insert(&['f' as u8; 108], RowAddress::new(111, 222)));
     let root = handle_normal_insert(root.node_insert(&['g' as u8; 108], RowAddress::new(111, 222)));
     let root = handle_normal_insert(root.node_insert(&['h' as u8; 109], RowAddress::new(111, 222)));
@@ -1,61 +1,385 @@
-use std::mem::size_of;
+use std::{collections::BTreeMap, mem::size_of, ops::Bound, time::SystemTime};
 
-use crate::unordered_file::File;
+use crate::{
+    btree_index::btree::{BTree, RowAddress},
+    timestamp,
+    unordered_file::{File, FileError, RecordId},
+    FileSystemError,
+};
 use buffer_manager::BufferManager;
 use disk_manager::DiskManager;
 
-/// This table is used to store the file name and the block number of the file.
-pub struct FilesTable<
-    'a,
-    const BLOCKSIZE: usize,
-    const CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
-> {
-    file: File<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+/// Separates the directories of a path, as in `tenant/table`
+pub const SEPARATOR: char = '/';
+
+/// Whether `path` names a file or a directory: names separated by `SEPARATOR`, none empty
+pub fn is_valid_path(path: &str) -> bool {
+    path.split(SEPARATOR).all(|name| !name.is_empty())
+}
+
+/// Directory holding `path`, the root directory is `""`
+pub fn parent(path: &str) -> &str {
+    path.rsplit_once(SEPARATOR).map_or("", |(parent, _)| parent)
+}
+
+/// What a file holds, which tells how to open it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// Records of a table, see `unordered_file::File`
+    Heap,
+    /// A `BTree`, whose root is the block number of the file
+    Index,
+    /// Schema of a table, stored in a heap file
+    Schema,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File(FileType),
+    Directory,
+}
+
+impl EntryKind {
+    fn to_u8(self) -> u8 {
+        match self {
+            EntryKind::File(FileType::Heap) => 0,
+            EntryKind::Directory => 1,
+            EntryKind::File(FileType::Index) => 2,
+            EntryKind::File(FileType::Schema) => 3,
+        }
+    }
+
+    fn from_u8(byte: u8) -> Self {
+        match byte {
+            0 => EntryKind::File(FileType::Heap),
+            1 => EntryKind::Directory,
+            2 => EntryKind::File(FileType::Index),
+            3 => EntryKind::File(FileType::Schema),
+            _ => panic!("Invalid entry kind: {}", byte),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: String,
+    pub kind: EntryKind,
+    /// Head page of a file, 0 for a directory
+    pub block_number: u32,
+    pub created: SystemTime,
+    /// Last change to the entry, such as a new property
+    pub modified: SystemTime,
+    /// Properties set by the users of the file system
+    pub properties: BTreeMap<String, String>,
+    /// Named buffer pool the pages of the file are cached in, `None` for the default pool
+    pub pool: Option<String>,
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn read_str(buf: &[u8], offset: &mut usize) -> String {
+    let len = u16::from_be_bytes(buf[*offset..*offset + size_of::<u16>()].try_into().unwrap());
+    *offset += size_of::<u16>();
+    let s = String::from_utf8(buf[*offset..*offset + len as usize].to_vec()).unwrap();
+    *offset += len as usize;
+    s
+}
+
+fn read_u64(buf: &[u8], offset: &mut usize) -> u64 {
+    let value = u64::from_be_bytes(buf[*offset..*offset + size_of::<u64>()].try_into().unwrap());
+    *offset += size_of::<u64>();
+    value
+}
+
+impl Entry {
+    pub(crate) fn new(path: &str, kind: EntryKind, block_number: u32) -> Self {
+        let now = timestamp::to_system_time(timestamp::now());
+        Self {
+            path: path.to_string(),
+            kind,
+            block_number,
+            created: now,
+            modified: now,
+            properties: BTreeMap::new(),
+            pool: None,
+        }
+    }
+
+    /// Last name of the path
+    pub fn name(&self) -> &str {
+        self.path.rsplit(SEPARATOR).next().unwrap()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![self.kind.to_u8()];
+        buf.extend_from_slice(&self.block_number.to_be_bytes());
+        for time in [self.created, self.modified] {
+            buf.extend_from_slice(&timestamp::to_millis(time).to_be_bytes());
+        }
+        write_str(&mut buf, &self.path);
+        buf.extend_from_slice(&(self.properties.len() as u16).to_be_bytes());
+        for (key, value) in &self.properties {
+            write_str(&mut buf, key);
+            write_str(&mut buf, value);
+        }
+        write_str(&mut buf, self.pool.as_deref().unwrap_or(""));
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Self {
+        let kind = EntryKind::from_u8(buf[0]);
+        let mut offset = 1 + size_of::<u32>();
+        let block_number = u32::from_be_bytes(buf[1..offset].try_into().unwrap());
+        let created = timestamp::to_system_time(read_u64(buf, &mut offset));
+        let modified = timestamp::to_system_time(read_u64(buf, &mut offset));
+        let path = read_str(buf, &mut offset);
+        let property_count =
+            u16::from_be_bytes(buf[offset..offset + size_of::<u16>()].try_into().unwrap());
+        offset += size_of::<u16>();
+        let properties = (0..property_count)
+            .map(|_| (read_str(buf, &mut offset), read_str(buf, &mut offset)))
+            .collect();
+        // Entries written before pools were recorded end with their properties
+        let pool = if offset < buf.len() {
+            Some(read_str(buf, &mut offset)).filter(|pool| !pool.is_empty())
+        } else {
+            None
+        };
+        Self {
+            path,
+            kind,
+            block_number,
+            created,
+            modified,
+            properties,
+            pool,
+        }
+    }
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    FilesTable<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
+/// This table is used to store the path and the block number of the files,
+/// and the paths of the directories holding them.
+/// Entries are records of a heap file, found by their path in a `BTree`.
+/// The first record of the heap file holds the root page of the tree.
+pub struct FilesTable<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    file: File<'a, BLOCKSIZE, CAPACITY>,
+    buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+    disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
+}
+
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> FilesTable<'a, BLOCKSIZE, CAPACITY> {
     pub fn init(
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
     ) -> Self {
         let file = File::init(disk_manager, buffer_manager);
-        Self { file }
+        let index = BTree::init(buffer_manager, disk_manager);
+        file.insert(&index.root().to_be_bytes());
+        Self {
+            file,
+            buffer_manager,
+            disk_manager,
+        }
     }
 
     pub fn open(
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
         pos: u32,
     ) -> Self {
         let file = File::open(buffer_manager, disk_manager, pos as u32);
-        Self { file }
+        Self {
+            file,
+            buffer_manager,
+            disk_manager,
+        }
+    }
+
+    /// Longest path of an entry, paths are the keys of the index of the table
+    pub const fn max_path_len() -> usize {
+        BTree::<BLOCKSIZE, CAPACITY>::max_key_size()
+    }
+
+    fn index_root_id(&self) -> RecordId {
+        RecordId::new(self.file.head_page_number, 0)
+    }
+
+    fn index(&self) -> BTree<'a, BLOCKSIZE, CAPACITY> {
+        let root = self.file.get(self.index_root_id()).unwrap();
+        let root = u32::from_be_bytes(root[..size_of::<u32>()].try_into().unwrap());
+        BTree::open(self.buffer_manager, self.disk_manager, root)
+    }
+
+    pub fn add_file(
+        &self,
+        name: &str,
+        file_type: FileType,
+        block_number: u32,
+    ) -> Result<(), FileSystemError> {
+        self.add(Entry::new(name, EntryKind::File(file_type), block_number))
     }
 
-    pub fn add_file(&self, name: &str, block_number: u32) {
-        let mut buf = name.as_bytes().to_vec();
-        buf.extend_from_slice(block_number.to_be_bytes().as_ref());
-        self.file.insert(&buf);
+    pub fn add_directory(&self, path: &str) -> Result<(), FileSystemError> {
+        self.add(Entry::new(path, EntryKind::Directory, 0))
+    }
+
+    /// Add `entry` to the table, fail with `DiskFull`, adding nothing,
+    /// if the disk may not have room for it
+    pub(crate) fn add(&self, entry: Entry) -> Result<(), FileSystemError> {
+        let mut index = self.index();
+        if index.find_row_address(entry.path.as_bytes()).is_some() {
+            return Err(FileSystemError::AlreadyExists);
+        }
+        let record = entry.to_bytes();
+        self.check_room(std::slice::from_ref(&record))?;
+        let rid = self.file.insert(&record);
+        index
+            .insert(
+                entry.path.as_bytes(),
+                RowAddress::new(rid.page(), rid.slot()),
+            )
+            .unwrap();
+        Ok(())
+    }
+
+    /// Replace the entry with the same path as `entry`,
+    /// fail with `DiskFull`, leaving the entry as it was, if the disk has no room for it
+    pub fn update(&self, entry: &Entry) -> Result<(), FileSystemError> {
+        let row_address = self
+            .index()
+            .find_row_address(entry.path.as_bytes())
+            .ok_or(FileSystemError::FileNotFound)?;
+        let rid = RecordId::new(row_address.page_number(), row_address.offset());
+        let record = entry.to_bytes();
+        self.check_room(std::slice::from_ref(&record))?;
+        self.file.update(rid, &record).map_err(|e| match e {
+            FileError::DiskFull | FileError::PageFull(_) => FileSystemError::DiskFull,
+            e => panic!("Entry of {} is not readable: {:?}", entry.path, e),
+        })
+    }
+
+    /// File or directory at `path`
+    pub fn search(&self, path: &str) -> Option<Entry> {
+        let row_address = self.index().find_row_address(path.as_bytes())?;
+        Some(self.entry(&row_address))
+    }
+
+    fn entry(&self, row_address: &RowAddress) -> Entry {
+        let rid = RecordId::new(row_address.page_number(), row_address.offset());
+        Entry::from_bytes(&self.file.get(rid).unwrap())
     }
 
     pub fn search_file(&'a self, name: &str) -> Option<u32> {
-        let search_name = name.as_bytes().to_vec();
-        let cursor = self.file.cursor();
-        for cell in cursor {
-            if cell.len() - size_of::<u32>() == search_name.len() {
-                let cell_name = &cell[0..search_name.len()];
-                if cell_name == &search_name {
-                    return Some(u32::from_be_bytes(cell[name.len()..].try_into().unwrap()));
-                }
+        self.search(name)
+            .filter(|entry| matches!(entry.kind, EntryKind::File(_)))
+            .map(|entry| entry.block_number)
+    }
+
+    /// Whether `path` is the root directory or a directory added to the table
+    pub fn is_directory(&self, path: &str) -> bool {
+        path.is_empty()
+            || self
+                .search(path)
+                .is_some_and(|entry| entry.kind == EntryKind::Directory)
+    }
+
+    /// Keys of the entries inside the directory at `path`, at any depth, ordered by path
+    fn inside(&self, path: &str) -> Vec<(Vec<u8>, RowAddress)> {
+        let index = self.index();
+        if path.is_empty() {
+            return index.range(Bound::Unbounded, Bound::Unbounded);
+        }
+        // Every path inside the directory starts with `path/`,
+        // and sorts before `path` followed by the character after the separator
+        let start = format!("{path}{SEPARATOR}");
+        let end = format!("{path}{}", (SEPARATOR as u8 + 1) as char);
+        index.range(
+            Bound::Included(start.as_bytes()),
+            Bound::Excluded(end.as_bytes()),
+        )
+    }
+
+    /// Files and directories directly inside the directory at `path`, ordered by name
+    pub fn list(&self, path: &str) -> Vec<Entry> {
+        self.inside(path)
+            .iter()
+            .filter(|(key, _)| parent(std::str::from_utf8(key).unwrap()) == path)
+            .map(|(_, row_address)| self.entry(row_address))
+            .collect()
+    }
+
+    /// Every file and directory, ordered by path
+    pub fn entries(&self) -> Vec<Entry> {
+        self.inside("")
+            .iter()
+            .map(|(_, row_address)| self.entry(row_address))
+            .collect()
+    }
+
+    /// Give the entry at `old` the path `new`, along with the entries inside it
+    /// if it is a directory. Entries keep their record, only their path changes.
+    /// Every entry is read and checked before the first one is changed,
+    /// so the table is left as it was when the rename fails.
+    pub fn rename(&self, old: &str, new: &str) -> Result<(), FileSystemError> {
+        let mut index = self.index();
+        let row_address = index
+            .find_row_address(old.as_bytes())
+            .ok_or(FileSystemError::FileNotFound)?;
+        let mut renamed = vec![(old.as_bytes().to_vec(), row_address)];
+        renamed.extend(self.inside(old));
+        let modified = timestamp::to_system_time(timestamp::now());
+        let mut staged = Vec::with_capacity(renamed.len());
+        let mut records = Vec::with_capacity(renamed.len());
+        for (path, row_address) in renamed {
+            let mut entry = self.entry(&row_address);
+            entry.path = format!("{new}{}", &entry.path[old.len()..]);
+            entry.modified = modified;
+            if entry.path.len() > Self::max_path_len() {
+                return Err(FileSystemError::InvalidPath);
+            }
+            if index.find_row_address(entry.path.as_bytes()).is_some() {
+                return Err(FileSystemError::AlreadyExists);
             }
+            records.push(entry.to_bytes());
+            staged.push((path, row_address, entry.path));
+        }
+        self.check_room(&records)?;
+
+        for ((path, row_address, new_path), record) in staged.into_iter().zip(records) {
+            let rid = RecordId::new(row_address.page_number(), row_address.offset());
+            self.file
+                .update(rid, &record)
+                .expect("the record was read while staging the rename");
+            index.delete(&path);
+            index
+                .insert(new_path.as_bytes(), row_address)
+                .expect("the new path was checked while staging the rename");
+        }
+        Ok(())
+    }
+
+    /// Fail with `DiskFull` unless the disk has room to write `records` to the table
+    /// and to add as many keys to its index. In the worst case every record moves
+    /// to a new page and to an overflow chain, and every key splits a node
+    /// on every level of the tree and adds a level.
+    fn check_room(&self, records: &[Vec<u8>]) -> Result<(), FileSystemError> {
+        let height = self.index().height() as usize;
+        let blocks: usize = records
+            .iter()
+            .map(|record| 1 + record.len().div_ceil(BLOCKSIZE / 2) + height + 2)
+            .sum();
+        if self.disk_manager.free_blocks() < blocks {
+            return Err(FileSystemError::DiskFull);
         }
-        None
+        Ok(())
     }
 
     pub fn save(&self) {
         self.file.save();
+        self.index().save();
     }
 }
 
@@ -74,19 +398,16 @@ mod tests {
         let disk_manager = DiskManager::init(&disk);
 
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager = BufferManager::init(&memory, &disk);
-            let files_table = FilesTable::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::init(
-                &buffer_manager,
-                &disk_manager,
-            );
+            let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+            let files_table =
+                FilesTable::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager);
             let file = File::init(&disk_manager, &buffer_manager);
             file.insert("test".as_bytes());
             file.insert("test".as_bytes());
             file.insert("test".as_bytes());
-            files_table.add_file("test", 1);
-            files_table.add_file("test2", 2);
-            files_table.add_file("test3", 3);
+            files_table.add_file("test", FileType::Heap, 1).unwrap();
+            files_table.add_file("test2", FileType::Heap, 2).unwrap();
+            files_table.add_file("test3", FileType::Heap, 3).unwrap();
             assert_eq!(files_table.search_file("test"), Some(1));
             assert_eq!(files_table.search_file("test2"), Some(2));
             assert_eq!(files_table.search_file("test3"), Some(3));
@@ -94,13 +415,9 @@ mod tests {
             files_table.save();
         }
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager = BufferManager::init(&memory, &disk);
-            let files_table = FilesTable::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::open(
-                &buffer_manager,
-                &disk_manager,
-                1,
-            );
+            let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+            let files_table =
+                FilesTable::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager, 1);
             assert_eq!(files_table.search_file("test"), Some(1));
             assert_eq!(files_table.search_file("test2"), Some(2));
             assert_eq!(files_table.search_file("test3"), Some(3));
@@ -117,15 +434,12 @@ mod tests {
         let disk_manager = DiskManager::init(&disk);
 
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager = BufferManager::init(&memory, &disk);
-            let files_table = FilesTable::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::init(
-                &buffer_manager,
-                &disk_manager,
-            );
-            files_table.add_file("test", 1);
-            files_table.add_file("test2", 2);
-            files_table.add_file("test3", 3);
+            let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+            let files_table =
+                FilesTable::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager);
+            files_table.add_file("test", FileType::Heap, 1).unwrap();
+            files_table.add_file("test2", FileType::Heap, 2).unwrap();
+            files_table.add_file("test3", FileType::Heap, 3).unwrap();
             assert_eq!(files_table.search_file("test"), Some(1));
             assert_eq!(files_table.search_file("test2"), Some(2));
             assert_eq!(files_table.search_file("test3"), Some(3));
@@ -133,17 +447,107 @@ mod tests {
             files_table.save();
         }
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager = BufferManager::init(&memory, &disk);
-            let files_table = FilesTable::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::open(
-                &buffer_manager,
-                &disk_manager,
-                1,
-            );
+            let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+            let files_table =
+                FilesTable::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager, 1);
             assert_eq!(files_table.search_file("test"), Some(1));
             assert_eq!(files_table.search_file("test2"), Some(2));
             assert_eq!(files_table.search_file("test3"), Some(3));
             assert_eq!(files_table.search_file("test4"), None);
         }
     }
+
+    #[test]
+    fn directories() {
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = 512 * 128;
+        let disk = Disk::<BLOCKSIZE, CAPACITY>::create("files_table::directories").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager = BufferManager::init(16, &disk);
+        let files_table = FilesTable::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager);
+        files_table.add_directory("tenant1").unwrap();
+        files_table.add_directory("tenant1/users").unwrap();
+        files_table
+            .add_file("tenant1/users/table", FileType::Heap, 1)
+            .unwrap();
+        files_table
+            .add_file("tenant1/users/index", FileType::Heap, 2)
+            .unwrap();
+        files_table
+            .add_file("tenant1/orders", FileType::Heap, 3)
+            .unwrap();
+        files_table.add_file("users", FileType::Heap, 4).unwrap();
+
+        assert_eq!(files_table.search_file("tenant1/users/index"), Some(2));
+        assert_eq!(files_table.search_file("users"), Some(4));
+        // Directories are not files
+        assert_eq!(files_table.search_file("tenant1/users"), None);
+        assert!(files_table.is_directory("tenant1/users"));
+        assert!(files_table.is_directory(""));
+        assert!(!files_table.is_directory("tenant1/orders"));
+
+        let names = |path| {
+            files_table
+                .list(path)
+                .iter()
+                .map(|entry| entry.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(""), vec!["tenant1", "users"]);
+        assert_eq!(names("tenant1"), vec!["orders", "users"]);
+        assert_eq!(names("tenant1/users"), vec!["index", "table"]);
+        assert_eq!(names("tenant1/orders"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn paths() {
+        assert!(is_valid_path("tenant/table"));
+        assert!(!is_valid_path(""));
+        assert!(!is_valid_path("/table"));
+        assert!(!is_valid_path("tenant//table"));
+        assert!(!is_valid_path("tenant/"));
+        assert_eq!(parent("tenant/users/table"), "tenant/users");
+        assert_eq!(parent("table"), "");
+    }
+
+    #[test]
+    fn many_entries() {
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = 512 * 1024;
+        let disk = Disk::<BLOCKSIZE, CAPACITY>::create("files_table::many_entries").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let path = |i: u32| format!("tenant{}/table{}", i % 10, i);
+
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let files_table =
+                FilesTable::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager);
+            for i in 0..10 {
+                files_table.add_directory(&format!("tenant{i}")).unwrap();
+            }
+            for i in 0..3000 {
+                files_table.add_file(&path(i), FileType::Heap, i).unwrap();
+            }
+            assert!(matches!(
+                files_table.add_file(&path(42), FileType::Heap, 0),
+                Err(FileSystemError::AlreadyExists)
+            ));
+            files_table.save();
+        }
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let files_table =
+                FilesTable::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager, 1);
+            assert_eq!(files_table.search_file(&path(2999)), Some(2999));
+            // The lookup reads a few pages of the tree, not every page of the table
+            assert!(buffer_manager.metrics().misses < 10);
+
+            for i in (0..3000).step_by(7) {
+                assert_eq!(files_table.search_file(&path(i)), Some(i));
+            }
+            assert_eq!(files_table.search_file("tenant1/table2"), None);
+            assert_eq!(files_table.list("tenant3").len(), 300);
+            assert_eq!(files_table.list("").len(), 10);
+        }
+    }
 }
@@ -1,76 +1,430 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::SystemTime,
+};
+
+use btree_index::btree::BTree;
 use buffer_manager::BufferManager;
 use disk_manager::DiskManager;
-use files_table::FilesTable;
+use files_table::{Entry, EntryKind, FileType, FilesTable};
 use unordered_file::File;
 
 pub mod btree_index;
 pub mod files_table;
+mod timestamp;
 pub mod unordered_file;
 
-pub struct FileSystem<
-    'a,
-    const BLOCKSIZE: usize,
-    const CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
-> {
-    files_table: FilesTable<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
-    buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+/// Files are named by paths such as `tenant/table`, every directory of a path
+/// must be created with `create_directory` before anything is created in it.
+/// The buffer manager given to `init` or `open` holds the files table,
+/// and is used for every file which is not assigned to a named pool.
+/// Named pools let files share memory only with the files assigned to the same pool,
+/// so a bulk load in one pool can not evict the pages of another.
+pub struct FileSystem<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    files_table: FilesTable<'a, BLOCKSIZE, CAPACITY>,
+    buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+    pools: HashMap<String, &'a BufferManager<BLOCKSIZE, CAPACITY>>,
     disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
 }
 
 #[derive(Debug)]
 pub enum FileSystemError {
     FileNotFound,
+    PoolNotFound,
     DiskFull,
+    /// A path is empty, starts or ends with the separator, holds an empty name,
+    /// or is longer than `FilesTable::max_path_len`
+    InvalidPath,
+    /// The directory a path would be created in does not exist
+    DirectoryNotFound,
+    /// A file or a directory already exists at the path
+    AlreadyExists,
+    /// The file is not of the type it is opened as, such as an index opened as a heap file
+    WrongFileType,
+}
+
+/// What `FileSystem::stat` tells about a file or a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: EntryKind,
+    pub created: SystemTime,
+    /// Last change to the content of the file or to its properties.
+    /// Changes to an index are not tracked, only those to its properties.
+    pub modified: SystemTime,
+    /// Pages of a heap file, without the overflow pages of large records, or nodes of an index
+    pub page_count: u64,
+    /// Records of a heap file, keys of an index, or entries of a directory
+    pub row_count: u64,
+    pub properties: BTreeMap<String, String>,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    FileSystem<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> FileSystem<'a, BLOCKSIZE, CAPACITY> {
     pub fn init(
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
     ) -> std::io::Result<Self> {
         let files_table = FilesTable::init(&buffer_manager, &disk_manager);
         Ok(Self {
             files_table,
             buffer_manager,
+            pools: HashMap::new(),
             disk_manager,
         })
     }
 
     pub fn open(
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
     ) -> std::io::Result<Self> {
         let files_table = FilesTable::open(&buffer_manager, &disk_manager, 1);
         Ok(Self {
             files_table,
             buffer_manager,
+            pools: HashMap::new(),
             disk_manager,
         })
     }
 
+    /// Register a buffer pool under `name`, replacing any pool with the same name.
+    /// A file must always be opened in the same pool,
+    /// otherwise two pools could hold diverging copies of its pages.
+    pub fn add_pool(&mut self, name: &str, buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>) {
+        self.pools.insert(name.to_string(), buffer_manager);
+    }
+
+    /// Buffer pool registered under `name`, for structures such as a `BTree`
+    /// which are not managed through the files table
+    pub fn pool(
+        &self,
+        name: &str,
+    ) -> Result<&'a BufferManager<BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.pools
+            .get(name)
+            .copied()
+            .ok_or(FileSystemError::PoolNotFound)
+    }
+
+    /// Create an empty directory at `path`, inside an existing directory
+    pub fn create_directory(&self, path: &str) -> Result<(), FileSystemError> {
+        self.check_new_path(path)?;
+        self.files_table.add_directory(path)?;
+        self.save_files_table();
+        Ok(())
+    }
+
+    /// Files and directories directly inside the directory at `path`, `""` for the root
+    pub fn list_directory(&self, path: &str) -> Result<Vec<Entry>, FileSystemError> {
+        if !self.files_table.is_directory(path) {
+            return Err(FileSystemError::DirectoryNotFound);
+        }
+        Ok(self.files_table.list(path))
+    }
+
+    /// Whether a file or a directory exists at `path`
+    pub fn exists(&self, path: &str) -> bool {
+        self.files_table.search(path).is_some()
+    }
+
+    /// Every file, in every directory, ordered by path
+    pub fn list_files(&self) -> Vec<Entry> {
+        self.files_table
+            .entries()
+            .into_iter()
+            .filter(|entry| entry.kind != EntryKind::Directory)
+            .collect()
+    }
+
+    fn check_new_path(&self, path: &str) -> Result<(), FileSystemError> {
+        if !files_table::is_valid_path(path)
+            || path.len() > FilesTable::<BLOCKSIZE, CAPACITY>::max_path_len()
+        {
+            return Err(FileSystemError::InvalidPath);
+        }
+        if !self.files_table.is_directory(files_table::parent(path)) {
+            return Err(FileSystemError::DirectoryNotFound);
+        }
+        if self.files_table.search(path).is_some() {
+            return Err(FileSystemError::AlreadyExists);
+        }
+        Ok(())
+    }
+
     pub fn create_file(
         &'a self,
         name: &str,
-    ) -> Result<File<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>, FileSystemError> {
-        let file = File::init(&self.disk_manager, &self.buffer_manager);
-        self.files_table.add_file(name, file.head_page_number);
+    ) -> Result<File<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.create_file_with(name, None, FileType::Heap)
+    }
+
+    /// Same as `create_file`, the file is marked as holding the schema of a table
+    pub fn create_schema_file(
+        &'a self,
+        name: &str,
+    ) -> Result<File<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.create_file_with(name, None, FileType::Schema)
+    }
+
+    /// Same as `create_file`, but the pages of the file are cached in the pool named `pool`.
+    /// The pool is recorded in the entry of the file.
+    pub fn create_file_in(
+        &'a self,
+        name: &str,
+        pool: &str,
+    ) -> Result<File<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.create_file_with(name, Some(pool), FileType::Heap)
+    }
+
+    fn create_file_with(
+        &'a self,
+        name: &str,
+        pool: Option<&str>,
+        file_type: FileType,
+    ) -> Result<File<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        let buffer_manager = match pool {
+            Some(pool) => self.pool(pool)?,
+            None => self.buffer_manager,
+        };
+        self.check_new_path(name)?;
+        let file = File::init(self.disk_manager, buffer_manager);
+        let mut entry = Entry::new(name, EntryKind::File(file_type), file.head_page_number);
+        entry.pool = pool.map(str::to_string);
+        self.files_table.add(entry)?;
         self.save_files_table();
         Ok(file)
     }
 
+    /// Create an empty `BTree` index named `name`, cached in the default pool
+    pub fn create_index(
+        &self,
+        name: &str,
+    ) -> Result<BTree<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.create_index_with(name, None)
+    }
+
+    /// Same as `create_index`, but the nodes of the index are cached in the pool named `pool`.
+    /// The pool is recorded in the entry of the index.
+    pub fn create_index_in(
+        &self,
+        name: &str,
+        pool: &str,
+    ) -> Result<BTree<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.create_index_with(name, Some(pool))
+    }
+
+    fn create_index_with(
+        &self,
+        name: &str,
+        pool: Option<&str>,
+    ) -> Result<BTree<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        let buffer_manager = match pool {
+            Some(pool) => self.pool(pool)?,
+            None => self.buffer_manager,
+        };
+        self.check_new_path(name)?;
+        let index = BTree::init(buffer_manager, self.disk_manager);
+        let mut entry = Entry::new(name, EntryKind::File(FileType::Index), index.root());
+        entry.pool = pool.map(str::to_string);
+        self.files_table.add(entry)?;
+        self.save_files_table();
+        Ok(index)
+    }
+
+    pub fn open_index(
+        &self,
+        name: &str,
+    ) -> Result<BTree<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.open_index_with(name, self.buffer_manager)
+    }
+
+    /// Same as `open_index`, but the nodes of the index are cached in the pool named `pool`
+    pub fn open_index_in(
+        &self,
+        name: &str,
+        pool: &str,
+    ) -> Result<BTree<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.open_index_with(name, self.pool(pool)?)
+    }
+
+    fn open_index_with(
+        &self,
+        name: &str,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+    ) -> Result<BTree<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        let entry = self.file_entry(name)?;
+        if entry.kind != EntryKind::File(FileType::Index) {
+            return Err(FileSystemError::WrongFileType);
+        }
+        Ok(BTree::open(
+            buffer_manager,
+            self.disk_manager,
+            entry.block_number,
+        ))
+    }
+
+    /// Buffer pool the pages of the file of `entry` are cached in.
+    /// A file whose pool is not registered is not cached in any other pool than the default one.
+    fn entry_pool(&self, entry: &Entry) -> &'a BufferManager<BLOCKSIZE, CAPACITY> {
+        entry
+            .pool
+            .as_deref()
+            .and_then(|pool| self.pool(pool).ok())
+            .unwrap_or(self.buffer_manager)
+    }
+
+    fn file_entry(&self, name: &str) -> Result<Entry, FileSystemError> {
+        self.files_table
+            .search(name)
+            .filter(|entry| entry.kind != EntryKind::Directory)
+            .ok_or(FileSystemError::FileNotFound)
+    }
+
     pub fn open_file(
         &'a self,
         name: &str,
-    ) -> Result<File<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>, FileSystemError> {
-        let num = self
+    ) -> Result<File<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.open_file_with(name, self.buffer_manager)
+    }
+
+    /// Same as `open_file`, but the pages of the file are cached in the pool named `pool`
+    pub fn open_file_in(
+        &'a self,
+        name: &str,
+        pool: &str,
+    ) -> Result<File<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        self.open_file_with(name, self.pool(pool)?)
+    }
+
+    fn open_file_with(
+        &'a self,
+        name: &str,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+    ) -> Result<File<'a, BLOCKSIZE, CAPACITY>, FileSystemError> {
+        let entry = self.file_entry(name)?;
+        if entry.kind == EntryKind::File(FileType::Index) {
+            return Err(FileSystemError::WrongFileType);
+        }
+        let file = File::open(buffer_manager, self.disk_manager, entry.block_number);
+        Ok(file)
+    }
+
+    /// Move the file or directory at `old` to `new`, a directory is moved with what it holds.
+    /// Files keep their pages, so files and indexes opened before are still valid.
+    pub fn rename_file(&self, old: &str, new: &str) -> Result<(), FileSystemError> {
+        if !self.exists(old) {
+            return Err(FileSystemError::FileNotFound);
+        }
+        self.check_new_path(new)?;
+        // A directory can not be moved inside itself
+        if new.starts_with(&format!("{old}{}", files_table::SEPARATOR)) {
+            return Err(FileSystemError::InvalidPath);
+        }
+        self.files_table.rename(old, new)?;
+        self.save_files_table();
+        Ok(())
+    }
+
+    /// Create the file `dst` holding a copy of the records or of the keys of the file `src`,
+    /// with the same type, properties and pool. Records get new ids in a copied heap file,
+    /// while a copied index maps its keys to the same row addresses as `src`.
+    pub fn copy_file(&self, src: &str, dst: &str) -> Result<(), FileSystemError> {
+        let entry = self.file_entry(src)?;
+        self.check_new_path(dst)?;
+        let EntryKind::File(file_type) = entry.kind else {
+            unreachable!()
+        };
+        // The source is read through the pool it is cached in, and the copy is cached there too
+        let pool = self.entry_pool(&entry);
+        let mut copy = Entry::new(dst, entry.kind, 0);
+        copy.properties = entry.properties;
+        copy.pool = entry.pool;
+        // The copy is given back to the disk manager if it can not be added to the table
+        if file_type == FileType::Index {
+            let source = BTree::open(pool, self.disk_manager, entry.block_number);
+            let index = source.copy().map_err(|_| FileSystemError::DiskFull)?;
+            copy.block_number = index.root();
+            if let Err(e) = self.files_table.add(copy) {
+                index.free();
+                return Err(e);
+            }
+            index.save();
+        } else {
+            let source = File::open(pool, self.disk_manager, entry.block_number);
+            let file = source.copy().map_err(|_| FileSystemError::DiskFull)?;
+            copy.block_number = file.head_page_number;
+            if let Err(e) = self.files_table.add(copy) {
+                file.free();
+                return Err(e);
+            }
+            file.save();
+        }
+        self.save_files_table();
+        Ok(())
+    }
+
+    /// Metadata of the file or directory at `path`.
+    /// Counts are read from the file through the pool it is cached in.
+    pub fn stat(&self, path: &str) -> Result<Metadata, FileSystemError> {
+        let entry = self
             .files_table
-            .search_file(name)
+            .search(path)
             .ok_or(FileSystemError::FileNotFound)?;
-        let file = File::open(&self.buffer_manager, &self.disk_manager, num);
-        Ok(file)
+        let (page_count, row_count, modified) = match entry.kind {
+            EntryKind::Directory => (0, self.files_table.list(path).len() as u64, entry.modified),
+            EntryKind::File(FileType::Index) => {
+                let index = BTree::open(
+                    self.entry_pool(&entry),
+                    self.disk_manager,
+                    entry.block_number,
+                );
+                (index.page_count(), index.key_count(), entry.modified)
+            }
+            EntryKind::File(_) => {
+                let file = File::open(
+                    self.entry_pool(&entry),
+                    self.disk_manager,
+                    entry.block_number,
+                );
+                let modified = entry.modified.max(file.modified());
+                (file.page_count(), file.record_count(), modified)
+            }
+        };
+        Ok(Metadata {
+            kind: entry.kind,
+            created: entry.created,
+            modified,
+            page_count,
+            row_count,
+            properties: entry.properties,
+        })
+    }
+
+    /// Set the property `key` of the file or directory at `path`
+    pub fn set_property(&self, path: &str, key: &str, value: &str) -> Result<(), FileSystemError> {
+        self.change_entry(path, |entry| {
+            entry.properties.insert(key.to_string(), value.to_string());
+        })
+    }
+
+    pub fn remove_property(&self, path: &str, key: &str) -> Result<(), FileSystemError> {
+        self.change_entry(path, |entry| {
+            entry.properties.remove(key);
+        })
+    }
+
+    fn change_entry(
+        &self,
+        path: &str,
+        change: impl FnOnce(&mut Entry),
+    ) -> Result<(), FileSystemError> {
+        let mut entry = self
+            .files_table
+            .search(path)
+            .ok_or(FileSystemError::FileNotFound)?;
+        change(&mut entry);
+        entry.modified = SystemTime::now();
+        self.files_table.update(&entry)?;
+        self.save_files_table();
+        Ok(())
     }
 
     pub fn save_files_table(&self) {
@@ -95,25 +449,583 @@ mod tests {
         let disk_manager = DiskManager::init(&disk);
 
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager = BufferManager::init(&memory, &disk);
-            let file_system = FileSystem::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::init(
-                &buffer_manager,
-                &disk_manager,
-            )
-            .unwrap();
+            let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
             let _file1 = file_system.create_file("file1").unwrap();
             file_system.save_files_table()
         }
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager = BufferManager::init(&memory, &disk);
-            let file_system = FileSystem::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::open(
-                &buffer_manager,
-                &disk_manager,
-            )
-            .unwrap();
+            let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager).unwrap();
             let _file1 = file_system.open_file("file1").unwrap();
         }
     }
+
+    #[test]
+    fn named_pools() {
+        use crate::{btree_index::btree::RowAddress, FileSystemError};
+        use disk::Disk;
+
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = BLOCKSIZE * 512;
+        let disk = Disk::create("named_pools").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+
+        {
+            let catalog = BufferManager::init(4, &disk);
+            let heap = BufferManager::init(8, &disk);
+            let mut file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::init(&catalog, &disk_manager).unwrap();
+            file_system.add_pool("heap", &heap);
+            assert!(matches!(
+                file_system.create_file_in("file1", "missing"),
+                Err(FileSystemError::PoolNotFound)
+            ));
+            let file = file_system.create_file_in("file1", "heap").unwrap();
+            let catalog_pages = catalog.snapshot().len();
+            for i in 0..200 {
+                file.insert(&[i as u8; 100]);
+            }
+            // The bulk load only went through the heap pool
+            assert_eq!(catalog.snapshot().len(), catalog_pages);
+            assert_eq!(heap.snapshot().len(), 8);
+            assert!(heap.metrics().evictions > 0);
+            assert_eq!(catalog.metrics().evictions, 0);
+            // Counts are read through the pool of the file, before it is saved
+            let stat = file_system.stat("file1").unwrap();
+            assert_eq!(stat.row_count, 200);
+            assert_eq!(stat.page_count, file.page_count());
+            assert_eq!(file_system.list_files()[0].pool.as_deref(), Some("heap"));
+            // The copy reads the records the heap pool holds, and is cached there too
+            file_system.copy_file("file1", "file2").unwrap();
+            let copy = file_system.open_file_in("file2", "heap").unwrap();
+            assert_eq!(copy.cursor().count(), 200);
+            assert_eq!(file_system.list_files()[1].pool.as_deref(), Some("heap"));
+
+            assert!(matches!(
+                file_system.create_index_in("index1", "missing"),
+                Err(FileSystemError::PoolNotFound)
+            ));
+            let mut index = file_system.create_index_in("index1", "heap").unwrap();
+            let catalog_pages = catalog.snapshot().len();
+            for i in 0..100u32 {
+                index
+                    .insert(format!("{i:050}").as_bytes(), RowAddress::new(i, 0))
+                    .unwrap();
+            }
+            // The nodes of the index only went through the heap pool
+            assert_eq!(catalog.snapshot().len(), catalog_pages);
+            assert_eq!(file_system.stat("index1").unwrap().row_count, 100);
+            index.save();
+        }
+        {
+            let catalog = BufferManager::init(4, &disk);
+            let heap = BufferManager::init(8, &disk);
+            let mut file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::open(&catalog, &disk_manager).unwrap();
+            file_system.add_pool("heap", &heap);
+            assert!(file_system.pool("heap").is_ok());
+            let file = file_system.open_file_in("file1", "heap").unwrap();
+            for (i, record) in file.cursor().enumerate() {
+                assert_eq!(record, [i as u8; 100]);
+            }
+            let index = file_system.open_index_in("index1", "heap").unwrap();
+            assert_eq!(
+                index.find_row_address(format!("{:050}", 42).as_bytes()),
+                Some(RowAddress::new(42, 0))
+            );
+            assert!(matches!(
+                file_system.open_index_in("file1", "heap"),
+                Err(FileSystemError::WrongFileType)
+            ));
+        }
+    }
+
+    #[test]
+    fn directories() {
+        use crate::{
+            files_table::{EntryKind, FileType},
+            FileSystemError,
+        };
+        use disk::Disk;
+
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = BLOCKSIZE * 512;
+        let disk = Disk::create("directories").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
+            assert!(matches!(
+                file_system.create_file("tenant1/users"),
+                Err(FileSystemError::DirectoryNotFound)
+            ));
+            file_system.create_directory("tenant1").unwrap();
+            file_system.create_directory("tenant2").unwrap();
+            assert!(matches!(
+                file_system.create_directory("tenant1"),
+                Err(FileSystemError::AlreadyExists)
+            ));
+            for path in ["", "/users", "tenant1/", "tenant1//users"] {
+                assert!(matches!(
+                    file_system.create_file(path),
+                    Err(FileSystemError::InvalidPath)
+                ));
+            }
+            file_system
+                .create_file("tenant1/users")
+                .unwrap()
+                .insert(&[1; 10]);
+            file_system
+                .create_file("tenant2/users")
+                .unwrap()
+                .insert(&[2; 10]);
+            // Files can not hold anything
+            assert!(matches!(
+                file_system.create_file("tenant1/users/index"),
+                Err(FileSystemError::DirectoryNotFound)
+            ));
+        }
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager).unwrap();
+            // Each tenant has its own table
+            let file = file_system.open_file("tenant2/users").unwrap();
+            assert_eq!(file.cursor().collect::<Vec<_>>(), vec![vec![2; 10]]);
+            assert!(matches!(
+                file_system.open_file("users"),
+                Err(FileSystemError::FileNotFound)
+            ));
+
+            let root = file_system.list_directory("").unwrap();
+            assert_eq!(
+                root.iter().map(|entry| entry.name()).collect::<Vec<_>>(),
+                vec!["tenant1", "tenant2"]
+            );
+            assert!(root.iter().all(|entry| entry.kind == EntryKind::Directory));
+            let tenant = file_system.list_directory("tenant1").unwrap();
+            assert_eq!(tenant.len(), 1);
+            assert_eq!(tenant[0].path, "tenant1/users");
+            assert_eq!(tenant[0].kind, EntryKind::File(FileType::Heap));
+            assert!(matches!(
+                file_system.list_directory("tenant1/users"),
+                Err(FileSystemError::DirectoryNotFound)
+            ));
+        }
+    }
+
+    #[test]
+    fn unique_names() {
+        use crate::FileSystemError;
+        use disk::Disk;
+
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = BLOCKSIZE * 512;
+        let disk = Disk::create("unique_names").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager = BufferManager::init(16, &disk);
+        let file_system =
+            FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
+        file_system.create_file("users").unwrap().insert(&[1; 10]);
+        file_system.create_directory("tenant").unwrap();
+        let free_page = disk_manager.allocate().unwrap();
+        disk_manager.deallocate(free_page).unwrap();
+
+        for path in ["users", "tenant"] {
+            assert!(matches!(
+                file_system.create_file(path),
+                Err(FileSystemError::AlreadyExists)
+            ));
+            assert!(matches!(
+                file_system.create_directory(path),
+                Err(FileSystemError::AlreadyExists)
+            ));
+        }
+        // No page was allocated for the rejected files
+        assert_eq!(disk_manager.allocate().unwrap(), free_page);
+        let file = file_system.open_file("users").unwrap();
+        assert_eq!(file.cursor().collect::<Vec<_>>(), vec![vec![1; 10]]);
+    }
+
+    #[test]
+    fn path_length() {
+        use crate::{files_table::FilesTable, FileSystemError};
+        use disk::Disk;
+
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = BLOCKSIZE * 512;
+        let disk = Disk::create("path_length").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager = BufferManager::init(16, &disk);
+        let file_system =
+            FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
+        let max = FilesTable::<BLOCKSIZE, CAPACITY>::max_path_len();
+        let too_long = "a".repeat(max + 1);
+
+        assert!(matches!(
+            file_system.create_file(&too_long),
+            Err(FileSystemError::InvalidPath)
+        ));
+        assert!(matches!(
+            file_system.create_directory(&too_long),
+            Err(FileSystemError::InvalidPath)
+        ));
+        file_system.create_file("users").unwrap().insert(&[1; 10]);
+        assert!(matches!(
+            file_system.rename_file("users", &too_long),
+            Err(FileSystemError::InvalidPath)
+        ));
+        assert!(matches!(
+            file_system.copy_file("users", &too_long),
+            Err(FileSystemError::InvalidPath)
+        ));
+        // The paths inside a renamed directory grow with the name of the directory
+        file_system.create_directory("d").unwrap();
+        file_system
+            .create_file(&format!("d/{}", "b".repeat(max - 2)))
+            .unwrap();
+        assert!(matches!(
+            file_system.rename_file("d", "dd"),
+            Err(FileSystemError::InvalidPath)
+        ));
+        assert!(file_system.exists("d"));
+        assert!(!file_system.exists("dd"));
+
+        // Paths of the maximum length split the nodes of the files table
+        for i in 0..64u8 {
+            let path = format!("{}{i:03}", "c".repeat(max - 3));
+            file_system.create_file(&path).unwrap();
+        }
+        assert_eq!(file_system.list_files().len(), 66);
+        assert_eq!(
+            file_system
+                .open_file("users")
+                .unwrap()
+                .cursor()
+                .collect::<Vec<_>>(),
+            vec![vec![1; 10]]
+        );
+    }
+
+    #[test]
+    fn stat() {
+        use crate::{
+            btree_index::btree::RowAddress,
+            files_table::{EntryKind, FileType},
+            FileSystemError,
+        };
+        use disk::Disk;
+        use std::time::SystemTime;
+
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = BLOCKSIZE * 512;
+        let disk = Disk::create("stat").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        // Times are stored in milliseconds
+        let start = SystemTime::now() - std::time::Duration::from_millis(1);
+
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
+            file_system.create_directory("tenant").unwrap();
+            let file = file_system.create_file("tenant/users").unwrap();
+            for i in 0..20 {
+                file.insert(&[i; 100]);
+            }
+            file_system
+                .create_schema_file("tenant/users_schema")
+                .unwrap();
+            let mut index = file_system.create_index("tenant/users_index").unwrap();
+            for i in 0..300u32 {
+                let key = format!("{:050}", i);
+                index.insert(key.as_bytes(), RowAddress::new(i, 0)).unwrap();
+            }
+            index.save();
+            file_system
+                .set_property("tenant/users", "owner", "alice")
+                .unwrap();
+            file_system
+                .set_property("tenant/users", "comment", "to remove")
+                .unwrap();
+            file_system
+                .remove_property("tenant/users", "comment")
+                .unwrap();
+            file.save();
+        }
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager).unwrap();
+            let users = file_system.stat("tenant/users").unwrap();
+            assert_eq!(users.kind, EntryKind::File(FileType::Heap));
+            assert_eq!(users.row_count, 20);
+            assert!(users.page_count > 1);
+            assert!(users.created >= start);
+            assert!(users.modified >= users.created);
+            assert_eq!(users.properties.len(), 1);
+            assert_eq!(users.properties["owner"], "alice");
+
+            let schema = file_system.stat("tenant/users_schema").unwrap();
+            assert_eq!(schema.kind, EntryKind::File(FileType::Schema));
+            assert_eq!((schema.page_count, schema.row_count), (1, 0));
+            assert!(file_system.open_file("tenant/users_schema").is_ok());
+
+            // The index is found again after its root was split
+            let index = file_system.stat("tenant/users_index").unwrap();
+            assert_eq!(index.kind, EntryKind::File(FileType::Index));
+            assert_eq!(index.row_count, 300);
+            assert!(index.page_count > 1);
+            let btree = file_system.open_index("tenant/users_index").unwrap();
+            let key = format!("{:050}", 123);
+            assert_eq!(
+                btree
+                    .find_row_address(key.as_bytes())
+                    .unwrap()
+                    .page_number(),
+                123
+            );
+            assert!(matches!(
+                file_system.open_file("tenant/users_index"),
+                Err(FileSystemError::WrongFileType)
+            ));
+            assert!(matches!(
+                file_system.open_index("tenant/users"),
+                Err(FileSystemError::WrongFileType)
+            ));
+
+            let tenant = file_system.stat("tenant").unwrap();
+            assert_eq!(tenant.kind, EntryKind::Directory);
+            assert_eq!(tenant.row_count, 3);
+            assert!(matches!(
+                file_system.stat("tenant/orders"),
+                Err(FileSystemError::FileNotFound)
+            ));
+
+            // Changing the records marks the file as modified
+            let file = file_system.open_file("tenant/users").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            file.insert(&[0xff; 10]);
+            let after = file_system.stat("tenant/users").unwrap();
+            assert!(after.modified > users.modified);
+            assert_eq!(after.row_count, 21);
+        }
+    }
+
+    #[test]
+    fn rename_copy() {
+        use crate::{btree_index::btree::RowAddress, FileSystemError};
+        use disk::Disk;
+
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = BLOCKSIZE * 512;
+        let disk = Disk::create("rename_copy").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
+            file_system.create_directory("tenant").unwrap();
+            file_system.create_directory("tenant/archive").unwrap();
+            let file = file_system.create_file("tenant/users").unwrap();
+            for i in 0..50 {
+                file.insert(&[i; 100]);
+            }
+            file_system
+                .create_file("tenant/archive/orders")
+                .unwrap()
+                .insert(&[7; 10]);
+            let mut index = file_system.create_index("tenant/users_index").unwrap();
+            for i in 0..300u32 {
+                let key = format!("{:050}", i);
+                index.insert(key.as_bytes(), RowAddress::new(i, 0)).unwrap();
+            }
+            index.save();
+            file_system
+                .set_property("tenant/users", "owner", "alice")
+                .unwrap();
+
+            file_system.copy_file("tenant/users", "users_copy").unwrap();
+            file_system
+                .copy_file("tenant/users_index", "index_copy")
+                .unwrap();
+            assert!(matches!(
+                file_system.copy_file("tenant", "tenant_copy"),
+                Err(FileSystemError::FileNotFound)
+            ));
+            assert!(matches!(
+                file_system.copy_file("tenant/users", "users_copy"),
+                Err(FileSystemError::AlreadyExists)
+            ));
+            // The copy is independent of its source
+            file.insert(&[0xff; 10]);
+
+            file_system.rename_file("tenant", "customer").unwrap();
+            assert!(matches!(
+                file_system.rename_file("customer", "customer/archive/old"),
+                Err(FileSystemError::InvalidPath)
+            ));
+            assert!(matches!(
+                file_system.rename_file("tenant", "other"),
+                Err(FileSystemError::FileNotFound)
+            ));
+            assert!(matches!(
+                file_system.rename_file("users_copy", "index_copy"),
+                Err(FileSystemError::AlreadyExists)
+            ));
+            // A file opened before the rename is still valid
+            file.insert(&[0xfe; 10]);
+        }
+        {
+            let buffer_manager = BufferManager::init(16, &disk);
+            let file_system =
+                FileSystem::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager).unwrap();
+            assert!(!file_system.exists("tenant"));
+            assert!(!file_system.exists("tenant/users"));
+            assert!(file_system.exists("customer"));
+            assert!(file_system.exists("customer/archive"));
+            assert_eq!(
+                file_system
+                    .list_files()
+                    .iter()
+                    .map(|entry| entry.path.as_str())
+                    .collect::<Vec<_>>(),
+                vec![
+                    "customer/archive/orders",
+                    "customer/users",
+                    "customer/users_index",
+                    "index_copy",
+                    "users_copy"
+                ]
+            );
+
+            let users = file_system.open_file("customer/users").unwrap();
+            assert_eq!(users.cursor().count(), 52);
+            let orders = file_system.open_file("customer/archive/orders").unwrap();
+            assert_eq!(orders.cursor().collect::<Vec<_>>(), vec![vec![7; 10]]);
+
+            let copy = file_system.open_file("users_copy").unwrap();
+            assert_eq!(
+                copy.cursor().collect::<Vec<_>>(),
+                (0..50).map(|i| vec![i; 100]).collect::<Vec<_>>()
+            );
+            let stat = file_system.stat("users_copy").unwrap();
+            assert_eq!(stat.properties["owner"], "alice");
+
+            let index = file_system.open_index("customer/users_index").unwrap();
+            let index_copy = file_system.open_index("index_copy").unwrap();
+            assert_ne!(index_copy.root(), index.root());
+            assert_eq!(index_copy.key_count(), 300);
+            for i in 0..300u32 {
+                let key = format!("{:050}", i);
+                assert_eq!(
+                    index_copy.find_row_address(key.as_bytes()),
+                    Some(RowAddress::new(i, 0))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rename_copy_on_full_disk() {
+        use crate::{btree_index::btree::RowAddress, FileSystemError};
+        use disk::Disk;
+
+        const BLOCKSIZE: usize = 512;
+        const CAPACITY: usize = BLOCKSIZE * 512;
+        let disk = Disk::create("rename_copy_on_full_disk").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager = BufferManager::init(16, &disk);
+        let file_system =
+            FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
+        file_system.create_directory("tenant").unwrap();
+        let file = file_system.create_file("tenant/users").unwrap();
+        for i in 0..50 {
+            file.insert(&[i; 100]);
+        }
+        file.insert(&[0xab; 2000]);
+        let mut index = file_system.create_index("tenant/users_index").unwrap();
+        for i in 0..300u32 {
+            let key = format!("{:050}", i);
+            index.insert(key.as_bytes(), RowAddress::new(i, 0)).unwrap();
+        }
+        let paths = || {
+            file_system
+                .list_files()
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect::<Vec<_>>()
+        };
+        let before = paths();
+
+        // Leave room for a part of the copies only
+        let free_blocks = disk_manager.free_blocks();
+        let taken = disk_manager.allocate_many(free_blocks - 5).unwrap();
+        for (src, dst) in [
+            ("tenant/users", "users_copy"),
+            ("tenant/users_index", "index_copy"),
+        ] {
+            assert!(matches!(
+                file_system.copy_file(src, dst),
+                Err(FileSystemError::DiskFull)
+            ));
+            // What was copied is given back
+            assert_eq!(disk_manager.free_blocks(), 5);
+            assert!(!file_system.exists(dst));
+        }
+        assert!(matches!(
+            file_system.rename_file("tenant", "a_directory_with_a_much_longer_name"),
+            Err(FileSystemError::DiskFull)
+        ));
+        assert_eq!(paths(), before);
+        assert_eq!(file_system.stat("tenant/users").unwrap().row_count, 51);
+        assert!(matches!(
+            file_system.set_property("tenant/users", "comment", &"a".repeat(1000)),
+            Err(FileSystemError::DiskFull)
+        ));
+        assert!(file_system
+            .stat("tenant/users")
+            .unwrap()
+            .properties
+            .is_empty());
+        assert_eq!(disk_manager.free_blocks(), 5);
+
+        for block in taken {
+            disk_manager.deallocate(block).unwrap();
+        }
+        let free_blocks = disk_manager.free_blocks();
+        file_system.copy_file("tenant/users", "users_copy").unwrap();
+        let copy_blocks = free_blocks - disk_manager.free_blocks();
+
+        // The copy fits but its entry does not
+        let taken = disk_manager
+            .allocate_many(disk_manager.free_blocks() - copy_blocks)
+            .unwrap();
+        assert!(matches!(
+            file_system.copy_file("tenant/users", "users_copy2"),
+            Err(FileSystemError::DiskFull)
+        ));
+        assert_eq!(disk_manager.free_blocks(), copy_blocks);
+        assert!(!file_system.exists("users_copy2"));
+        for block in taken {
+            disk_manager.deallocate(block).unwrap();
+        }
+        file_system
+            .rename_file("tenant", "a_directory_with_a_much_longer_name")
+            .unwrap();
+        assert_eq!(
+            paths(),
+            vec![
+                "a_directory_with_a_much_longer_name/users",
+                "a_directory_with_a_much_longer_name/users_index",
+                "users_copy"
+            ]
+        );
+    }
 }
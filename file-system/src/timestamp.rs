@@ -0,0 +1,17 @@
+//! Times are stored as milliseconds since the Unix epoch
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub fn now() -> u64 {
+    to_millis(SystemTime::now())
+}
+
+pub fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+pub fn to_system_time(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
@@ -0,0 +1,273 @@
+//! Appending records one `File::insert` at a time reads the head page and the tail page
+//! and rewrites the file header for every record. A `BulkWriter` keeps the tail page
+//! pinned while it fills it, allocates new pages in batches
+//! and only writes the file header once, when it is finished.
+
+use buffer_manager::PageLatch;
+use disk_manager::DiskManagerError;
+
+use super::{cell, node::Node, overflow, File, OverflowStub, RecordId};
+use crate::timestamp;
+
+/// Number of pages taken from the disk manager at once
+const ALLOCATION_BATCH: usize = 16;
+
+/// Append records at the end of a `File`.
+/// The writer holds the latch of the file until it is finished, and changes to the file
+/// made by other threads wait for it. A change made by the same thread while the writer
+/// is alive would wait for itself, it panics instead.
+pub struct BulkWriter<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    file: File<'a, BLOCKSIZE, CAPACITY>,
+    /// Always `Some` until the writer is finished
+    tail: Option<Node<'a, BLOCKSIZE, CAPACITY>>,
+    tail_page_number: u32,
+    /// Pages allocated in advance, the next one to use is last
+    free_pages: Vec<u32>,
+    inserted: u64,
+    /// Pages added to the file
+    new_pages: u32,
+    _latch: PageLatch<'a>,
+}
+
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> BulkWriter<'a, BLOCKSIZE, CAPACITY> {
+    pub fn new(file: &File<'a, BLOCKSIZE, CAPACITY>) -> Self {
+        let file = File { ..*file };
+        let latch = file.latch();
+        let tail_page_number = file.node(file.head_page_number).tail_page();
+        Self {
+            tail: Some(file.node(tail_page_number)),
+            file,
+            tail_page_number,
+            free_pages: Vec::new(),
+            inserted: 0,
+            new_pages: 0,
+            _latch: latch,
+        }
+    }
+
+    fn tail(&mut self) -> &mut Node<'a, BLOCKSIZE, CAPACITY> {
+        self.tail.as_mut().unwrap()
+    }
+
+    /// Take a page allocated in advance. Once the disk does not have room
+    /// for a whole batch anymore, the blocks left are allocated one by one.
+    fn new_page(&mut self) -> Result<u32, DiskManagerError> {
+        if self.free_pages.is_empty() {
+            let disk_manager = self.file.disk_manager;
+            let mut pages = match disk_manager.allocate_many(ALLOCATION_BATCH) {
+                Ok(pages) => pages,
+                Err(DiskManagerError::DiskFull) => vec![disk_manager.allocate()?],
+                Err(e) => return Err(e),
+            };
+            pages.reverse();
+            self.free_pages = pages;
+        }
+        Ok(self.free_pages.pop().unwrap())
+    }
+
+    /// Append a record and return its id.
+    /// Fail with `DiskFull`, leaving the file as it was, if the disk has no room for it.
+    pub fn insert(&mut self, payload: &[u8]) -> Result<RecordId, DiskManagerError> {
+        let (payload, flags) = match self.file.stored_form(payload) {
+            // The overflow chain of the record may need the pages allocated in advance
+            Err(DiskManagerError::DiskFull) if !self.free_pages.is_empty() => {
+                self.give_back_pages();
+                self.file.stored_form(payload)?
+            }
+            result => result?,
+        };
+        if let Some(slot) = self.tail().insert(&payload, flags) {
+            self.inserted += 1;
+            return Ok(RecordId::new(self.tail_page_number, slot));
+        }
+
+        let new_page_number = match self.new_page() {
+            Ok(page_number) => page_number,
+            Err(e) => {
+                if flags & cell::OVERFLOW != 0 {
+                    let stub = OverflowStub::read_from(&payload);
+                    overflow::free(self.file.disk_manager, self.file.buffer_manager, &stub);
+                }
+                return Err(e);
+            }
+        };
+        let mut new_tail = self.file.new_node(new_page_number);
+        new_tail.set_prev(self.tail_page_number);
+        let slot = new_tail.insert(&payload, flags).unwrap();
+        self.tail().set_next(new_page_number);
+        // The previous tail is unpinned here
+        self.tail = Some(new_tail);
+        self.tail_page_number = new_page_number;
+        self.new_pages += 1;
+        self.inserted += 1;
+        Ok(RecordId::new(new_page_number, slot))
+    }
+
+    /// Write the file header and give back the pages allocated in advance.
+    /// Dropping the writer finishes it as well.
+    pub fn finish(mut self) {
+        self.write_header();
+    }
+
+    fn write_header(&mut self) {
+        // The tail may be the head page
+        if self.tail.take().is_none() {
+            return;
+        }
+        let mut head = self.file.node(self.file.head_page_number);
+        head.set_tail(self.tail_page_number);
+        head.set_cell_count(head.cell_count() + self.inserted);
+        head.set_page_count(head.page_count() + self.new_pages);
+        if self.inserted > 0 {
+            head.set_modified(timestamp::now());
+        }
+        self.give_back_pages();
+    }
+
+    fn give_back_pages(&mut self) {
+        for page_number in self.free_pages.drain(..) {
+            self.file.disk_manager.deallocate(page_number).unwrap();
+        }
+    }
+}
+
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> Drop
+    for BulkWriter<'a, BLOCKSIZE, CAPACITY>
+{
+    fn drop(&mut self) {
+        self.write_header();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buffer_manager::BufferManager;
+    use disk::Disk;
+    use disk_manager::{DiskManager, DiskManagerError};
+
+    use crate::unordered_file::File;
+
+    #[test]
+    fn insert_many() {
+        let disk = Disk::<512, 65536>::create("bulk_writer::insert_many").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        file.insert(&[0xff; 100]);
+        let records: Vec<Vec<u8>> = (0..50)
+            .map(|i| vec![i as u8; 50 + i % 3 * 100])
+            .chain([vec![0xab; 2000]])
+            .collect();
+        let rids = file
+            .insert_many(records.iter().map(|record| record.as_slice()))
+            .unwrap();
+
+        for (rid, record) in rids.iter().zip(&records) {
+            assert_eq!(&file.get(*rid).unwrap(), record);
+        }
+        let mut expected = vec![vec![0xff; 100]];
+        expected.extend(records.clone());
+        assert_eq!(file.cursor().collect::<Vec<_>>(), expected);
+        // The file header is up to date, records are appended after the bulk ones
+        let rid = file.insert(&[0xee; 100]);
+        assert!(rid.page() >= rids.last().unwrap().page());
+        assert_eq!(file.cursor().last().unwrap(), vec![0xee; 100]);
+        assert_eq!(file.node(file.head_page_number).cell_count(), 53);
+
+        // Pages allocated in advance and not used are given back
+        let mut writer = file.bulk_writer();
+        let mut last_page = 0;
+        for _ in 0..10 {
+            last_page = writer.insert(&[0xcd; 200]).unwrap().page();
+        }
+        writer.finish();
+        assert_eq!(disk_manager.allocate().unwrap(), last_page + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "already latched by this thread")]
+    fn change_file_during_bulk_write() {
+        let disk =
+            Disk::<512, 65536>::create("bulk_writer::change_file_during_bulk_write").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let rid = file.insert(&[1; 100]);
+        let _writer = file.bulk_writer();
+        file.delete(rid).unwrap();
+    }
+
+    #[test]
+    fn insert_on_almost_full_disk() {
+        let disk = Disk::<512, 65536>::create("bulk_writer::insert_on_almost_full_disk").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        // Leave less free blocks than an allocation batch
+        let mut taken = Vec::new();
+        while let Ok(block) = disk_manager.allocate() {
+            taken.push(block);
+        }
+        for block in taken.drain(..3) {
+            disk_manager.deallocate(block).unwrap();
+        }
+
+        let mut writer = file.bulk_writer();
+        let mut inserted = 0;
+        let error = loop {
+            match writer.insert(&[0xcd; 200]) {
+                Ok(_) => inserted += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(error, DiskManagerError::DiskFull));
+        // The head page and the 3 free blocks are filled
+        assert_eq!(inserted, 8);
+        // A record going to an overflow chain does not leak it
+        assert!(matches!(
+            writer.insert(&[0xab; 2000]),
+            Err(DiskManagerError::DiskFull)
+        ));
+        writer.finish();
+        assert_eq!(file.cursor().count(), inserted);
+        assert_eq!(
+            file.node(file.head_page_number).cell_count(),
+            inserted as u64
+        );
+        assert_eq!(file.page_count(), 4);
+        assert!(matches!(
+            disk_manager.allocate(),
+            Err(DiskManagerError::DiskFull)
+        ));
+        // insert_many reports the record which does not fit, instead of panicking
+        let records = [vec![0xef; 10], vec![0xab; 2000]];
+        assert!(matches!(
+            file.insert_many(records.iter().map(|record| record.as_slice())),
+            Err(DiskManagerError::DiskFull)
+        ));
+        assert_eq!(file.cursor().count(), inserted + 1);
+    }
+
+    #[test]
+    fn overflow_chain_takes_pages_allocated_in_advance() {
+        let disk = Disk::<512, 65536>::create("bulk_writer::overflow_chain_takes_pages").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let mut taken = Vec::new();
+        while let Ok(block) = disk_manager.allocate() {
+            taken.push(block);
+        }
+        for block in taken.drain(..18) {
+            disk_manager.deallocate(block).unwrap();
+        }
+
+        let mut writer = file.bulk_writer();
+        // Fill the head page, the next page comes with a batch allocated in advance
+        // and leaves too few free blocks for an overflow chain
+        while writer.insert(&[0xcd; 200]).unwrap().page() == file.head_page_number {}
+        let rid = writer.insert(&[0xab; 2000]).unwrap();
+        writer.finish();
+        assert_eq!(file.get(rid).unwrap(), vec![0xab; 2000]);
+    }
+}
@@ -1,6 +1,16 @@
 use self::header::header_size;
 
-use super::node::InsertResult;
+/// The payload is an `OverflowStub` pointing to the actual record
+pub const OVERFLOW: u8 = 1 << 1;
+/// The record was moved, the payload is the `RecordId` of its new place
+pub const FORWARD: u8 = 1 << 2;
+/// The record was moved here from the slot holding the forwarding pointer,
+/// it is only reached through that slot
+pub const MOVED: u8 = 1 << 3;
+
+/// Every cell has room for at least this much payload,
+/// so any record can be replaced in place by a forwarding pointer
+const MIN_PAYLOAD_SIZE: usize = 8;
 
 mod header {
     use std::mem::size_of;
@@ -8,135 +18,101 @@ mod header {
     /// (<offset>, <size>)
     pub const PAYLOAD_SIZE: (usize, usize) = (0, size_of::<u32>());
     /// (<offset>, <size>)
-    pub const DELETE_FLAG: (usize, usize) = (PAYLOAD_SIZE.0 + PAYLOAD_SIZE.1, size_of::<bool>());
+    pub const FLAGS: (usize, usize) = (PAYLOAD_SIZE.0 + PAYLOAD_SIZE.1, size_of::<u8>());
 
     pub const fn header_size() -> usize {
-        PAYLOAD_SIZE.1 + DELETE_FLAG.1
+        PAYLOAD_SIZE.1 + FLAGS.1
     }
 }
 
-pub fn insert_cell<'a>(page: &mut [u8], at: usize, payload: &'a [u8]) -> InsertResult<'a> {
-    let start = at as usize;
-    let end = start + header_size() + payload.len();
-
-    if start + header_size() > page.len() {
-        // Not enough space to store the cell's header
-        // This cell should be stored in a new page
-        return InsertResult::OutOfSpace(payload);
-    }
-
-    page[start + header::PAYLOAD_SIZE.0..start + header::PAYLOAD_SIZE.0 + header::PAYLOAD_SIZE.1]
-        .copy_from_slice(&(payload.len() as u32).to_be_bytes());
-    if end < page.len() {
-        // record can be inserted in a single page
-        page[start + header_size()..end].copy_from_slice(payload);
-        return InsertResult::Normal(end);
-    } else {
-        // record cannot be inserted in a single page and should be spilled
-        let kept_size = page.len() - (start + header_size());
-        let page_size = page.len();
-        page[start + header_size()..page_size]
-            .copy_from_slice(payload[..kept_size as usize].as_ref());
-        return InsertResult::Spill(payload, kept_size);
+/// Size taken by a cell holding `payload_size` bytes
+pub const fn cell_size(payload_size: usize) -> usize {
+    if payload_size < MIN_PAYLOAD_SIZE {
+        return header_size() + MIN_PAYLOAD_SIZE;
     }
+    header_size() + payload_size
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum PayloadReadResult<'a> {
-    InPage {
-        payload: &'a [u8],
-    },
-    InOverflow {
-        initial_payload: &'a [u8],
-        remain: usize,
-    },
+/// Write a cell holding `payload` at `at`, the page must have room for it.
+/// `flags` is a combination of `OVERFLOW`, `FORWARD` and `MOVED`.
+pub fn write_cell(page: &mut [u8], at: usize, payload: &[u8], flags: u8) {
+    let start = at + header::PAYLOAD_SIZE.0;
+    page[start..start + header::PAYLOAD_SIZE.1]
+        .copy_from_slice(&(payload.len() as u32).to_be_bytes());
+    page[at + header::FLAGS.0] = flags;
+    page[at + header_size()..at + header_size() + payload.len()].copy_from_slice(payload);
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Cell<'a>(&'a [u8]);
 
 impl<'a> Cell<'a> {
-    /// ### Safety: Slice should be at correct position so that
-    /// header can be read correctly
-    pub unsafe fn new(at: usize, page: &'a [u8]) -> Option<Self> {
+    /// Read the cell starting at `at` in `page`.
+    ///
+    /// # Safety
+    ///
+    /// `at` must be the offset of a well-formed cell within `page`: its header is read
+    /// from there, and the payload size it holds must not reach past the end of `page`.
+    pub unsafe fn new(at: usize, page: &'a [u8]) -> Self {
         let start = at + header::PAYLOAD_SIZE.0;
-        let payload_size = u32::from_be_bytes([
-            page[start],
-            page[start + 1],
-            page[start + 2],
-            page[start + 3],
-        ]) as usize;
-        if payload_size == 0 {
-            return None;
-        }
-        if start + header_size() > page.len() {
-            panic!()
-        }
-        let end = start + header_size() + payload_size;
-        let end = if end > page.len() { page.len() } else { end };
-        Some(Self(&page[start..end]))
+        let payload_size = u32::from_be_bytes(
+            page[start..start + header::PAYLOAD_SIZE.1]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        Self(&page[at..at + header_size() + payload_size])
     }
 
-    pub fn is_delete(&self) -> bool {
-        self.0[header::DELETE_FLAG.0] == 1
+    pub fn flags(&self) -> u8 {
+        self.0[header::FLAGS.0]
     }
 
-    pub fn cell_size(&self) -> usize {
-        self.0.len()
+    pub fn is_overflow(&self) -> bool {
+        self.flags() & OVERFLOW != 0
     }
 
-    pub fn payload_size(&self) -> usize {
-        let start = header::PAYLOAD_SIZE.0;
-        u32::from_be_bytes([
-            self.0[start],
-            self.0[start + 1],
-            self.0[start + 2],
-            self.0[start + 3],
-        ]) as usize
+    pub fn is_forward(&self) -> bool {
+        self.flags() & FORWARD != 0
     }
 
-    pub fn in_cell_payload_size(&self) -> usize {
-        self.cell_size() - header_size()
+    pub fn is_moved(&self) -> bool {
+        self.flags() & MOVED != 0
     }
 
-    pub fn header_size() -> usize {
-        header::header_size()
+    /// Space taken by the cell in its page
+    pub fn cell_size(&self) -> usize {
+        cell_size(self.payload_size())
     }
 
-    pub fn payload(&self) -> PayloadReadResult<'a> {
-        if self.0.len() < self.payload_size() + header_size() {
-            return PayloadReadResult::InOverflow {
-                initial_payload: &self.0[header_size()..],
-                remain: self.payload_size() - (self.0.len() - header_size()),
-            };
-        }
-        return PayloadReadResult::InPage {
-            payload: &self.0[header_size()..],
-        };
+    pub fn payload_size(&self) -> usize {
+        self.0.len() - header_size()
     }
-}
-
-#[derive(Debug, PartialEq)]
-pub struct CellMut<'a>(&'a mut [u8]);
 
-impl<'a> CellMut<'a> {
-    /// ### Safety: Slice should be at correct position so that
-    /// header can be read correctly
-    pub unsafe fn new(at: usize, page: &'a mut [u8]) -> Self {
-        let start = at + header::PAYLOAD_SIZE.0;
-        let payload_size =
-            u32::from_be_bytes([page[at], page[at + 1], page[at + 2], page[at + 3]]) as usize;
-        let end = start + header_size() + payload_size;
-        let end = if end > page.len() { page.len() } else { end };
-        Self(&mut page[start..end])
+    pub fn header_size() -> usize {
+        header::header_size()
     }
 
-    pub fn is_delete(&self) -> bool {
-        Cell(self.0).is_delete()
+    pub fn payload(&self) -> &'a [u8] {
+        &self.0[header_size()..]
     }
+}
 
-    pub fn set_delete(&mut self, flag: bool) {
-        let start = header::DELETE_FLAG.0;
-        self.0[start] = flag as u8;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read() {
+        let mut page = [0u8; 64];
+        write_cell(&mut page, 10, &[1, 2, 3], 0);
+        write_cell(&mut page, 30, &[4; 8], OVERFLOW | MOVED);
+        let cell = unsafe { Cell::new(10, &page) };
+        assert_eq!(cell.payload(), &[1, 2, 3]);
+        // Small cells keep room for a forwarding pointer
+        assert_eq!(cell.cell_size(), cell_size(MIN_PAYLOAD_SIZE));
+        assert!(!cell.is_overflow());
+        let cell = unsafe { Cell::new(30, &page) };
+        assert_eq!(cell.payload(), &[4; 8]);
+        assert!(cell.is_overflow() && cell.is_moved() && !cell.is_forward());
     }
 }
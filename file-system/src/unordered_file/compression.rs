@@ -0,0 +1,148 @@
+//! Pages of a compressed file, except its head, hold a slotted page `FACTOR` times
+//! larger than a block, compressed with LZ4. A page is full, or sealed, once its content
+//! would not fit in the block anymore, so repetitive records take fewer blocks.
+//!
+//! The block starts with a header, followed by the compressed content.
+//! Records inserted since the page was last compressed are appended after it as they are,
+//! the page is only compressed again once they take all the room left.
+//! Each of them is accounted for the most it could take once compressed,
+//! so compressing them along with the rest of the page always fits.
+
+use std::mem::size_of;
+
+/// Size of the uncompressed content of a page, in blocks
+pub const FACTOR: usize = 4;
+
+/// Room kept free in the block when records are added,
+/// records rewritten in place may use part of it
+pub const SLACK: usize = 32;
+
+/// Part of `SLACK` always kept free,
+/// so small changes such as linking the page to another one always fit
+pub const LINK_SLACK: usize = 16;
+
+/// (<offset>, <size>)
+const COMPRESSED_SIZE: (usize, usize) = (0, size_of::<u32>());
+/// Size of the records appended after the compressed content
+const APPENDED_SIZE: (usize, usize) = (COMPRESSED_SIZE.0 + COMPRESSED_SIZE.1, size_of::<u32>());
+/// Room the content of the block may take once compressed again
+const RESERVED_SIZE: (usize, usize) = (APPENDED_SIZE.0 + APPENDED_SIZE.1, size_of::<u32>());
+const HEADER_SIZE: usize = RESERVED_SIZE.0 + RESERVED_SIZE.1;
+
+/// An appended record starts with its flags and the size of its payload
+const ENTRY_HEADER_SIZE: usize = size_of::<u8>() + size_of::<u32>();
+
+pub const fn image_size<const BLOCKSIZE: usize>() -> usize {
+    BLOCKSIZE * FACTOR
+}
+
+fn read_field(page: &[u8], (offset, size): (usize, usize)) -> usize {
+    u32::from_be_bytes(page[offset..offset + size].try_into().unwrap()) as usize
+}
+
+fn write_field(page: &mut [u8], (offset, size): (usize, usize), value: usize) {
+    page[offset..offset + size].copy_from_slice(&(value as u32).to_be_bytes());
+}
+
+/// Compress `image` into `page`, keeping `reserve` bytes of the page free.
+/// Records appended to the page are dropped, `image` must include them.
+/// Return false, leaving the page untouched, if it does not fit.
+pub fn store(image: &[u8], page: &mut [u8], reserve: usize) -> bool {
+    let available = page.len() - HEADER_SIZE - reserve;
+    let mut buffer = vec![0; lz4_flex::block::get_maximum_output_size(image.len())];
+    let len = lz4_flex::block::compress_into(image, &mut buffer).unwrap();
+    if len > available {
+        return false;
+    }
+    write_field(page, COMPRESSED_SIZE, len);
+    write_field(page, APPENDED_SIZE, 0);
+    write_field(page, RESERVED_SIZE, len);
+    page[HEADER_SIZE..HEADER_SIZE + len].copy_from_slice(&buffer[..len]);
+    true
+}
+
+/// Append a record inserted in the content of a page without compressing it again,
+/// keeping `reserve` bytes of the page free.
+/// `growth` is how much the record made the content grow, slot included.
+/// Return false, leaving the page untouched, if it does not fit.
+pub fn append(page: &mut [u8], payload: &[u8], flags: u8, growth: usize, reserve: usize) -> bool {
+    let reserved = read_field(page, RESERVED_SIZE)
+        + lz4_flex::block::get_maximum_output_size(growth.max(ENTRY_HEADER_SIZE + payload.len()));
+    if reserved > page.len() - HEADER_SIZE - reserve {
+        return false;
+    }
+    let appended = read_field(page, APPENDED_SIZE);
+    let start = HEADER_SIZE + read_field(page, COMPRESSED_SIZE) + appended;
+    page[start] = flags;
+    write_field(page, (start + 1, size_of::<u32>()), payload.len());
+    page[start + ENTRY_HEADER_SIZE..start + ENTRY_HEADER_SIZE + payload.len()]
+        .copy_from_slice(payload);
+    write_field(
+        page,
+        APPENDED_SIZE,
+        appended + ENTRY_HEADER_SIZE + payload.len(),
+    );
+    write_field(page, RESERVED_SIZE, reserved);
+    true
+}
+
+/// Uncompressed content of a page written by `store`, without the appended records
+pub fn load<const BLOCKSIZE: usize>(page: &[u8]) -> Vec<u8> {
+    let len = read_field(page, COMPRESSED_SIZE);
+    let mut image = vec![0; image_size::<BLOCKSIZE>()];
+    lz4_flex::block::decompress_into(&page[HEADER_SIZE..HEADER_SIZE + len], &mut image).unwrap();
+    image
+}
+
+/// Records appended to a page by `append`, as (<flags>, <payload>), in order
+pub fn appended(page: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut start = HEADER_SIZE + read_field(page, COMPRESSED_SIZE);
+    let end = start + read_field(page, APPENDED_SIZE);
+    let mut records = Vec::new();
+    while start < end {
+        let len = read_field(page, (start + 1, size_of::<u32>()));
+        let payload = &page[start + ENTRY_HEADER_SIZE..start + ENTRY_HEADER_SIZE + len];
+        records.push((page[start], payload));
+        start += ENTRY_HEADER_SIZE + len;
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_load() {
+        let mut page = [0u8; 512];
+        let image: Vec<u8> = (0..image_size::<512>()).map(|i| (i / 100) as u8).collect();
+        assert!(store(&image, &mut page, SLACK));
+        assert_eq!(load::<512>(&page), image);
+
+        // Content which does not compress well does not fit
+        let noise: Vec<u8> = (0..image_size::<512>()).map(|_| rand::random()).collect();
+        let before = page;
+        assert!(!store(&noise, &mut page, SLACK));
+        assert_eq!(page, before);
+    }
+
+    #[test]
+    fn append_records() {
+        let mut page = [0u8; 512];
+        let image = vec![0; image_size::<512>()];
+        assert!(store(&image, &mut page, SLACK));
+        assert!(append(&mut page, &[1; 100], 3, 110, SLACK));
+        assert!(append(&mut page, &[2; 50], 0, 60, SLACK));
+        assert_eq!(appended(&page), vec![(3, &[1; 100][..]), (0, &[2; 50][..])]);
+        assert_eq!(load::<512>(&page), image);
+
+        // Records are accounted for the most they could take once compressed
+        let before = page;
+        assert!(!append(&mut page, &[3; 250], 0, 260, SLACK));
+        assert_eq!(page, before);
+        // Compressing the page again makes room for them
+        assert!(store(&image, &mut page, SLACK));
+        assert!(appended(&page).is_empty());
+        assert!(append(&mut page, &[3; 250], 0, 260, SLACK));
+    }
+}
@@ -1,127 +1,196 @@
+use std::cell::RefCell;
+
 use buffer_manager::BufferManager;
+use disk_manager::DiskManager;
 
 use super::{
-    cell::{Cell, PayloadReadResult},
-    header::{FileHeader, FileNodeHeader},
-    node::Node,
+    node::{DecodedPage, Node},
+    overflow::{self, OverflowStub},
+    File, FileError, RecordId,
 };
 
-pub struct Cursor<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> {
-    head_number: std::cell::Cell<u32>,
-    block_number: std::cell::Cell<u32>,
-    offset: std::cell::Cell<usize>,
-    cell_count: u64,
-    at_head: std::cell::Cell<bool>,
-    cur_cell: std::cell::Cell<u64>,
-    buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+/// Walk the records of a `File` in both directions.
+/// The cursor sits before a record: `next` returns that record and moves past it,
+/// `prev` moves back over the previous record and returns it.
+pub struct Cursor<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    head_number: u32,
+    /// 0 once the cursor went past the last page
+    block_number: u32,
+    slot: u32,
+    started: bool,
+    file: File<'a, BLOCKSIZE, CAPACITY>,
+    /// Content of the current page of a compressed file,
+    /// so it is only uncompressed again if the page changes
+    decoded: RefCell<Option<DecodedPage>>,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> Iterator
-    for Cursor<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> Iterator
+    for Cursor<'a, BLOCKSIZE, CAPACITY>
 {
     type Item = Vec<u8>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.cur_cell.set(self.cur_cell.get() + 1);
-        if self.cur_cell.get() > self.cell_count {
-            return None;
+        if !self.started {
+            self.started = true;
+            self.prefetch_next();
         }
         self.skip_delete();
-        let cell = { self.read() };
+        let cell = self.read();
         self.advance();
         cell
     }
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    Cursor<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> Cursor<'a, BLOCKSIZE, CAPACITY> {
     pub fn new(
-        cell_count: u64,
         head_block_number: u32,
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
     ) -> Self {
         Self {
-            head_number: std::cell::Cell::new(head_block_number),
-            cur_cell: std::cell::Cell::new(0),
-            cell_count,
-            block_number: std::cell::Cell::new(head_block_number),
-            offset: std::cell::Cell::new(FileNodeHeader::size() + FileHeader::size()),
-            at_head: std::cell::Cell::new(true),
-            buffer_manager,
+            head_number: head_block_number,
+            block_number: head_block_number,
+            slot: 0,
+            started: false,
+            file: File::open(buffer_manager, disk_manager, head_block_number),
+            decoded: RefCell::new(None),
+        }
+    }
+
+    fn node(&self) -> Option<Node<'a, BLOCKSIZE, CAPACITY>> {
+        if self.block_number == 0 {
+            return None;
         }
+        Some(
+            self.file
+                .node_cached(self.block_number, &mut self.decoded.borrow_mut()),
+        )
     }
 
     pub fn read(&self) -> Option<Vec<u8>> {
-        let page = self.buffer_manager.get_page(self.block_number.get());
-        let node: Node<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-            Node::from_page(self.at_head.get(), page);
-        let rs = unsafe { node.read_record_at(self.offset.get()) }?;
-        match rs.payload() {
-            PayloadReadResult::InPage { payload } => Some(payload.to_vec()),
-            PayloadReadResult::InOverflow {
-                initial_payload,
-                remain,
-            } => {
-                let mut payload = initial_payload.to_vec();
-                let next_block = node.next().unwrap();
-                let page = self.buffer_manager.get_page(next_block);
-                let node: Node<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> = Node::from_page(false, page);
-                let remain = node.read_partial_record(remain);
-                payload.extend(remain);
-                Some(payload)
+        let (payload, overflow) = self.read_cell()?;
+        if overflow {
+            let stub = OverflowStub::read_from(&payload);
+            return Some(overflow::read(self.file.buffer_manager, &stub));
+        }
+        Some(payload)
+    }
+
+    /// Read the payload of the current cell without following overflow chains
+    fn read_cell(&self) -> Option<(Vec<u8>, bool)> {
+        let node = self.node()?;
+        let cell = node.cell(self.slot).filter(|cell| !cell.is_moved())?;
+        if cell.is_forward() {
+            let target = RecordId::read_from(cell.payload());
+            let node = self.file.node(target.page());
+            let cell = node.cell(target.slot()).unwrap();
+            return Some((cell.payload().to_vec(), cell.is_overflow()));
+        }
+        Some((cell.payload().to_vec(), cell.is_overflow()))
+    }
+
+    fn record_id(&self) -> RecordId {
+        RecordId::new(self.block_number, self.slot)
+    }
+
+    /// Id of the record `next` would return, `None` at the end of the file
+    pub fn position(&mut self) -> Option<RecordId> {
+        self.skip_delete();
+        if self.block_number == 0 {
+            return None;
+        }
+        Some(self.record_id())
+    }
+
+    /// Move before the record of `rid`, so that `next` returns it.
+    /// `rid` should belong to the file of the cursor.
+    pub fn seek(&mut self, rid: RecordId) -> Result<(), FileError> {
+        self.file.resolve(rid)?;
+        self.block_number = rid.page();
+        self.slot = rid.slot();
+        self.prefetch_next();
+        Ok(())
+    }
+
+    /// Go back to the first record of the file
+    pub fn restart(&mut self) {
+        self.block_number = self.head_number;
+        self.slot = 0;
+        self.started = false;
+    }
+
+    /// Whether the record of a slot is read at this slot.
+    /// Moved records are read at the slot pointing to them.
+    fn is_live(node: &Node<'a, BLOCKSIZE, CAPACITY>, slot: u32) -> bool {
+        node.cell(slot).is_some_and(|cell| !cell.is_moved())
+    }
+
+    /// Move to the first record at or after the current position which is not deleted
+    pub fn skip_delete(&mut self) {
+        while let Some(node) = self.node() {
+            let slot_count = node.slot_count();
+            while self.slot < slot_count {
+                if Self::is_live(&node, self.slot) {
+                    return;
+                }
+                self.slot += 1;
             }
+            self.block_number = node.next().unwrap_or(0);
+            self.slot = 0;
+            drop(node);
+            self.prefetch_next();
+        }
+    }
+
+    pub fn advance(&mut self) {
+        if self.block_number != 0 {
+            self.slot += 1;
         }
     }
 
-    pub fn skip_delete(&self) {
+    /// Move back over the previous record and return it,
+    /// `None` if the cursor is before the first record
+    pub fn prev(&mut self) -> Option<Vec<u8>> {
+        if self.block_number == 0 {
+            // Past the end, start again from the last slot of the last page
+            self.block_number = self.file.node(self.head_number).tail_page();
+            self.slot = self.node().unwrap().slot_count();
+        }
         loop {
-            let page = self.buffer_manager.get_page(self.block_number.get());
-            let node: Node<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-                Node::from_page(self.at_head.get(), page);
-            let rs = unsafe { node.read_record_at(self.offset.get()) }.unwrap();
-            if !rs.is_delete() {
-                return;
+            let node = self.node().unwrap();
+            if self.slot == 0 {
+                self.block_number = node.prev()?;
+                drop(node);
+                self.slot = self.node().unwrap().slot_count();
+                self.prefetch_prev();
+                continue;
             }
-            self.advance();
-        }
-    }
-
-    pub fn advance(&self) {
-        let page = self.buffer_manager.get_page(self.block_number.get());
-        let cell = unsafe { Cell::new(self.offset.get(), &page) };
-        let cell = match cell {
-            Some(cell) => cell,
-            None => return,
-        };
-        let next_offset = self.offset.get() + cell.payload_size() + Cell::header_size();
-        if next_offset <= BLOCKSIZE - Cell::header_size() {
-            self.offset.set(next_offset);
-        } else if next_offset >= BLOCKSIZE - Cell::header_size() && next_offset < BLOCKSIZE {
-            let page_header = FileNodeHeader::read_from(self.at_head.get(), page.as_ref());
-            self.block_number.set(page_header.next);
-            self.offset.set(FileNodeHeader::size());
-            self.at_head.set(false);
-        } else {
-            let page_header = FileNodeHeader::read_from(self.at_head.get(), page.as_ref());
-            self.block_number.set(page_header.next);
-            if self.block_number.get() == 0 {
-                panic!("No next page");
+            self.slot -= 1;
+            if Self::is_live(&node, self.slot) {
+                drop(node);
+                return self.read();
             }
-            self.offset
-                .set(next_offset - BLOCKSIZE + FileNodeHeader::size());
-            self.at_head.set(false);
         }
     }
 
-    pub fn delete(&self) {
-        let page = self.buffer_manager.get_page(self.block_number.get());
-        let mut node = Node::from_page(self.at_head.get(), page);
-        unsafe {
-            node.delete_record_at(self.offset.get());
+    /// Ask the buffer manager to load the block after the current one,
+    /// so it is ready by the time the cursor gets there
+    fn prefetch_next(&self) {
+        if let Some(next) = self.node().and_then(|node| node.next()) {
+            self.file.buffer_manager.prefetch(&[next]);
+        }
+    }
+
+    /// Same as `prefetch_next`, for cursors moving backwards
+    fn prefetch_prev(&self) {
+        if let Some(prev) = self.node().and_then(|node| node.prev()) {
+            self.file.buffer_manager.prefetch(&[prev]);
         }
-        let head_page = self.buffer_manager.get_page(self.head_number.get());
-        let mut head = Node::from_page(true, head_page);
-        head.set_cell_count(head.cell_count() - 1);
+    }
+
+    /// Delete the record at the current position, skipping records already deleted
+    pub fn delete(&mut self) {
+        self.skip_delete();
+        self.file.delete(self.record_id()).unwrap();
     }
 }
 
@@ -129,7 +198,7 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
 mod tests {
     use disk::Disk;
 
-    use crate::unordered_file::File;
+    use crate::unordered_file::{File, FileError};
     use buffer_manager::BufferManager;
     use disk_manager::DiskManager;
 
@@ -138,15 +207,14 @@ mod tests {
         let disk = Disk::<512, 65536>::create("cursor::basic_insert_delete").unwrap();
         let disk_manager = DiskManager::init(&disk);
         const MEMORY_SIZE: usize = 512 * 16;
-        let memory = vec![0; MEMORY_SIZE];
-        let buffer_manager: BufferManager<512, 65536, MEMORY_SIZE> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<512, 65536> =
+            BufferManager::init(MEMORY_SIZE / 512, &disk);
         let file = File::init(&disk_manager, &buffer_manager);
         let records = vec![[0x2; 51].to_vec(), [0x3; 200].to_vec(), [0x4; 412].to_vec()];
         file.insert(&records[0]);
         file.insert(&records[1]);
         file.insert(&records[2]);
-        let cursor = file.cursor();
+        let mut cursor = file.cursor();
         cursor.advance();
         cursor.delete();
         let cursor = file.cursor();
@@ -154,13 +222,13 @@ mod tests {
         assert_eq!(iter.next().unwrap(), records[0]);
         assert_eq!(iter.next().unwrap(), records[2]);
 
-        let cursor = file.cursor();
+        let mut cursor = file.cursor();
         cursor.delete();
         let cursor = file.cursor();
         let mut iter = cursor.into_iter();
         assert_eq!(iter.next().unwrap(), records[2]);
 
-        let cursor = file.cursor();
+        let mut cursor = file.cursor();
         cursor.delete();
         let cursor = file.cursor();
         let mut iter = cursor.into_iter();
@@ -172,9 +240,8 @@ mod tests {
         let disk = Disk::<512, 65536>::create("basic_cursor_test").unwrap();
         let disk_manager = DiskManager::init(&disk);
         const MEMORY_SIZE: usize = 512 * 16;
-        let memory = vec![0; MEMORY_SIZE];
-        let buffer_manager: BufferManager<512, 65536, MEMORY_SIZE> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<512, 65536> =
+            BufferManager::init(MEMORY_SIZE / 512, &disk);
         let file = File::init(&disk_manager, &buffer_manager);
         let records = vec![
             [0x2; 51].to_vec(),
@@ -186,11 +253,89 @@ mod tests {
             [0x1; 17].to_vec(),
         ];
         for record in records.clone() {
-            file.insert(&record)
+            file.insert(&record);
         }
 
         for (i, record) in file.cursor().enumerate() {
             assert_eq!(records[i], record);
         }
     }
+
+    #[test]
+    fn prefetch_next_block() {
+        let disk = Disk::<512, 65536>::create("cursor::prefetch_next_block").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        for i in 0..20 {
+            file.insert(&[i as u8; 100]);
+        }
+        let blocks: Vec<_> = buffer_manager
+            .snapshot()
+            .iter()
+            .map(|page| page.page_number)
+            .collect();
+        buffer_manager.flush().unwrap();
+        for block in blocks {
+            buffer_manager.discard_page(block).unwrap();
+        }
+
+        let mut cursor = file.cursor();
+        assert_eq!(cursor.next().unwrap(), [0u8; 100]);
+        // The second block is requested as soon as the cursor reads the first one
+        let start = std::time::Instant::now();
+        while buffer_manager.snapshot().len() < 2 {
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            std::thread::yield_now();
+        }
+        for (i, record) in cursor.enumerate() {
+            assert_eq!(record, [i as u8 + 1; 100]);
+        }
+    }
+
+    #[test]
+    fn seek_prev_restart() {
+        let disk = Disk::<512, 65536>::create("cursor::seek_prev_restart").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let rids: Vec<_> = (0..20).map(|i| file.insert(&[i as u8; 100])).collect();
+        file.delete(rids[0]).unwrap();
+        file.delete(rids[7]).unwrap();
+        file.delete(rids[8]).unwrap();
+        file.delete(rids[19]).unwrap();
+        // Moved records are seen once, at their id
+        file.update(rids[5], &[5; 250]).unwrap();
+        let mut expected: Vec<_> = (1..19)
+            .filter(|i| *i != 7 && *i != 8)
+            .map(|i| vec![i as u8; 100])
+            .collect();
+        expected[4] = vec![5; 250];
+
+        let mut cursor = file.cursor();
+        assert_eq!(cursor.prev(), None);
+        assert_eq!(cursor.position(), Some(rids[1]));
+        assert_eq!(cursor.by_ref().collect::<Vec<_>>(), expected);
+        assert_eq!(cursor.position(), None);
+        // Walking back from the end gives the records in reverse order
+        let mut backwards = Vec::new();
+        while let Some(record) = cursor.prev() {
+            backwards.push(record);
+        }
+        backwards.reverse();
+        assert_eq!(backwards, expected);
+        assert_eq!(cursor.position(), Some(rids[1]));
+
+        // `next` and `prev` return the same record when called one after the other
+        cursor.seek(rids[9]).unwrap();
+        assert_eq!(cursor.next().unwrap(), vec![9; 100]);
+        assert_eq!(cursor.prev().unwrap(), vec![9; 100]);
+        assert_eq!(cursor.prev().unwrap(), vec![6; 100]);
+        assert_eq!(cursor.position(), Some(rids[6]));
+        assert_eq!(cursor.seek(rids[0]), Err(FileError::RecordDeleted(rids[0])));
+        assert_eq!(cursor.position(), Some(rids[6]));
+
+        cursor.restart();
+        assert_eq!(cursor.next().unwrap(), vec![1; 100]);
+    }
 }
@@ -5,11 +5,19 @@ pub struct FileHeader {
     pub cell_count: u64,
     pub head_page_num: u32,
     pub tail_page_num: u32,
+    pub flags: u32,
+    /// Last change to the records, see `timestamp`
+    pub modified: u64,
+    /// Pages linked from the head, the head included
+    pub page_count: u32,
 }
 
 impl FileHeader {
+    /// Pages other than the head are compressed, see `compression`
+    pub const COMPRESSED: u32 = 1 << 0;
+
     pub const fn size() -> usize {
-        size_of::<u64>() + size_of::<u32>() + size_of::<u32>()
+        size_of::<u64>() * 2 + size_of::<u32>() * 4
     }
 
     pub fn read_from(buffer: &[u8]) -> Self {
@@ -31,10 +39,31 @@ impl FileHeader {
                 .try_into()
                 .unwrap(),
         );
+        offset += size_of::<u32>();
+        let flags = u32::from_be_bytes(
+            buffer[offset..offset + size_of::<u32>()]
+                .try_into()
+                .unwrap(),
+        );
+        offset += size_of::<u32>();
+        let modified = u64::from_be_bytes(
+            buffer[offset..offset + size_of::<u64>()]
+                .try_into()
+                .unwrap(),
+        );
+        offset += size_of::<u64>();
+        let page_count = u32::from_be_bytes(
+            buffer[offset..offset + size_of::<u32>()]
+                .try_into()
+                .unwrap(),
+        );
         Self {
             cell_count,
             head_page_num,
             tail_page_num,
+            flags,
+            modified,
+            page_count,
         }
     }
 
@@ -47,51 +76,73 @@ impl FileHeader {
         offset += size_of::<u32>();
         buffer[offset..offset + size_of::<u32>()]
             .copy_from_slice(&self.tail_page_num.to_be_bytes());
+        offset += size_of::<u32>();
+        buffer[offset..offset + size_of::<u32>()].copy_from_slice(&self.flags.to_be_bytes());
+        offset += size_of::<u32>();
+        buffer[offset..offset + size_of::<u64>()].copy_from_slice(&self.modified.to_be_bytes());
+        offset += size_of::<u64>();
+        buffer[offset..offset + size_of::<u32>()].copy_from_slice(&self.page_count.to_be_bytes());
     }
 }
 
+/// Header of every page of a file, followed by the slot array.
+/// Cells are stored from the end of the page towards the slot array.
 #[derive(Debug)]
 pub struct FileNodeHeader {
-    pub free_space_start: u32,
     pub next: u32,
+    /// 0 for the head page
+    pub prev: u32,
+    pub slot_count: u32,
+    pub cell_content_start: u32,
 }
 
 impl FileNodeHeader {
-    pub fn new(next: u32, free_space_start: u32) -> Self {
-        Self {
-            free_space_start,
-            next,
-        }
-    }
-
     pub const fn size() -> usize {
-        size_of::<u32>() * 2
+        size_of::<u32>() * 4
     }
 
     pub fn read_from(is_head: bool, buffer: &[u8]) -> Self {
         let mut offset = if is_head { FileHeader::size() } else { 0 };
-        let free_space_start = u32::from_be_bytes(
+        let next = u32::from_be_bytes(
             buffer[offset..offset + size_of::<u32>()]
                 .try_into()
                 .unwrap(),
         );
         offset += size_of::<u32>();
-        let next = u32::from_be_bytes(
+        let prev = u32::from_be_bytes(
+            buffer[offset..offset + size_of::<u32>()]
+                .try_into()
+                .unwrap(),
+        );
+        offset += size_of::<u32>();
+        let slot_count = u32::from_be_bytes(
+            buffer[offset..offset + size_of::<u32>()]
+                .try_into()
+                .unwrap(),
+        );
+        offset += size_of::<u32>();
+        let cell_content_start = u32::from_be_bytes(
             buffer[offset..offset + size_of::<u32>()]
                 .try_into()
                 .unwrap(),
         );
         Self {
-            free_space_start,
             next,
+            prev,
+            slot_count,
+            cell_content_start,
         }
     }
 
     pub fn write_to(&self, is_head: bool, page: &mut [u8]) {
         let mut offset = if is_head { FileHeader::size() } else { 0 };
-        page[offset..offset + size_of::<u32>()]
-            .copy_from_slice(&self.free_space_start.to_be_bytes());
-        offset += size_of::<u32>();
         page[offset..offset + size_of::<u32>()].copy_from_slice(&self.next.to_be_bytes());
+        offset += size_of::<u32>();
+        page[offset..offset + size_of::<u32>()].copy_from_slice(&self.prev.to_be_bytes());
+        offset += size_of::<u32>();
+        page[offset..offset + size_of::<u32>()].copy_from_slice(&self.slot_count.to_be_bytes());
+        offset += size_of::<u32>();
+        page[offset..offset + size_of::<u32>()]
+            .copy_from_slice(&self.cell_content_start.to_be_bytes());
     }
 }
@@ -1,148 +1,546 @@
+mod bulk_writer;
 pub mod cell;
+mod compression;
 pub mod cursor;
 mod header;
 mod node;
+mod overflow;
+mod record_id;
+mod scan;
 
-use buffer_manager::BufferManager;
-use disk_manager::DiskManager;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::SystemTime;
 
+use buffer_manager::{BufferManager, PageLatch};
+use disk_manager::{DiskManager, DiskManagerError};
+
+use crate::timestamp;
+
+pub use bulk_writer::BulkWriter;
 pub use cell::Cell;
 pub use cursor::Cursor;
 use header::FileHeader;
-use node::{InsertResult, Node};
+use node::{DecodedPage, Node};
+use overflow::OverflowStub;
+pub use record_id::RecordId;
+pub use scan::{CopyRecord, Scan};
 
-use self::header::FileNodeHeader;
+#[derive(Debug, PartialEq)]
+pub enum FileError {
+    /// The record id does not point to a slot of its page
+    InvalidRecordId(RecordId),
+    RecordDeleted(RecordId),
+    /// The page of the record has no room left to point to the place it is moved to
+    PageFull(RecordId),
+    /// No block is left on the disk to store the record
+    DiskFull,
+}
 
 /// A `File` which only contain records from one `Table`
 /// Implemented as a linked list of page
-pub struct File<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> {
+pub struct File<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
     disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
-    buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+    buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
     pub head_page_number: u32,
+    compressed: bool,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    File<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> File<'a, BLOCKSIZE, CAPACITY> {
     pub fn init(
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+    ) -> Self {
+        Self::init_with_flags(disk_manager, buffer_manager, 0).unwrap()
+    }
+
+    /// Same as `init`, but the pages of the file are compressed.
+    /// Cursors, scans and inserts work the same, repetitive records take fewer pages.
+    pub fn init_compressed(
+        disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
     ) -> Self {
-        let new_page_number = disk_manager.allocate().unwrap();
+        Self::init_with_flags(disk_manager, buffer_manager, FileHeader::COMPRESSED).unwrap()
+    }
+
+    fn init_with_flags(
+        disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
+        flags: u32,
+    ) -> Result<Self, DiskManagerError> {
+        let new_page_number = disk_manager.allocate()?;
         let mut new_page = buffer_manager.get_page(new_page_number);
         let file_header = FileHeader {
             cell_count: 0,
             tail_page_num: new_page_number as u32,
             head_page_num: new_page_number as u32,
+            flags,
+            modified: timestamp::now(),
+            page_count: 1,
         };
         file_header.write_to(&mut new_page);
-
-        let page_header = FileNodeHeader {
-            free_space_start: (FileNodeHeader::size() + FileHeader::size()) as u32,
-            next: 0,
-        };
-        page_header.write_to(true, &mut new_page);
-        File {
+        Node::new(true, new_page);
+        Ok(File {
             disk_manager,
             buffer_manager,
             head_page_number: new_page_number,
-        }
+            compressed: flags & FileHeader::COMPRESSED != 0,
+        })
     }
 
     pub fn open(
-        buffer_manager: &'a BufferManager<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+        buffer_manager: &'a BufferManager<BLOCKSIZE, CAPACITY>,
         disk_manager: &'a DiskManager<BLOCKSIZE, CAPACITY>,
         first_page_num: u32,
     ) -> Self {
+        let header = FileHeader::read_from(&buffer_manager.get_page(first_page_num));
         File {
             disk_manager,
             buffer_manager,
             head_page_number: first_page_num,
+            compressed: header.flags & FileHeader::COMPRESSED != 0,
         }
     }
 
-    pub fn cursor(&'a self) -> Cursor<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> {
-        let page = self.buffer_manager.get_page(self.head_page_number);
-        let block: Node<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> = Node::from_page(true, page);
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Number of records in the file
+    pub fn record_count(&self) -> u64 {
+        self.node(self.head_page_number).cell_count()
+    }
+
+    /// Number of pages holding the records of the file,
+    /// the overflow pages of large records are not counted
+    pub fn page_count(&self) -> u64 {
+        self.node(self.head_page_number).page_count() as u64
+    }
+
+    /// Last time a record was added, changed or removed
+    pub fn modified(&self) -> SystemTime {
+        timestamp::to_system_time(self.node(self.head_page_number).modified())
+    }
+
+    pub fn cursor(&'a self) -> Cursor<'a, BLOCKSIZE, CAPACITY> {
         Cursor::new(
-            block.cell_count(),
             self.head_page_number,
+            self.disk_manager,
             self.buffer_manager,
         )
     }
 
-    pub fn insert(&self, payload: &[u8]) {
-        // Traverse to the last page
-        // If the last page is full, allocate a new page
-        // Write the cell to the last page
-        let page = self.buffer_manager.get_page(self.head_page_number);
-        let mut head: Node<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> = Node::from_page(true, page);
-        let first_block = head.tail_page() == self.head_page_number;
-
-        let tail = self.buffer_manager.get_page(head.tail_page());
-        let mut node: Node<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-            Node::from_page(first_block, tail);
-        let rs = node.insert(payload);
-        match rs {
-            InsertResult::Normal(_) => {
-                if first_block {
-                    drop(head);
-                    let count = node.cell_count() + 1;
-                    node.set_cell_count(count);
+    /// Records matching `predicate`, with their id.
+    /// Records are checked in their page, only matches are copied.
+    pub fn scan<P>(&self, predicate: P) -> Scan<'a, BLOCKSIZE, CAPACITY, P, CopyRecord>
+    where
+        P: FnMut(&[u8]) -> bool,
+    {
+        Scan::new(self, predicate)
+    }
+
+    /// Records larger than this are stored in an overflow chain,
+    /// smaller ones always fit in an empty page
+    pub const fn overflow_threshold() -> usize {
+        BLOCKSIZE / 2
+    }
+
+    pub fn insert(&self, payload: &[u8]) -> RecordId {
+        let (payload, flags) = self.stored_form(payload).unwrap();
+        let _latch = self.latch();
+        let rid = self.insert_cell(&payload, flags).unwrap();
+        self.update_cell_count(|count| count + 1);
+        rid
+    }
+
+    /// Latch of the file, taken by every change to its pages.
+    /// The file header and the tail page are only modified while holding it,
+    /// so several threads can add records to the file at once.
+    fn latch(&self) -> PageLatch<'a> {
+        self.buffer_manager.latch_page(self.head_page_number)
+    }
+
+    /// Append records at the end of the file, see `BulkWriter`.
+    /// Fail with `DiskFull` at the first record the disk has no room for,
+    /// the records before it are kept.
+    pub fn insert_many<'r>(
+        &self,
+        records: impl IntoIterator<Item = &'r [u8]>,
+    ) -> Result<Vec<RecordId>, DiskManagerError> {
+        let mut writer = self.bulk_writer();
+        let rids = records
+            .into_iter()
+            .map(|record| writer.insert(record))
+            .collect::<Result<_, _>>()?;
+        writer.finish();
+        Ok(rids)
+    }
+
+    pub fn bulk_writer(&self) -> BulkWriter<'a, BLOCKSIZE, CAPACITY> {
+        BulkWriter::new(self)
+    }
+
+    /// What is stored in the cell of a record: the record itself,
+    /// or the stub of a new overflow chain holding it
+    fn stored_form(&self, payload: &[u8]) -> Result<(Vec<u8>, u8), DiskManagerError> {
+        if payload.len() > Self::overflow_threshold() {
+            let stub = overflow::write(self.disk_manager, self.buffer_manager, payload)?;
+            Ok((stub.to_bytes().to_vec(), cell::OVERFLOW))
+        } else {
+            Ok((payload.to_vec(), 0))
+        }
+    }
+
+    fn node(&self, page_number: u32) -> Node<'a, BLOCKSIZE, CAPACITY> {
+        let page = self.buffer_manager.get_page(page_number);
+        if page_number == self.head_page_number {
+            Node::from_page(true, page)
+        } else if self.compressed {
+            Node::from_compressed_page(page)
+        } else {
+            Node::from_page(false, page)
+        }
+    }
+
+    /// Same as `node`, the content of a compressed page is taken from `decoded`
+    /// if the page did not change since it was decoded
+    fn node_cached(
+        &self,
+        page_number: u32,
+        decoded: &mut Option<DecodedPage>,
+    ) -> Node<'a, BLOCKSIZE, CAPACITY> {
+        if !self.compressed || page_number == self.head_page_number {
+            return self.node(page_number);
+        }
+        Node::from_compressed_page_cached(self.buffer_manager.get_page(page_number), decoded)
+    }
+
+    /// Set up a newly allocated page as an empty page of the file
+    fn new_node(&self, page_number: u32) -> Node<'a, BLOCKSIZE, CAPACITY> {
+        let page = self.buffer_manager.get_page(page_number);
+        if self.compressed {
+            Node::new_compressed(page)
+        } else {
+            Node::new(false, page)
+        }
+    }
+
+    /// Change the number of records, which also marks the file as modified
+    fn update_cell_count(&self, update: impl FnOnce(u64) -> u64) {
+        let mut head = self.node(self.head_page_number);
+        let count = update(head.cell_count());
+        head.set_cell_count(count);
+        head.set_modified(timestamp::now());
+    }
+
+    /// Store a cell in the last page, or in a new page if it does not fit.
+    /// Fail with `DiskFull` if no page is left for it.
+    /// The cell count is left to the caller.
+    fn insert_cell(&self, payload: &[u8], flags: u8) -> Result<RecordId, FileError> {
+        let mut head = self.node(self.head_page_number);
+        let tail_page = head.tail_page();
+        let inserted = if tail_page == self.head_page_number {
+            head.insert(payload, flags)
+        } else {
+            self.node(tail_page).insert(payload, flags)
+        };
+        match inserted {
+            Some(slot) => Ok(RecordId::new(tail_page, slot)),
+            None => {
+                let new_block = self
+                    .disk_manager
+                    .allocate()
+                    .map_err(|_| FileError::DiskFull)?;
+                let mut new_node = self.new_node(new_block);
+                new_node.set_prev(tail_page);
+                let slot = new_node.insert(payload, flags).unwrap();
+                if tail_page == self.head_page_number {
+                    head.set_next(new_block);
                 } else {
-                    let count = head.cell_count() + 1;
-                    head.set_cell_count(count);
+                    self.node(tail_page).set_next(new_block);
                 }
+                head.set_tail(new_block);
+                head.set_page_count(head.page_count() + 1);
+                Ok(RecordId::new(new_block, slot))
             }
-            InsertResult::Spill(buf, remain_start) => {
-                let new_block = self.disk_manager.allocate().unwrap();
-                let new_page = self.buffer_manager.get_page(new_block);
-                let mut new_node: Node<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-                    Node::new(false, new_page);
-                let spilled_cell = &buf[remain_start..];
-                new_node.insert_spilled(&spilled_cell);
-
-                // `node` and `head` is the same block
-                if first_block {
-                    drop(head);
-                    node.set_next(new_block as u32);
-                    node.set_tail(new_block as u32);
-                    let count = node.cell_count() + 1;
-                    node.set_cell_count(count);
-                } else {
-                    node.set_next(new_block as u32);
-                    head.set_tail(new_block as u32);
-                    let count = head.cell_count() + 1;
-                    head.set_cell_count(count);
+        }
+    }
+
+    /// Where the record of `rid` is stored:
+    /// `rid` itself, or the target of its forwarding pointer if it was moved.
+    /// Whether the page of `rid` belongs to this file is not checked.
+    fn resolve(&self, rid: RecordId) -> Result<RecordId, FileError> {
+        if rid.page() == 0 {
+            return Err(FileError::InvalidRecordId(rid));
+        }
+        let node = self.node(rid.page());
+        if rid.slot() >= node.slot_count() {
+            return Err(FileError::InvalidRecordId(rid));
+        }
+        let cell = node.cell(rid.slot()).ok_or(FileError::RecordDeleted(rid))?;
+        if cell.is_moved() {
+            // Moved records are only reachable through their original id
+            return Err(FileError::InvalidRecordId(rid));
+        }
+        if cell.is_forward() {
+            return Ok(RecordId::read_from(cell.payload()));
+        }
+        Ok(rid)
+    }
+
+    /// Payload of a record as stored in the file, along with its overflow flag
+    fn read_cell(&self, rid: RecordId) -> Result<(Vec<u8>, bool), FileError> {
+        let target = self.resolve(rid)?;
+        let node = self.node(target.page());
+        let cell = node.cell(target.slot()).unwrap();
+        Ok((cell.payload().to_vec(), cell.is_overflow()))
+    }
+
+    pub fn get(&self, rid: RecordId) -> Result<Vec<u8>, FileError> {
+        let (payload, overflow) = self.read_cell(rid)?;
+        if overflow {
+            let stub = OverflowStub::read_from(&payload);
+            return Ok(overflow::read(self.buffer_manager, &stub));
+        }
+        Ok(payload)
+    }
+
+    /// Replace a record, keeping its id.
+    /// The record is rewritten in place if its page has room for it,
+    /// otherwise it is moved to another page and its slot keeps a forwarding pointer.
+    /// Fail with `PageFull`, leaving the record as it was, if the record has to move
+    /// but its compressed page has no room left for the forwarding pointer,
+    /// or with `DiskFull` if no block is left for the record.
+    pub fn update(&self, rid: RecordId, payload: &[u8]) -> Result<(), FileError> {
+        let _latch = self.latch();
+        let target = self.resolve(rid)?;
+        let (old_payload, old_overflow) = self.read_cell(rid)?;
+        let (payload, flags) = self.stored_form(payload).map_err(|_| FileError::DiskFull)?;
+        // The overflow chain written for the record is freed if the record is not stored
+        let free_overflow = || {
+            if flags & cell::OVERFLOW != 0 {
+                let stub = OverflowStub::read_from(&payload);
+                overflow::free(self.disk_manager, self.buffer_manager, &stub);
+            }
+        };
+
+        if target != rid && self.node(rid.page()).replace(rid.slot(), &payload, flags) {
+            // The record moves back to its own slot
+            self.node(target.page()).delete(target.slot());
+        } else {
+            let moved = if target != rid { cell::MOVED } else { 0 };
+            let replaced = self
+                .node(target.page())
+                .replace(target.slot(), &payload, flags | moved);
+            if !replaced {
+                let new_target = match self.insert_cell(&payload, flags | cell::MOVED) {
+                    Ok(new_target) => new_target,
+                    Err(e) => {
+                        free_overflow();
+                        return Err(e);
+                    }
+                };
+                // Every cell has room for a forwarding pointer,
+                // but a compressed page may not have room for it once compressed
+                let forwarded = self.node(rid.page()).replace(
+                    rid.slot(),
+                    &new_target.to_bytes(),
+                    cell::FORWARD,
+                );
+                if !forwarded {
+                    self.node(new_target.page()).delete(new_target.slot());
+                    free_overflow();
+                    return Err(FileError::PageFull(rid));
+                }
+                if target != rid {
+                    self.node(target.page()).delete(target.slot());
                 }
             }
-            InsertResult::OutOfSpace(cell) => {
-                let new_block = self.disk_manager.allocate().unwrap();
-                let new_page = self.buffer_manager.get_page(new_block);
-                let mut new_node: Node<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-                    Node::new(false, new_page);
-                new_node.insert(cell);
-                drop(new_node);
-                if first_block {
-                    drop(head);
-                    node.set_next(new_block as u32);
-                    node.set_tail(new_block as u32);
-                    let count = node.cell_count() + 1;
-                    node.set_cell_count(count);
+        }
+
+        if old_overflow {
+            let old_stub = OverflowStub::read_from(&old_payload);
+            overflow::free(self.disk_manager, self.buffer_manager, &old_stub);
+        }
+        self.node(self.head_page_number)
+            .set_modified(timestamp::now());
+        Ok(())
+    }
+
+    pub fn delete(&self, rid: RecordId) -> Result<(), FileError> {
+        let _latch = self.latch();
+        let target = self.resolve(rid)?;
+        let (payload, overflow) = self.read_cell(rid)?;
+        if overflow {
+            let stub = OverflowStub::read_from(&payload);
+            overflow::free(self.disk_manager, self.buffer_manager, &stub);
+        }
+        self.node(target.page()).delete(target.slot());
+        if target != rid {
+            self.node(rid.page()).delete(rid.slot());
+        }
+        self.update_cell_count(|count| count - 1);
+        Ok(())
+    }
+
+    /// Compact the file: live records are rewritten from the head page,
+    /// pages left empty are given back to the disk manager.
+    /// Pages are compacted one at a time, in the order of the file: the cells of a page
+    /// are read, then the page is emptied and the cells are written again to the first
+    /// emptied page with room for them, so cells of later pages move forward.
+    /// Overflow chains of live records are kept as they are.
+    /// Records move, so their previous ids are no longer valid.
+    pub fn vacuum(&self) {
+        let _latch = self.latch();
+        // Emptied pages in the order of the file, cells are written to the first one
+        let mut emptied: VecDeque<u32> = VecDeque::new();
+        let mut pending: VecDeque<(Vec<u8>, u8)> = VecDeque::new();
+        // Moved records read before their forwarding pointer, and the moved records
+        // whose forwarding pointer was read first and which are skipped
+        let mut moved: HashMap<RecordId, (Vec<u8>, u8)> = HashMap::new();
+        let mut forwarded: HashSet<RecordId> = HashSet::new();
+        let mut page_count = 1;
+
+        let mut next_page_num = Some(self.head_page_number);
+        while let Some(page_num) = next_page_num {
+            let node = self.node(page_num);
+            next_page_num = node.next();
+            for slot in 0..node.slot_count() {
+                let Some(cell) = node.cell(slot) else {
+                    continue;
+                };
+                let stored = (cell.payload().to_vec(), cell.flags() & cell::OVERFLOW);
+                let rid = RecordId::new(page_num, slot);
+                if cell.is_moved() {
+                    if !forwarded.remove(&rid) {
+                        moved.insert(rid, stored);
+                    }
+                } else if cell.is_forward() {
+                    let target = RecordId::read_from(cell.payload());
+                    // The page of the target is not emptied yet if it was not read
+                    let stored = moved.remove(&target).unwrap_or_else(|| {
+                        forwarded.insert(target);
+                        let node = self.node(target.page());
+                        let cell = node.cell(target.slot()).unwrap();
+                        (cell.payload().to_vec(), cell.flags() & cell::OVERFLOW)
+                    });
+                    pending.push_back(stored);
                 } else {
-                    node.set_next(new_block as u32);
-                    head.set_tail(new_block as u32);
-                    let count = head.cell_count() + 1;
-                    head.set_cell_count(count);
+                    pending.push_back(stored);
+                }
+            }
+            drop(node);
+
+            if page_num == self.head_page_number {
+                let head = self.node(page_num);
+                let (cell_count, modified) = (head.cell_count(), head.modified());
+                drop(head);
+                let mut head = Node::new(true, self.buffer_manager.get_page(page_num));
+                head.set_cell_count(cell_count);
+                head.set_modified(modified);
+            } else {
+                self.new_node(page_num);
+            }
+            emptied.push_back(page_num);
+
+            while let Some((payload, flags)) = pending.front() {
+                let page = emptied[0];
+                if self.node(page).insert(payload, *flags).is_some() {
+                    pending.pop_front();
+                    continue;
+                }
+                // The page is full, the next emptied page is linked after it
+                let Some(&next_page) = emptied.get(1) else {
+                    break;
+                };
+                self.node(page).set_next(next_page);
+                self.node(next_page).set_prev(page);
+                emptied.pop_front();
+                page_count += 1;
+            }
+        }
+
+        let mut head = self.node(self.head_page_number);
+        head.set_tail(emptied[0]);
+        head.set_page_count(page_count);
+        drop(head);
+        for page_num in emptied.drain(1..) {
+            overflow::free_page(self.disk_manager, self.buffer_manager, page_num);
+        }
+        // Cells are only left when they are laid out in more pages than they were
+        for (payload, flags) in pending {
+            // Pages were freed for every page the cells are stored in again
+            self.insert_cell(&payload, flags).unwrap();
+        }
+    }
+
+    /// Copy the records into a new file, compressed if this one is.
+    /// Records get new ids in the copy. Fail with `DiskFull`,
+    /// giving back what was allocated for the copy, if the disk has no room for it.
+    pub fn copy(&self) -> Result<Self, DiskManagerError> {
+        let flags = if self.compressed {
+            FileHeader::COMPRESSED
+        } else {
+            0
+        };
+        let copy = Self::init_with_flags(self.disk_manager, self.buffer_manager, flags)?;
+        let mut writer = copy.bulk_writer();
+        for (_, record) in self.scan(|_| true) {
+            if let Err(e) = writer.insert(&record) {
+                writer.finish();
+                copy.free();
+                return Err(e);
+            }
+        }
+        writer.finish();
+        Ok(copy)
+    }
+
+    /// Give every page of the file back to the disk manager, overflow chains included
+    pub fn free(self) {
+        self.truncate();
+        overflow::free_page(
+            self.disk_manager,
+            self.buffer_manager,
+            self.head_page_number,
+        );
+    }
+
+    /// Remove every record of the file.
+    /// Pages other than the head and the overflow chains of the records
+    /// are given back to the disk manager. Only the slot arrays of the pages are read,
+    /// so this takes time proportional to the number of pages rather than records.
+    pub fn truncate(&self) {
+        let _latch = self.latch();
+        let mut next_page_num = Some(self.head_page_number);
+        while let Some(page_num) = next_page_num {
+            let node = self.node(page_num);
+            for slot in 0..node.slot_count() {
+                match node.cell(slot) {
+                    Some(cell) if cell.is_overflow() => {
+                        let stub = OverflowStub::read_from(cell.payload());
+                        overflow::free(self.disk_manager, self.buffer_manager, &stub);
+                    }
+                    _ => {}
                 }
             }
+            next_page_num = node.next();
+            drop(node);
+            if page_num != self.head_page_number {
+                overflow::free_page(self.disk_manager, self.buffer_manager, page_num);
+            }
         }
+
+        let page = self.buffer_manager.get_page(self.head_page_number);
+        let mut head: Node<'_, BLOCKSIZE, CAPACITY> = Node::new(true, page);
+        head.set_cell_count(0);
+        head.set_tail(self.head_page_number);
+        head.set_page_count(1);
+        head.set_modified(timestamp::now());
     }
 
     pub fn save(&self) {
         let current_page = self.buffer_manager.get_page(self.head_page_number);
-        let current_node: Node<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-            Node::from_page(true, current_page);
+        let current_node: Node<'_, BLOCKSIZE, CAPACITY> = Node::from_page(true, current_page);
         let mut next_page_num = current_node.next();
         drop(current_node);
         self.buffer_manager
@@ -153,9 +551,7 @@ impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: u
                 break;
             }
             let next = next_page_num.unwrap();
-            let next_page = self.buffer_manager.get_page(next);
-            let next_node: Node<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-                Node::from_page(false, next_page);
+            let next_node = self.node(next);
             next_page_num = next_node.next();
             drop(next_node);
             self.buffer_manager.save_page(next).unwrap();
@@ -168,6 +564,19 @@ mod tests {
     use super::*;
     use disk::Disk;
 
+    /// Pages linked from the head of `file`
+    fn linked_pages<const BLOCKSIZE: usize, const CAPACITY: usize>(
+        file: &File<BLOCKSIZE, CAPACITY>,
+    ) -> u64 {
+        let mut count = 1;
+        let mut next = file.node(file.head_page_number).next();
+        while let Some(page_number) = next {
+            count += 1;
+            next = file.node(page_number).next();
+        }
+        count
+    }
+
     #[test]
     fn simple_read() {
         const BLOCKSIZE: usize = 512;
@@ -177,9 +586,8 @@ mod tests {
         let disk_manager = DiskManager::init(&disk);
 
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager: BufferManager<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-                BufferManager::init(&memory, &disk);
+            let buffer_manager: BufferManager<BLOCKSIZE, CAPACITY> =
+                BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
             let file = File::init(&disk_manager, &buffer_manager);
             let record = vec![1, 2, 3];
             file.insert(&record);
@@ -188,9 +596,8 @@ mod tests {
             file.save();
         }
         {
-            let memory = [0; MEMORY_CAPACITY];
-            let buffer_manager: BufferManager<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-                BufferManager::init(&memory, &disk);
+            let buffer_manager: BufferManager<BLOCKSIZE, CAPACITY> =
+                BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
             let file = File::open(&buffer_manager, &disk_manager, 1);
             let mut cursor = file.cursor();
             let record = cursor.next().unwrap();
@@ -198,16 +605,321 @@ mod tests {
         }
     }
 
+    #[test]
+    fn large_records() {
+        let disk = Disk::<512, 65536>::create("unordered_file::large_records").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let records: Vec<Vec<u8>> = vec![
+            (0..3000).map(|i| i as u8).collect(),
+            vec![0x1; 17],
+            (0..10000).map(|i| (i % 251) as u8).collect(),
+            vec![0x2; 256],
+            vec![0x3; 257],
+        ];
+        for record in &records {
+            file.insert(record);
+        }
+        assert_eq!(file.cursor().collect::<Vec<_>>(), records);
+
+        // Deleting a large record frees its overflow chain,
+        // the disk manager hands out the lowest free page first
+        let first_free = disk_manager.allocate().unwrap();
+        disk_manager.deallocate(first_free).unwrap();
+        let mut cursor = file.cursor();
+        cursor.next();
+        cursor.next();
+        cursor.delete();
+        assert!(disk_manager.allocate().unwrap() < first_free);
+        let mut expected = records.clone();
+        expected.remove(2);
+        assert_eq!(file.cursor().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn record_ids() {
+        let disk = Disk::<512, 65536>::create("unordered_file::record_ids").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let records: Vec<Vec<u8>> = (0..30).map(|i| vec![i as u8; 40 + i * 7]).collect();
+        let rids: Vec<RecordId> = records.iter().map(|record| file.insert(record)).collect();
+        let large = vec![0xab; 1500];
+        let large_rid = file.insert(&large);
+        for (rid, record) in rids.iter().zip(&records) {
+            assert_eq!(&file.get(*rid).unwrap(), record);
+        }
+        assert_eq!(file.get(large_rid).unwrap(), large);
+
+        // Records are updated in place
+        for (i, rid) in rids.iter().enumerate() {
+            file.update(*rid, &vec![0xff - i as u8; records[i].len()])
+                .unwrap();
+        }
+        for (i, rid) in rids.iter().enumerate() {
+            assert_eq!(
+                file.get(*rid).unwrap(),
+                vec![0xff - i as u8; records[i].len()]
+            );
+        }
+        // A record can shrink in place
+        file.update(rids[0], &[1, 2, 3]).unwrap();
+        assert_eq!(file.get(rids[0]).unwrap(), vec![1, 2, 3]);
+        // Overflow records can change size
+        file.update(large_rid, &[0xcd; 3000]).unwrap();
+        assert_eq!(file.get(large_rid).unwrap(), vec![0xcd; 3000]);
+        file.update(large_rid, &[0xef; 10]).unwrap();
+        assert_eq!(file.get(large_rid).unwrap(), vec![0xef; 10]);
+
+        file.delete(rids[3]).unwrap();
+        assert_eq!(file.get(rids[3]), Err(FileError::RecordDeleted(rids[3])));
+        assert_eq!(file.delete(rids[3]), Err(FileError::RecordDeleted(rids[3])));
+        assert_eq!(file.cursor().count(), 30);
+        let invalid = RecordId::new(file.head_page_number, 1000);
+        assert_eq!(file.get(invalid), Err(FileError::InvalidRecordId(invalid)));
+
+        // The cursor reports the id of the record it is on
+        let mut cursor = file.cursor();
+        assert_eq!(cursor.position(), Some(rids[0]));
+        cursor.next();
+        assert_eq!(cursor.position(), Some(rids[1]));
+    }
+
+    #[test]
+    fn relocate_on_update() {
+        let disk = Disk::<512, 65536>::create("unordered_file::relocate_on_update").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let rids: Vec<RecordId> = (0..4).map(|i| file.insert(&[i as u8; 100])).collect();
+        assert!(rids.iter().all(|rid| rid.page() == file.head_page_number));
+
+        // The record does not fit in its page anymore, it moves but keeps its id
+        file.update(rids[1], &[0xaa; 200]).unwrap();
+        assert_eq!(file.get(rids[1]).unwrap(), vec![0xaa; 200]);
+        let target = file.resolve(rids[1]).unwrap();
+        assert_ne!(target.page(), file.head_page_number);
+        // The moved record is only reachable through its id
+        assert_eq!(file.get(target), Err(FileError::InvalidRecordId(target)));
+        // Each record is seen once, at the place of its id
+        assert_eq!(
+            file.cursor().collect::<Vec<_>>(),
+            vec![vec![0; 100], vec![0xaa; 200], vec![2; 100], vec![3; 100]]
+        );
+
+        // A moved record is updated at its new place, forwarding pointers are never chained
+        file.update(rids[1], &[0xbb; 250]).unwrap();
+        assert_eq!(file.resolve(rids[1]), Ok(target));
+        assert_eq!(file.get(rids[1]).unwrap(), vec![0xbb; 250]);
+
+        // A record small enough goes back to its own slot
+        file.update(rids[1], &[0xcc; 50]).unwrap();
+        assert_eq!(file.resolve(rids[1]), Ok(rids[1]));
+        assert_eq!(file.get(rids[1]).unwrap(), vec![0xcc; 50]);
+        assert!(file.get(target).is_err());
+
+        // Deleting a moved record frees both of its cells
+        file.update(rids[2], &[0xdd; 250]).unwrap();
+        let target = file.resolve(rids[2]).unwrap();
+        assert_ne!(target, rids[2]);
+        file.delete(rids[2]).unwrap();
+        assert_eq!(file.get(rids[2]), Err(FileError::RecordDeleted(rids[2])));
+        assert!(file.get(target).is_err());
+        assert_eq!(
+            file.cursor().collect::<Vec<_>>(),
+            vec![vec![0; 100], vec![0xcc; 50], vec![3; 100]]
+        );
+        assert_eq!(file.node(file.head_page_number).cell_count(), 3);
+    }
+
+    #[test]
+    fn update_disk_full() {
+        let disk = Disk::<512, 65536>::create("unordered_file::update_disk_full").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let rids: Vec<RecordId> = (0..4).map(|i| file.insert(&[i as u8; 100])).collect();
+        let taken = disk_manager
+            .allocate_many(disk_manager.free_blocks())
+            .unwrap();
+
+        // The record has to move, but no page is left for it
+        assert_eq!(file.update(rids[1], &[0xaa; 200]), Err(FileError::DiskFull));
+        // The overflow chain of the record does not fit, none of its pages are kept
+        disk_manager.deallocate(taken[0]).unwrap();
+        assert_eq!(file.update(rids[1], &[0xbb; 600]), Err(FileError::DiskFull));
+        assert_eq!(disk_manager.free_blocks(), 1);
+        assert_eq!(
+            file.cursor().collect::<Vec<_>>(),
+            (0..4).map(|i| vec![i as u8; 100]).collect::<Vec<_>>()
+        );
+
+        // Records which fit in their page are still updated
+        file.update(rids[1], &[0xdd; 120]).unwrap();
+        assert_eq!(file.get(rids[1]).unwrap(), vec![0xdd; 120]);
+    }
+
+    #[test]
+    fn reuse_deleted_space() {
+        let disk = Disk::<512, 65536>::create("unordered_file::reuse_deleted_space").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let rids: Vec<RecordId> = (0..4).map(|i| file.insert(&[i as u8; 100])).collect();
+        assert!(rids.iter().all(|rid| rid.page() == file.head_page_number));
+        file.delete(rids[1]).unwrap();
+        file.delete(rids[2]).unwrap();
+
+        // The page is compacted to make room, the other records keep their ids
+        let rid = file.insert(&[0xff; 180]);
+        assert_eq!(rid.page(), file.head_page_number);
+        assert_eq!(file.get(rids[0]).unwrap(), vec![0; 100]);
+        assert_eq!(file.get(rids[3]).unwrap(), vec![3; 100]);
+        assert_eq!(file.get(rid).unwrap(), vec![0xff; 180]);
+        assert_eq!(
+            file.cursor().collect::<Vec<_>>(),
+            vec![vec![0; 100], vec![0xff; 180], vec![3; 100]]
+        );
+    }
+
+    #[test]
+    fn random_operations() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        use std::collections::HashMap;
+
+        for (seed, compressed) in (0..4).flat_map(|seed| [(seed, false), (seed, true)]) {
+            let name = format!("unordered_file::random_operations_{seed}_{compressed}");
+            let disk = Disk::<512, { 512 * 512 }>::create(&name).unwrap();
+            let disk_manager = DiskManager::init(&disk);
+            let buffer_manager: BufferManager<512, { 512 * 512 }> = BufferManager::init(16, &disk);
+            let file = if compressed {
+                File::init_compressed(&disk_manager, &buffer_manager)
+            } else {
+                File::init(&disk_manager, &buffer_manager)
+            };
+            let mut rng = StdRng::seed_from_u64(seed);
+            // Some records of a compressed file do not compress, which seals their page
+            let mut noise = StdRng::seed_from_u64(seed);
+            let mut records: HashMap<RecordId, Vec<u8>> = HashMap::new();
+            for i in 0..500 {
+                let mut record = vec![i as u8; rng.gen_range(1..300)];
+                if compressed && i % 4 == 0 {
+                    noise.fill(record.as_mut_slice());
+                }
+                let existing = records.keys().nth(rng.gen_range(0..records.len().max(1)));
+                match (rng.gen_range(0..3), existing.copied()) {
+                    (1, Some(rid)) => match file.update(rid, &record) {
+                        Ok(()) => {
+                            records.insert(rid, record);
+                        }
+                        // The record is left as it was
+                        Err(FileError::PageFull(_)) => assert!(compressed),
+                        Err(e) => panic!("{e:?}"),
+                    },
+                    (2, Some(rid)) => {
+                        file.delete(rid).unwrap();
+                        records.remove(&rid);
+                    }
+                    _ => {
+                        records.insert(file.insert(&record), record);
+                    }
+                }
+            }
+            for (rid, record) in &records {
+                assert_eq!(&file.get(*rid).unwrap(), record);
+            }
+            let mut read: Vec<_> = file.cursor().collect();
+            let mut expected: Vec<_> = records.values().cloned().collect();
+            read.sort();
+            expected.sort();
+            assert_eq!(read, expected);
+            assert_eq!(file.record_count(), records.len() as u64);
+        }
+    }
+
+    #[test]
+    fn vacuum() {
+        let disk = Disk::<512, 65536>::create("unordered_file::vacuum").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let records: Vec<Vec<u8>> = (0..40).map(|i| vec![i as u8; 100]).collect();
+        for record in &records {
+            file.insert(record);
+        }
+        file.insert(&[0xff; 2000]);
+        let first_free = disk_manager.allocate().unwrap();
+        disk_manager.deallocate(first_free).unwrap();
+        assert!(file.page_count() > 1);
+        assert_eq!(file.page_count(), linked_pages(&file));
+
+        // Delete every record but the last two
+        for _ in 0..38 {
+            let mut cursor = file.cursor();
+            cursor.skip_delete();
+            cursor.delete();
+        }
+        file.vacuum();
+        assert!(disk_manager.allocate().unwrap() < first_free);
+        let mut expected = records[38..].to_vec();
+        expected.push(vec![0xff; 2000]);
+        assert_eq!(file.cursor().collect::<Vec<_>>(), expected);
+        assert_eq!(file.page_count(), linked_pages(&file));
+
+        // The file keeps working after a vacuum
+        file.insert(&[0x7; 300]);
+        expected.push(vec![0x7; 300]);
+        assert_eq!(file.cursor().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn vacuum_moved_records() {
+        for compressed in [false, true] {
+            let disk = Disk::<512, 65536>::create("unordered_file::vacuum_moved_records").unwrap();
+            let disk_manager = DiskManager::init(&disk);
+            let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+            let file = if compressed {
+                File::init_compressed(&disk_manager, &buffer_manager)
+            } else {
+                File::init(&disk_manager, &buffer_manager)
+            };
+            let rids: Vec<RecordId> = (0..60)
+                .map(|i| file.insert(format!("{i:03}").repeat(30).as_bytes()))
+                .collect();
+            // Records moved to later pages, deleted records and an overflow chain
+            for (i, rid) in rids.iter().enumerate() {
+                match i % 5 {
+                    0 => file.delete(*rid).unwrap(),
+                    1 => file
+                        .update(*rid, format!("{i:03}").repeat(70).as_bytes())
+                        .unwrap(),
+                    _ => {}
+                }
+            }
+            file.update(rids[2], &[0xab; 2000]).unwrap();
+            let expected = file.cursor().collect::<Vec<_>>();
+            let free_blocks = disk_manager.free_blocks();
+
+            file.vacuum();
+            assert_eq!(file.cursor().collect::<Vec<_>>(), expected);
+            assert_eq!(file.record_count(), expected.len() as u64);
+            assert_eq!(file.page_count(), linked_pages(&file));
+            assert!(disk_manager.free_blocks() > free_blocks);
+            file.insert(&[0x7; 100]);
+            assert_eq!(file.cursor().last().unwrap(), vec![0x7; 100]);
+        }
+    }
+
     #[test]
     fn edge_case() {
         const BLOCKSIZE: usize = 512;
         const CAPACITY: usize = 512 * 128;
         const MEMORY_CAPACITY: usize = 512 * 32;
-        let memory = [0; MEMORY_CAPACITY];
         let disk = Disk::<BLOCKSIZE, CAPACITY>::create("edge_case").unwrap();
         let disk_manager = DiskManager::init(&disk);
-        let buffer_manager: BufferManager<'_, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<BLOCKSIZE, CAPACITY> =
+            BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
         let mut file = File::init(&disk_manager, &buffer_manager);
     }
 
@@ -225,4 +937,174 @@ mod tests {
         // let mut file = File::init(&disk, &disk_manager);
         let mut rng = rand::thread_rng();
     }
+
+    #[test]
+    fn truncate() {
+        let disk = Disk::<512, 65536>::create("unordered_file::truncate").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let first_free = disk_manager.allocate().unwrap();
+        disk_manager.deallocate(first_free).unwrap();
+        for i in 0..20 {
+            file.insert(&[i as u8; 100]);
+        }
+        let large = file.insert(&[0xab; 2000]);
+        file.update(large, &[0xcd; 3000]).unwrap();
+        // A moved record, its cell is on another page than its id
+        let moved = file.insert(&[0xef; 10]);
+        file.update(moved, &[0xef; 200]).unwrap();
+
+        file.truncate();
+        assert_eq!(file.cursor().next(), None);
+        assert_eq!(file.node(file.head_page_number).cell_count(), 0);
+        assert_eq!(file.page_count(), 1);
+        // Every page but the head is given back, overflow chains included
+        assert_eq!(disk_manager.allocate().unwrap(), first_free);
+        disk_manager.deallocate(first_free).unwrap();
+
+        // The file is usable again
+        let rid = file.insert(&[1; 100]);
+        assert_eq!(rid.page(), file.head_page_number);
+        assert_eq!(file.cursor().collect::<Vec<_>>(), vec![vec![1; 100]]);
+    }
+
+    #[test]
+    fn copy_free() {
+        let disk = Disk::<512, 65536>::create("unordered_file::copy_free").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let mut records: Vec<Vec<u8>> = (0..20).map(|i| vec![i as u8; 100]).collect();
+        records.push(vec![0xab; 2000]);
+        for record in &records {
+            file.insert(record);
+        }
+        let free_blocks = disk_manager.free_blocks();
+
+        let copy = file.copy().unwrap();
+        assert_eq!(copy.cursor().collect::<Vec<_>>(), records);
+        assert_eq!(copy.record_count(), file.record_count());
+        copy.insert(&[0xcd; 100]);
+        assert_eq!(file.cursor().count(), records.len());
+        copy.free();
+        assert_eq!(disk_manager.free_blocks(), free_blocks);
+
+        // A copy which does not fit gives back the pages it took
+        let taken = disk_manager.allocate_many(free_blocks - 3).unwrap();
+        assert!(matches!(file.copy(), Err(DiskManagerError::DiskFull)));
+        assert_eq!(disk_manager.free_blocks(), 3);
+        for block in taken {
+            disk_manager.deallocate(block).unwrap();
+        }
+    }
+
+    #[test]
+    fn compressed() {
+        // Detail columns repeat the same text over and over
+        let records: Vec<Vec<u8>> = (0..400)
+            .map(|i| {
+                format!("{i:05}|shipped to the warehouse on time, no damage reported").into_bytes()
+            })
+            .collect();
+        let disk_footprint = |name: &str, compressed: bool| {
+            let disk = Disk::<512, { 512 * 1024 }>::create(name).unwrap();
+            let disk_manager = DiskManager::init(&disk);
+            let buffer_manager: BufferManager<512, { 512 * 1024 }> = BufferManager::init(16, &disk);
+            let file = if compressed {
+                File::init_compressed(&disk_manager, &buffer_manager)
+            } else {
+                File::init(&disk_manager, &buffer_manager)
+            };
+            for record in &records {
+                file.insert(record);
+            }
+            assert_eq!(file.cursor().collect::<Vec<_>>(), records);
+            disk_manager.allocate().unwrap()
+        };
+        let raw = disk_footprint("unordered_file::compressed_raw", false);
+        let compressed = disk_footprint("unordered_file::compressed", true);
+        assert!(
+            compressed * 2 < raw,
+            "{compressed} blocks, {raw} uncompressed"
+        );
+
+        // Compression is transparent to every operation on the file
+        let disk = Disk::<512, { 512 * 1024 }>::create("unordered_file::compressed_ops").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, { 512 * 1024 }> = BufferManager::init(16, &disk);
+        let file = File::init_compressed(&disk_manager, &buffer_manager);
+        let rids: Vec<_> = records.iter().map(|record| file.insert(record)).collect();
+        let file = File::open(&buffer_manager, &disk_manager, file.head_page_number);
+        assert!(file.is_compressed());
+        file.delete(rids[1]).unwrap();
+        // Random bytes do not compress, the record is moved to another page
+        let noise: Vec<u8> = (0..250).map(|_| rand::random()).collect();
+        file.update(rids[2], &noise).unwrap();
+        file.update(rids[3], &[0xab; 3000]).unwrap();
+        assert_eq!(file.get(rids[0]).unwrap(), records[0]);
+        assert_eq!(file.get(rids[2]).unwrap(), noise);
+        assert_eq!(file.get(rids[3]).unwrap(), vec![0xab; 3000]);
+        assert_eq!(file.scan(|record| record.starts_with(b"00100")).count(), 1);
+        let mut cursor = file.cursor();
+        cursor.seek(rids[399]).unwrap();
+        assert_eq!(cursor.prev().unwrap(), records[398]);
+        assert_eq!(file.cursor().count(), 399);
+        file.vacuum();
+        assert_eq!(file.cursor().count(), 399);
+    }
+
+    #[test]
+    fn concurrent_inserts() {
+        const THREADS: usize = 8;
+        const RECORDS: usize = 300;
+        let disk =
+            Disk::<512, { 512 * 4096 }>::create("unordered_file::concurrent_inserts").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, { 512 * 4096 }> = BufferManager::init(64, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        // Records are tagged with their thread and index, some of them overflow
+        let record = |thread: usize, i: usize| {
+            let mut record = vec![thread as u8, (i >> 8) as u8, i as u8];
+            record.resize(
+                if i.is_multiple_of(50) {
+                    1000
+                } else {
+                    20 + i % 100
+                },
+                0xab,
+            );
+            record
+        };
+
+        std::thread::scope(|s| {
+            for thread in 0..THREADS {
+                let file = &file;
+                s.spawn(move || {
+                    if thread % 4 == 0 {
+                        let records: Vec<_> = (0..RECORDS).map(|i| record(thread, i)).collect();
+                        file.insert_many(records.iter().map(|record| record.as_slice()))
+                            .unwrap();
+                    } else {
+                        for i in 0..RECORDS {
+                            file.insert(&record(thread, i));
+                        }
+                    }
+                });
+            }
+        });
+
+        // Every record arrived exactly once
+        let mut records: Vec<_> = file.cursor().collect();
+        records.sort();
+        let mut expected: Vec<_> = (0..THREADS)
+            .flat_map(|thread| (0..RECORDS).map(move |i| record(thread, i)))
+            .collect();
+        expected.sort();
+        assert_eq!(records, expected);
+        assert_eq!(
+            file.node(file.head_page_number).cell_count(),
+            (THREADS * RECORDS) as u64
+        );
+    }
 }
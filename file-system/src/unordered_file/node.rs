@@ -1,79 +1,192 @@
+use std::{mem::size_of, sync::Arc};
+
 use buffer_manager::Page;
 
 use super::{
-    cell::{self, Cell, CellMut},
+    cell::{self, Cell},
+    compression,
     header::{FileHeader, FileNodeHeader},
 };
 
-#[derive(Debug)]
-pub enum InsertResult<'a> {
-    /// new size
-    Normal(usize),
-    /// remain payload, kept size
-    Spill(&'a [u8], usize),
-    /// payload
-    OutOfSpace(&'a [u8]),
-}
+/// Each slot holds the offset of its cell, or 0 for a free slot
+const SLOT_SIZE: usize = size_of::<u32>();
 
-pub struct Node<
-    'a,
-    const BLOCKSIZE: usize,
-    const DISK_CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
-> {
+/// A page of a `File`, laid out as a slotted page:
+/// the slot array grows after the header, cells grow from the end of the page.
+/// A record keeps its slot when cells are moved around, so its id stays valid.
+/// The page of a compressed file is worked on uncompressed, see `compression`.
+pub struct Node<'a, const BLOCKSIZE: usize, const DISK_CAPACITY: usize> {
     pub is_head: bool,
-    pub page: Page<'a, BLOCKSIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
+    pub page: Page<'a, BLOCKSIZE, DISK_CAPACITY>,
+    /// Uncompressed content of a compressed page,
+    /// shared with the `DecodedPage` it comes from until it changes
+    image: Option<Arc<Vec<u8>>>,
 }
 
-impl<'a, const BLOCKSIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    Node<'a, BLOCKSIZE, DISK_CAPACITY, MEMORY_CAPACITY>
-{
-    pub fn from_page(
-        is_head: bool,
-        page: Page<'a, BLOCKSIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
-    ) -> Self {
-        Self { is_head, page }
+/// Content of a compressed page decoded for a reader going through its records,
+/// reused as long as the block of the page does not change
+pub struct DecodedPage {
+    block: Vec<u8>,
+    image: Arc<Vec<u8>>,
+}
+
+impl<'a, const BLOCKSIZE: usize, const DISK_CAPACITY: usize> Node<'a, BLOCKSIZE, DISK_CAPACITY> {
+    pub fn from_page(is_head: bool, page: Page<'a, BLOCKSIZE, DISK_CAPACITY>) -> Self {
+        Self {
+            is_head,
+            page,
+            image: None,
+        }
+    }
+
+    pub fn new(is_head: bool, page: Page<'a, BLOCKSIZE, DISK_CAPACITY>) -> Self {
+        let mut node = Self::from_page(is_head, page);
+        node.init();
+        node
+    }
+
+    /// Node of a page of a compressed file, which can not be the head
+    pub fn from_compressed_page(page: Page<'a, BLOCKSIZE, DISK_CAPACITY>) -> Self {
+        let mut node = Self {
+            is_head: false,
+            page,
+            image: None,
+        };
+        node.decode();
+        node
     }
 
-    pub fn new(
-        is_head: bool,
-        mut page: Page<'a, BLOCKSIZE, DISK_CAPACITY, MEMORY_CAPACITY>,
+    /// Same as `from_compressed_page`, the content in `decoded` is used
+    /// if it was decoded from the same block, otherwise it is replaced
+    pub fn from_compressed_page_cached(
+        page: Page<'a, BLOCKSIZE, DISK_CAPACITY>,
+        decoded: &mut Option<DecodedPage>,
     ) -> Self {
-        let header_size = if is_head {
-            FileNodeHeader::size() + FileHeader::size()
-        } else {
-            FileNodeHeader::size()
+        if let Some(decoded) = decoded
+            .as_ref()
+            .filter(|decoded| decoded.block[..] == page[..])
+        {
+            return Self {
+                is_head: false,
+                image: Some(decoded.image.clone()),
+                page,
+            };
+        }
+        let node = Self::from_compressed_page(page);
+        *decoded = Some(DecodedPage {
+            block: node.page.to_vec(),
+            image: node.image.clone().unwrap(),
+        });
+        node
+    }
+
+    pub fn new_compressed(page: Page<'a, BLOCKSIZE, DISK_CAPACITY>) -> Self {
+        let mut node = Self {
+            is_head: false,
+            page,
+            image: Some(Arc::new(vec![0; compression::image_size::<BLOCKSIZE>()])),
         };
-        let header = FileNodeHeader::new(0, header_size as u32);
-        header.write_to(is_head, &mut page);
-        Self { is_head, page }
+        node.init();
+        node
+    }
+
+    fn init(&mut self) {
+        let header = FileNodeHeader {
+            next: 0,
+            prev: 0,
+            slot_count: 0,
+            cell_content_start: self.buf().len() as u32,
+        };
+        header.write_to(self.is_head, self.buf_mut());
+        self.store();
+    }
+
+    /// Content of the page, uncompressed
+    fn buf(&self) -> &[u8] {
+        match &self.image {
+            Some(image) => image,
+            None => &self.page,
+        }
+    }
+
+    fn buf_mut(&mut self) -> &mut [u8] {
+        match &mut self.image {
+            Some(image) => Arc::make_mut(image).as_mut_slice(),
+            None => &mut self.page,
+        }
+    }
+
+    /// Compress the content of a compressed page back into the page,
+    /// keeping `reserve` bytes free. Return false, leaving the page untouched,
+    /// if it does not fit.
+    fn try_store(&mut self, reserve: usize) -> bool {
+        match &self.image {
+            Some(image) => compression::store(image, &mut self.page, reserve),
+            None => true,
+        }
+    }
+
+    fn store(&mut self) {
+        assert!(self.try_store(0), "Compressed page overflow");
+    }
+
+    /// Uncompress the content of a compressed page
+    /// and insert the records appended to it since it was last compressed
+    fn decode(&mut self) {
+        self.image = Some(Arc::new(compression::load::<BLOCKSIZE>(&self.page)));
+        let appended: Vec<(u8, Vec<u8>)> = compression::appended(&self.page)
+            .into_iter()
+            .map(|(flags, payload)| (flags, payload.to_vec()))
+            .collect();
+        for (flags, payload) in appended {
+            // Inserting the same records in the same order gives the same content back
+            self.insert_cell(&payload, flags).unwrap();
+        }
+    }
+
+    /// Run a change which may fail for lack of space,
+    /// and undo it if the page does not have room for it once compressed.
+    fn try_change<T>(
+        &mut self,
+        reserve: usize,
+        change: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<T> {
+        let backup = self.image.clone();
+        let result = change(self)?;
+        if self.try_store(reserve) {
+            Some(result)
+        } else {
+            self.image = backup;
+            None
+        }
     }
 
-    pub fn free_start(&self) -> u32 {
-        let header = FileNodeHeader::read_from(self.is_head, self.page.as_ref());
-        header.free_space_start
+    fn header(&self) -> FileNodeHeader {
+        FileNodeHeader::read_from(self.is_head, self.buf())
     }
 
-    pub fn set_free_start(&mut self, free_start: u32) {
-        let mut header = FileNodeHeader::read_from(self.is_head, self.page.as_ref());
-        header.free_space_start = free_start;
-        header.write_to(self.is_head, self.page.as_mut());
+    fn slots_start(&self) -> usize {
+        if self.is_head {
+            FileHeader::size() + FileNodeHeader::size()
+        } else {
+            FileNodeHeader::size()
+        }
     }
 
     pub fn set_tail(&mut self, block_number: u32) {
         if !self.is_head {
             panic!("set_tail called on non-head node");
         }
-        let mut header = FileHeader::read_from(self.page.as_ref());
+        let mut header = FileHeader::read_from(self.buf());
         header.tail_page_num = block_number;
-        header.write_to(self.page.as_mut());
+        header.write_to(self.buf_mut());
     }
 
     pub fn tail_page(&self) -> u32 {
         if !self.is_head {
             panic!("tail_page called on non-head node");
         }
-        let header = FileHeader::read_from(self.page.as_ref());
+        let header = FileHeader::read_from(self.buf());
         header.tail_page_num
     }
 
@@ -81,79 +194,265 @@ impl<'a, const BLOCKSIZE: usize, const DISK_CAPACITY: usize, const MEMORY_CAPACI
         if !self.is_head {
             panic!("set_cell_count called on non-head node");
         }
-        let mut header = FileHeader::read_from(self.page.as_ref());
+        let mut header = FileHeader::read_from(self.buf());
         header.cell_count = count;
-        header.write_to(self.page.as_mut());
+        header.write_to(self.buf_mut());
     }
 
-    pub fn cell_count(&self) -> u64 {
+    pub fn set_modified(&mut self, modified: u64) {
         if !self.is_head {
-            panic!("cell_count called on non-head node");
+            panic!("set_modified called on non-head node");
         }
-        let header = FileHeader::read_from(self.page.as_ref());
-        header.cell_count
+        let mut header = FileHeader::read_from(self.buf());
+        header.modified = modified;
+        header.write_to(self.buf_mut());
     }
 
-    pub fn read_partial_record(&self, len: usize) -> Vec<u8> {
-        // Partial record is always at the start of the block, after the header
-        let range = FileNodeHeader::size()..FileNodeHeader::size() + len;
-        self.page.as_ref()[range].to_vec()
+    pub fn modified(&self) -> u64 {
+        if !self.is_head {
+            panic!("modified called on non-head node");
+        }
+        FileHeader::read_from(self.buf()).modified
+    }
+
+    pub fn set_page_count(&mut self, count: u32) {
+        if !self.is_head {
+            panic!("set_page_count called on non-head node");
+        }
+        let mut header = FileHeader::read_from(self.buf());
+        header.page_count = count;
+        header.write_to(self.buf_mut());
     }
 
-    /// ### Safety: Must ensure that `start` is correct
-    pub unsafe fn read_record_at(&self, start: usize) -> Option<Cell> {
-        let cell = Cell::new(start, &self.page)?;
-        Some(cell)
+    pub fn page_count(&self) -> u32 {
+        if !self.is_head {
+            panic!("page_count called on non-head node");
+        }
+        FileHeader::read_from(self.buf()).page_count
+    }
+
+    pub fn cell_count(&self) -> u64 {
+        if !self.is_head {
+            panic!("cell_count called on non-head node");
+        }
+        let header = FileHeader::read_from(self.buf());
+        header.cell_count
     }
 
     pub fn set_next(&mut self, next: u32) {
-        let header = FileNodeHeader::read_from(self.is_head, self.page.as_ref());
-        let page_header = FileNodeHeader {
-            free_space_start: header.free_space_start,
-            next,
-        };
-        page_header.write_to(self.is_head, self.page.as_mut());
+        let mut header = self.header();
+        header.next = next;
+        header.write_to(self.is_head, self.buf_mut());
+        self.store();
     }
 
     pub fn next(&self) -> Option<u32> {
-        let header = FileNodeHeader::read_from(self.is_head, self.page.as_ref());
-        let next = header.next;
+        let next = self.header().next;
         if next == 0 {
             return None;
         }
         Some(next)
     }
 
-    pub fn insert_spilled(&mut self, spilled: &[u8]) {
-        let offset = FileNodeHeader::size();
+    pub fn set_prev(&mut self, prev: u32) {
+        let mut header = self.header();
+        header.prev = prev;
+        header.write_to(self.is_head, self.buf_mut());
+        self.store();
+    }
 
-        let start = offset;
-        self.page.as_mut()[start..start + spilled.len()].copy_from_slice(spilled);
-        let page_header = FileNodeHeader {
-            free_space_start: (start + spilled.len()) as u32,
-            next: 0,
-        };
-        page_header.write_to(false, self.page.as_mut());
+    pub fn prev(&self) -> Option<u32> {
+        let prev = self.header().prev;
+        if prev == 0 {
+            return None;
+        }
+        Some(prev)
     }
 
-    pub unsafe fn delete_record_at(&mut self, start: usize) {
-        CellMut::new(start, self.page.as_mut()).set_delete(true);
+    /// Number of slots, including free ones
+    pub fn slot_count(&self) -> u32 {
+        self.header().slot_count
     }
 
-    pub fn insert(&mut self, payload: &'a [u8]) -> InsertResult {
-        let offset = self.free_start() as usize;
-        let insert_rs = cell::insert_cell(self.page.as_mut(), offset, payload);
-        return match insert_rs {
-            InsertResult::Normal(end) => {
-                self.set_free_start(end as u32);
-                insert_rs
-            }
-            InsertResult::Spill(_remain, _kept_size) => {
-                self.set_free_start(BLOCKSIZE as u32);
-                insert_rs
+    fn slot_offset(&self, slot: u32) -> usize {
+        let start = self.slots_start() + slot as usize * SLOT_SIZE;
+        u32::from_be_bytes(self.buf()[start..start + SLOT_SIZE].try_into().unwrap()) as usize
+    }
+
+    fn set_slot_offset(&mut self, slot: u32, offset: usize) {
+        let start = self.slots_start() + slot as usize * SLOT_SIZE;
+        self.buf_mut()[start..start + SLOT_SIZE].copy_from_slice(&(offset as u32).to_be_bytes());
+    }
+
+    /// Cell of a slot, `None` if the slot does not exist or is free
+    pub fn cell(&self, slot: u32) -> Option<Cell<'_>> {
+        if slot >= self.slot_count() {
+            return None;
+        }
+        match self.slot_offset(slot) {
+            0 => None,
+            offset => Some(unsafe { Cell::new(offset, self.buf()) }),
+        }
+    }
+
+    /// Space between the slot array and the cells
+    fn contiguous_free_space(&self) -> usize {
+        let header = self.header();
+        header.cell_content_start as usize
+            - (self.slots_start() + header.slot_count as usize * SLOT_SIZE)
+    }
+
+    /// Free space, including the holes left between the cells by deleted records
+    pub fn free_space(&self) -> usize {
+        let used: usize = (0..self.slot_count())
+            .filter_map(|slot| self.cell(slot).map(|cell| cell.cell_size()))
+            .sum();
+        let slots_end = self.slots_start() + self.slot_count() as usize * SLOT_SIZE;
+        self.buf().len() - slots_end - used
+    }
+
+    fn free_slot(&self) -> Option<u32> {
+        (0..self.slot_count()).find(|slot| self.slot_offset(*slot) == 0)
+    }
+
+    /// Take `size` bytes from the free space for a new cell, compacting the page if needed.
+    /// The caller must have checked that the page has enough free space.
+    fn allocate_cell(&mut self, size: usize) -> usize {
+        if size > self.contiguous_free_space() {
+            self.compact();
+        }
+        let mut header = self.header();
+        header.cell_content_start -= size as u32;
+        header.write_to(self.is_head, self.buf_mut());
+        header.cell_content_start as usize
+    }
+
+    /// Store a record in the page and return its slot,
+    /// or `None` if the page does not have enough space left.
+    /// The page is compacted if the record only fits in the holes between cells.
+    /// A record added to a compressed page is appended to the block as it is,
+    /// the page is only compressed again once the block is full.
+    pub fn insert(&mut self, payload: &[u8], flags: u8) -> Option<u32> {
+        if self.image.is_none() {
+            return self.insert_cell(payload, flags);
+        }
+        let content_start = self.header().cell_content_start as usize;
+        let slot = self.insert_cell(payload, flags)?;
+        let size = cell::cell_size(payload.len());
+        // A compaction moves the other cells, the page is then compressed again
+        let appended = self.header().cell_content_start as usize + size == content_start
+            && compression::append(
+                &mut self.page,
+                payload,
+                flags,
+                size + SLOT_SIZE,
+                compression::SLACK,
+            );
+        if appended || self.try_store(compression::SLACK) {
+            return Some(slot);
+        }
+        // The page is sealed, the block was left untouched
+        self.decode();
+        None
+    }
+
+    fn insert_cell(&mut self, payload: &[u8], flags: u8) -> Option<u32> {
+        let free_slot = self.free_slot();
+        let size = cell::cell_size(payload.len());
+        let needed = size + if free_slot.is_some() { 0 } else { SLOT_SIZE };
+        if needed > self.free_space() {
+            return None;
+        }
+        // A new slot and the cell are both taken from the contiguous free space,
+        // which must have room for them before the slot array grows
+        if needed > self.contiguous_free_space() {
+            self.compact();
+        }
+        let slot = match free_slot {
+            Some(slot) => slot,
+            None => {
+                let mut header = self.header();
+                header.slot_count += 1;
+                header.write_to(self.is_head, self.buf_mut());
+                let slot = header.slot_count - 1;
+                // The slot is free until the cell is written
+                self.set_slot_offset(slot, 0);
+                slot
             }
-            InsertResult::OutOfSpace(_payload) => insert_rs,
         };
+        let offset = self.allocate_cell(size);
+        cell::write_cell(self.buf_mut(), offset, payload, flags);
+        self.set_slot_offset(slot, offset);
+        Some(slot)
+    }
+
+    /// Replace the cell of a record, moving it inside the page if it grows.
+    /// Return false, leaving the page untouched, if the page does not have room for it.
+    pub fn replace(&mut self, slot: u32, payload: &[u8], flags: u8) -> bool {
+        // A cell rewritten in place may use some of the room kept free in a compressed page
+        let in_place = cell::cell_size(payload.len()) <= self.cell(slot).unwrap().cell_size();
+        let reserve = if in_place {
+            compression::LINK_SLACK
+        } else {
+            compression::SLACK
+        };
+        self.try_change(reserve, |node| {
+            node.replace_cell(slot, payload, flags).then_some(())
+        })
+        .is_some()
+    }
+
+    fn replace_cell(&mut self, slot: u32, payload: &[u8], flags: u8) -> bool {
+        let old_size = self.cell(slot).unwrap().cell_size();
+        let size = cell::cell_size(payload.len());
+        let offset = if size <= old_size {
+            self.slot_offset(slot)
+        } else if size <= self.free_space() + old_size {
+            // The old cell becomes a hole
+            self.set_slot_offset(slot, 0);
+            self.allocate_cell(size)
+        } else {
+            return false;
+        };
+        cell::write_cell(self.buf_mut(), offset, payload, flags);
+        self.set_slot_offset(slot, offset);
+        true
+    }
+
+    /// Free the slot of a record. The space of its cell is reclaimed by the next compaction.
+    pub fn delete(&mut self, slot: u32) {
+        self.set_slot_offset(slot, 0);
+        // Trailing free slots are dropped to give their space back
+        let mut header = self.header();
+        while header.slot_count > 0 && self.slot_offset(header.slot_count - 1) == 0 {
+            header.slot_count -= 1;
+        }
+        header.write_to(self.is_head, self.buf_mut());
+        self.store();
+    }
+
+    /// Move every cell to the end of the page, so the holes left by deleted records
+    /// are merged into the free space. Slots keep pointing to their record.
+    pub fn compact(&mut self) {
+        let mut cells: Vec<(u32, usize, Vec<u8>)> = (0..self.slot_count())
+            .filter_map(|slot| {
+                let offset = self.slot_offset(slot);
+                let size = self.cell(slot)?.cell_size();
+                Some((slot, offset, self.buf()[offset..offset + size].to_vec()))
+            })
+            .collect();
+        // Keep the cells in the same order to move as little as possible
+        cells.sort_by_key(|(_, offset, _)| std::cmp::Reverse(*offset));
+        let mut end = self.buf().len();
+        for (slot, _, cell) in cells {
+            let start = end - cell.len();
+            self.buf_mut()[start..end].copy_from_slice(&cell);
+            self.set_slot_offset(slot, start);
+            end = start;
+        }
+        let mut header = self.header();
+        header.cell_content_start = end as u32;
+        header.write_to(self.is_head, self.buf_mut());
     }
 }
 
@@ -172,57 +471,48 @@ mod tests {
         let block1 = disk_manager.allocate().unwrap();
         let block2 = disk_manager.allocate().unwrap();
         const MEMORY_SIZE: usize = 512 * 16;
-        let memory = vec![0; MEMORY_SIZE];
-        let buffer_manager: BufferManager<512, 65536, MEMORY_SIZE> =
-            BufferManager::init(&memory, &disk);
-        let mut root: Node<'_, 512, 65536, MEMORY_SIZE> =
-            Node::new(true, buffer_manager.get_page(block1));
+        let buffer_manager: BufferManager<512, 65536> =
+            BufferManager::init(MEMORY_SIZE / 512, &disk);
+        let mut root: Node<'_, 512, 65536> = Node::new(true, buffer_manager.get_page(block1));
 
         root.set_next(block2 as u32);
         assert_eq!(root.next(), Some(block2 as u32));
+        assert_eq!(root.prev(), None);
+        root.set_prev(block1);
+        assert_eq!(root.prev(), Some(block1));
+        assert_eq!(root.next(), Some(block2));
     }
 
     #[test]
-    fn insert_spilled() {
-        let disk = Disk::<512, 65536>::create("node_insert_spilled").unwrap();
+    fn insert_delete_compact() {
+        let disk = Disk::<512, 65536>::create("node_insert_delete_compact").unwrap();
         let disk_manager = DiskManager::init(&disk);
-        let block1 = disk_manager.allocate().unwrap();
-        let block2 = disk_manager.allocate().unwrap();
-        const MEMORY_SIZE: usize = 512 * 16;
-        let memory = vec![0; MEMORY_SIZE];
-        let buffer_manager: BufferManager<512, 65536, MEMORY_SIZE> =
-            BufferManager::init(&memory, &disk);
-        let buf = vec![0xa; 400];
-        let buf2 = vec![0xa; 400];
-        let mut node: Node<'_, 512, 65536, MEMORY_SIZE> =
-            Node::new(true, buffer_manager.get_page(block1));
-        node.insert(&buf);
-        let rs = node.insert(&buf2);
-        let mut node2: Node<'_, 512, 65536, MEMORY_SIZE> =
-            Node::new(false, buffer_manager.get_page(block1));
-        // match rs {
-        //     InsertResult::Spill(buf, start) => node2.insert_spilled(&buf[start..]),
-        //     _ => panic!("should be spilled"),
-        // }
-        // let rs = unsafe { node.read_record_at(429) };
-        // match rs {
-        //     ReadResult::Partial(initial, remain) => {
-        //         assert_eq!(initial.len(), 80);
-        //         assert_eq!(remain, 120);
-        //     }
-        //     _ => panic!("should be partial"),
-        // }
-        // let buf = vec![0xff; 200];
-        // let rs = node2.insert(Cell::new(buf));
-        // println!("{:?}", node2.buffer.as_ref());
-        // match rs {
-        //     InsertResult::Normal => {}
-        //     _ => panic!("should be normal"),
-        // }
-        // let rs = unsafe { node2.read_record_at(120 + 8) };
-        // match rs {
-        //     ReadResult::Normal(cell) => assert_eq!(cell.len(), 200),
-        //     _ => panic!("should be normal"),
-        // }
+        let block = disk_manager.allocate().unwrap();
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(4, &disk);
+        let mut node: Node<'_, 512, 65536> = Node::new(false, buffer_manager.get_page(block));
+        let slots: Vec<u32> = (0..4)
+            .map(|i| node.insert(&[i as u8; 100], 0).unwrap())
+            .collect();
+        assert_eq!(slots, vec![0, 1, 2, 3]);
+        assert_eq!(node.insert(&[0xff; 100], 0), None);
+
+        // The holes left by deleted records are only usable after a compaction
+        node.delete(0);
+        node.delete(2);
+        assert_eq!(node.cell(0), None);
+        assert_eq!(node.insert(&[0xff; 150], 0), Some(0));
+        assert_eq!(node.cell(0).unwrap().payload(), &[0xff; 150]);
+        assert_eq!(node.cell(1).unwrap().payload(), &[1; 100]);
+        assert_eq!(node.cell(3).unwrap().payload(), &[3; 100]);
+
+        // Cells can grow in place as long as the page has room
+        assert!(node.replace(1, &[0xee; 150], 0));
+        assert!(!node.replace(3, &[0xdd; 200], 0));
+        assert_eq!(node.cell(1).unwrap().payload(), &[0xee; 150]);
+        assert_eq!(node.cell(3).unwrap().payload(), &[3; 100]);
+
+        // Trailing free slots are dropped
+        node.delete(3);
+        assert_eq!(node.slot_count(), 2);
     }
 }
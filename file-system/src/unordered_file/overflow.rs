@@ -0,0 +1,150 @@
+//! Records too large to be stored in the pages of a file are kept in a chain of
+//! overflow pages. The file only stores a small stub cell pointing to the chain.
+//! Each overflow page starts with the number of the next page of the chain,
+//! or 0 for the last one, followed by a part of the record.
+
+use std::mem::size_of;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use buffer_manager::{BufferManager, BufferManagerError};
+use disk_manager::{DiskManager, DiskManagerError};
+
+/// How long to wait for a page which is still pinned by a prefetch before freeing it
+const FREE_PAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Content of the cell standing in for a record stored in an overflow chain
+#[derive(Debug, PartialEq)]
+pub struct OverflowStub {
+    pub payload_size: u32,
+    pub head_page_num: u32,
+}
+
+impl OverflowStub {
+    pub const fn size() -> usize {
+        size_of::<u32>() * 2
+    }
+
+    pub fn read_from(buffer: &[u8]) -> Self {
+        Self {
+            payload_size: u32::from_be_bytes(buffer[0..4].try_into().unwrap()),
+            head_page_num: u32::from_be_bytes(buffer[4..8].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::size()] {
+        let mut buffer = [0; Self::size()];
+        buffer[0..4].copy_from_slice(&self.payload_size.to_be_bytes());
+        buffer[4..8].copy_from_slice(&self.head_page_num.to_be_bytes());
+        buffer
+    }
+}
+
+const fn data_size<const BLOCKSIZE: usize>() -> usize {
+    BLOCKSIZE - size_of::<u32>()
+}
+
+fn next_of(page: &[u8]) -> u32 {
+    u32::from_be_bytes(page[0..4].try_into().unwrap())
+}
+
+/// Store `payload` in a new overflow chain and return the stub pointing to it.
+/// Overflow pages are written to the disk right away.
+/// Fail without allocating anything if the disk does not have room for the chain.
+pub fn write<const BLOCKSIZE: usize, const CAPACITY: usize>(
+    disk_manager: &DiskManager<BLOCKSIZE, CAPACITY>,
+    buffer_manager: &BufferManager<BLOCKSIZE, CAPACITY>,
+    payload: &[u8],
+) -> Result<OverflowStub, DiskManagerError> {
+    let chunks: Vec<&[u8]> = payload.chunks(data_size::<BLOCKSIZE>()).collect();
+    let pages = disk_manager.allocate_many(chunks.len())?;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let next = pages.get(i + 1).copied().unwrap_or(0);
+        let mut page = buffer_manager.get_page(pages[i]);
+        page[0..4].copy_from_slice(&next.to_be_bytes());
+        page[4..4 + chunk.len()].copy_from_slice(chunk);
+        drop(page);
+        buffer_manager.save_page(pages[i]).unwrap();
+    }
+    Ok(OverflowStub {
+        payload_size: payload.len() as u32,
+        head_page_num: pages[0],
+    })
+}
+
+/// Read back a record stored in an overflow chain
+pub fn read<const BLOCKSIZE: usize, const CAPACITY: usize>(
+    buffer_manager: &BufferManager<BLOCKSIZE, CAPACITY>,
+    stub: &OverflowStub,
+) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(stub.payload_size as usize);
+    let mut page_num = stub.head_page_num;
+    while payload.len() < stub.payload_size as usize {
+        let page = buffer_manager.get_page(page_num);
+        let next = next_of(&page);
+        if next != 0 {
+            buffer_manager.prefetch(&[next]);
+        }
+        let len = (stub.payload_size as usize - payload.len()).min(data_size::<BLOCKSIZE>());
+        payload.extend_from_slice(&page[4..4 + len]);
+        page_num = next;
+    }
+    payload
+}
+
+/// Deallocate every page of an overflow chain and drop them from the buffer pool
+pub fn free<const BLOCKSIZE: usize, const CAPACITY: usize>(
+    disk_manager: &DiskManager<BLOCKSIZE, CAPACITY>,
+    buffer_manager: &BufferManager<BLOCKSIZE, CAPACITY>,
+    stub: &OverflowStub,
+) {
+    let mut page_num = stub.head_page_num;
+    while page_num != 0 {
+        let next = next_of(&buffer_manager.get_page(page_num));
+        free_page(disk_manager, buffer_manager, page_num);
+        page_num = next;
+    }
+}
+
+/// Drop a page from the buffer pool and give it back to the disk manager.
+/// A prefetch started by a reader may still pin the page for a moment, wait for it.
+pub(super) fn free_page<const BLOCKSIZE: usize, const CAPACITY: usize>(
+    disk_manager: &DiskManager<BLOCKSIZE, CAPACITY>,
+    buffer_manager: &BufferManager<BLOCKSIZE, CAPACITY>,
+    page_num: u32,
+) {
+    let start = Instant::now();
+    loop {
+        match buffer_manager.discard_page(page_num) {
+            Err(BufferManagerError::PagePinned(_)) if start.elapsed() < FREE_PAGE_TIMEOUT => {
+                thread::yield_now()
+            }
+            result => break result.unwrap(),
+        }
+    }
+    disk_manager.deallocate(page_num).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disk::Disk;
+
+    #[test]
+    fn write_read_free() {
+        let disk = Disk::<512, 65536>::create("overflow::write_read_free").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(4, &disk);
+        let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let stub = write(&disk_manager, &buffer_manager, &payload).unwrap();
+        assert_eq!(stub.payload_size, 3000);
+        assert_eq!(OverflowStub::read_from(&stub.to_bytes()), stub);
+        assert_eq!(read(&buffer_manager, &stub), payload);
+
+        free(&disk_manager, &buffer_manager, &stub);
+        // Freed pages are reused by the next chain
+        let stub2 = write(&disk_manager, &buffer_manager, &payload[..100]).unwrap();
+        assert_eq!(stub2.head_page_num, stub.head_page_num);
+        assert_eq!(read(&buffer_manager, &stub2), &payload[..100]);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::btree_index::btree::RowAddress;
+
+/// Address of a record of a `File`, returned by `File::insert`.
+/// The slot indexes the slot array of the page, so the id does not change
+/// when the page is compacted. It stays valid until the record is deleted
+/// or the file is vacuumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecordId {
+    page: u32,
+    slot: u32,
+}
+
+impl RecordId {
+    pub fn new(page: u32, slot: u32) -> Self {
+        Self { page, slot }
+    }
+
+    pub fn page(&self) -> u32 {
+        self.page
+    }
+
+    pub fn slot(&self) -> u32 {
+        self.slot
+    }
+
+    pub(super) const fn size() -> usize {
+        std::mem::size_of::<u32>() * 2
+    }
+
+    pub(super) fn read_from(buffer: &[u8]) -> Self {
+        Self {
+            page: u32::from_be_bytes(buffer[0..4].try_into().unwrap()),
+            slot: u32::from_be_bytes(buffer[4..8].try_into().unwrap()),
+        }
+    }
+
+    pub(super) fn to_bytes(self) -> [u8; Self::size()] {
+        let mut buffer = [0; Self::size()];
+        buffer[0..4].copy_from_slice(&self.page.to_be_bytes());
+        buffer[4..8].copy_from_slice(&self.slot.to_be_bytes());
+        buffer
+    }
+}
+
+/// Index entries point to records through their `RowAddress`
+impl From<RecordId> for RowAddress {
+    fn from(rid: RecordId) -> Self {
+        RowAddress::new(rid.page, rid.slot)
+    }
+}
+
+impl From<RowAddress> for RecordId {
+    fn from(address: RowAddress) -> Self {
+        RecordId::new(address.page_number(), address.offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_address_conversion() {
+        let rid = RecordId::new(3, 42);
+        let address: RowAddress = rid.into();
+        assert_eq!(address, RowAddress::new(3, 42));
+        assert_eq!(RecordId::from(address), rid);
+    }
+}
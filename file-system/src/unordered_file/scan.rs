@@ -0,0 +1,174 @@
+use super::{
+    node::Node,
+    overflow::{self, OverflowStub},
+    File, RecordId,
+};
+
+/// Projection of a `Scan` until `Scan::project` is called, copying the records
+pub type CopyRecord = fn(&[u8]) -> Vec<u8>;
+
+/// Walk the records of a `File` matching a predicate.
+/// The predicate and the projection see the bytes of a record in its page,
+/// only records stored in overflow chains are copied before being checked.
+pub struct Scan<'a, const BLOCKSIZE: usize, const CAPACITY: usize, P, F> {
+    file: File<'a, BLOCKSIZE, CAPACITY>,
+    /// Page being scanned, `None` once the scan is over
+    node: Option<Node<'a, BLOCKSIZE, CAPACITY>>,
+    page_number: u32,
+    slot: u32,
+    predicate: P,
+    projection: F,
+}
+
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, P>
+    Scan<'a, BLOCKSIZE, CAPACITY, P, CopyRecord>
+where
+    P: FnMut(&[u8]) -> bool,
+{
+    pub fn new(file: &File<'a, BLOCKSIZE, CAPACITY>, predicate: P) -> Self {
+        let file = File::open(
+            file.buffer_manager,
+            file.disk_manager,
+            file.head_page_number,
+        );
+        let head = file.node(file.head_page_number);
+        if let Some(next) = head.next() {
+            file.buffer_manager.prefetch(&[next]);
+        }
+        Self {
+            page_number: file.head_page_number,
+            node: Some(head),
+            file,
+            slot: 0,
+            predicate,
+            projection: <[u8]>::to_vec,
+        }
+    }
+}
+
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, P, F> Scan<'a, BLOCKSIZE, CAPACITY, P, F> {
+    /// Yield `projection` of the matching records instead of a copy of them
+    pub fn project<G, T>(self, projection: G) -> Scan<'a, BLOCKSIZE, CAPACITY, P, G>
+    where
+        G: FnMut(&[u8]) -> T,
+    {
+        Scan {
+            file: self.file,
+            node: self.node,
+            page_number: self.page_number,
+            slot: self.slot,
+            predicate: self.predicate,
+            projection,
+        }
+    }
+
+    fn next_page(&mut self) {
+        let next = self.node.take().and_then(|node| node.next());
+        if let Some(page_number) = next {
+            let node = self.file.node(page_number);
+            if let Some(next) = node.next() {
+                self.file.buffer_manager.prefetch(&[next]);
+            }
+            self.node = Some(node);
+            self.page_number = page_number;
+            self.slot = 0;
+        }
+    }
+}
+
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, P, F, T> Iterator
+    for Scan<'a, BLOCKSIZE, CAPACITY, P, F>
+where
+    P: FnMut(&[u8]) -> bool,
+    F: FnMut(&[u8]) -> T,
+{
+    type Item = (RecordId, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.node.as_ref()?;
+            if self.slot >= node.slot_count() {
+                self.next_page();
+                continue;
+            }
+            let rid = RecordId::new(self.page_number, self.slot);
+            self.slot += 1;
+
+            let cell = match node.cell(rid.slot()) {
+                Some(cell) if !cell.is_moved() => cell,
+                _ => continue,
+            };
+            // Moved records are checked at the page they were moved to
+            let target_node;
+            let cell = if cell.is_forward() {
+                let target = RecordId::read_from(cell.payload());
+                target_node = self.file.node(target.page());
+                target_node.cell(target.slot()).unwrap()
+            } else {
+                cell
+            };
+            let matched = if cell.is_overflow() {
+                let stub = OverflowStub::read_from(cell.payload());
+                let record = overflow::read(self.file.buffer_manager, &stub);
+                (self.predicate)(&record).then(|| (self.projection)(&record))
+            } else {
+                let record = cell.payload();
+                (self.predicate)(record).then(|| (self.projection)(record))
+            };
+            if let Some(item) = matched {
+                return Some((rid, item));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buffer_manager::BufferManager;
+    use disk::Disk;
+    use disk_manager::DiskManager;
+
+    use crate::unordered_file::File;
+
+    #[test]
+    fn predicate_projection() {
+        let disk = Disk::<512, 65536>::create("scan::predicate_projection").unwrap();
+        let disk_manager = DiskManager::init(&disk);
+        let buffer_manager: BufferManager<512, 65536> = BufferManager::init(16, &disk);
+        let file = File::init(&disk_manager, &buffer_manager);
+        let rids: Vec<_> = (0..30u8)
+            .map(|i| {
+                let size = if i == 20 { 1000 } else { 50 };
+                let mut record = vec![i; size];
+                record[1] = i % 3;
+                file.insert(&record)
+            })
+            .collect();
+        file.delete(rids[3]).unwrap();
+        // Moved and overflow records are seen once, at their id
+        let mut moved = vec![6; 200];
+        moved[1] = 0;
+        file.update(rids[6], &moved).unwrap();
+        assert_ne!(file.resolve(rids[6]), Ok(rids[6]));
+
+        let matches: Vec<_> = file.scan(|record| record[1] == 0).collect();
+        let expected: Vec<u8> = (0..30).filter(|i| i % 3 == 0 && *i != 3).collect();
+        assert_eq!(
+            matches.iter().map(|(rid, _)| *rid).collect::<Vec<_>>(),
+            expected
+                .iter()
+                .map(|i| rids[*i as usize])
+                .collect::<Vec<_>>()
+        );
+        for (rid, record) in &matches {
+            assert_eq!(record, &file.get(*rid).unwrap());
+        }
+
+        let lengths: Vec<_> = file
+            .scan(|record| record[0] % 10 == 0)
+            .project(|record| record.len())
+            .map(|(_, len)| len)
+            .collect();
+        assert_eq!(lengths, vec![50, 50, 1000]);
+    }
+}
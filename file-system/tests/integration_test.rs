@@ -38,13 +38,9 @@ fn no_replacement() {
     ];
 
     {
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager = BufferManager::init(&memory, &disk);
-        let file_system = FileSystem::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::init(
-            &buffer_manager,
-            &disk_manager,
-        )
-        .unwrap();
+        let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+        let file_system =
+            FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
         let file1 = file_system.create_file("file1").unwrap();
         let file2 = file_system.create_file("file2").unwrap();
         for cell in cells.clone().iter() {
@@ -59,13 +55,9 @@ fn no_replacement() {
         file2.save();
     }
     {
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager = BufferManager::init(&memory, &disk);
-        let file_system = FileSystem::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::open(
-            &buffer_manager,
-            &disk_manager,
-        )
-        .unwrap();
+        let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+        let file_system =
+            FileSystem::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager).unwrap();
         let file1 = file_system.open_file("file1").unwrap();
         let file2 = file_system.open_file("file2").unwrap();
 
@@ -118,13 +110,9 @@ fn need_replacement() {
     }
 
     {
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager = BufferManager::init(&memory, &disk);
-        let file_system = FileSystem::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::init(
-            &buffer_manager,
-            &disk_manager,
-        )
-        .unwrap();
+        let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+        let file_system =
+            FileSystem::<BLOCKSIZE, CAPACITY>::init(&buffer_manager, &disk_manager).unwrap();
         let file1 = file_system.create_file("file1").unwrap();
         let file2 = file_system.create_file("file2").unwrap();
         for cell in cells.clone().iter() {
@@ -139,13 +127,9 @@ fn need_replacement() {
         file2.save();
     }
     {
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager = BufferManager::init(&memory, &disk);
-        let file_system = FileSystem::<BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>::open(
-            &buffer_manager,
-            &disk_manager,
-        )
-        .unwrap();
+        let buffer_manager = BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
+        let file_system =
+            FileSystem::<BLOCKSIZE, CAPACITY>::open(&buffer_manager, &disk_manager).unwrap();
         let file1 = file_system.open_file("file1").unwrap();
         let file2 = file_system.open_file("file2").unwrap();
 
@@ -42,12 +42,13 @@ fn make_table(schema: &Schema) -> TableView<Record, ColumnBase> {
 }
 
 struct App {
-    file_system: file_system::FileSystem<'static, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+    file_system: file_system::FileSystem<'static, BLOCKSIZE, CAPACITY>,
 }
 
 impl App {
-    fn init(memory: &'static [u8; MEMORY_CAPACITY], disk: &disk::Disk<BLOCKSIZE, CAPACITY>) -> Self {
-        let buffer_manager = buffer_manager::BufferManager::init(memory, &disk);
+    fn init(disk: &disk::Disk<BLOCKSIZE, CAPACITY>) -> Self {
+        let buffer_manager =
+            buffer_manager::BufferManager::init(MEMORY_CAPACITY / BLOCKSIZE, &disk);
         let disk_manager = disk_manager::DiskManager::init(&disk);
         App {
             file_system: file_system::FileSystem::open(&buffer_manager, &disk_manager).unwrap(),
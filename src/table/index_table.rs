@@ -2,14 +2,12 @@ use file_system::btree_index::btree::{BTree, KeyExistedError, RowAddress};
 
 pub struct Index(Vec<u8>, RowAddress);
 
-pub struct Table<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> {
-    btree: BTree<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+pub struct Table<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    btree: BTree<'a, BLOCKSIZE, CAPACITY>,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    Table<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
-    pub fn new(btree: BTree<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>) -> Self {
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> Table<'a, BLOCKSIZE, CAPACITY> {
+    pub fn new(btree: BTree<'a, BLOCKSIZE, CAPACITY>) -> Self {
         Self { btree }
     }
 
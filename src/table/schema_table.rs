@@ -2,29 +2,22 @@ use file_system::unordered_file::{Cursor, File};
 
 use super::schema::Schema;
 
-pub struct SchemaTable<
-    'a,
-    const BLOCKSIZE: usize,
-    const CAPACITY: usize,
-    const MEMORY_CAPACITY: usize,
-> {
-    file: File<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+pub struct SchemaTable<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    file: File<'a, BLOCKSIZE, CAPACITY>,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    SchemaTable<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
-    pub fn new(file: File<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>) -> Self {
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> SchemaTable<'a, BLOCKSIZE, CAPACITY> {
+    pub fn new(file: File<'a, BLOCKSIZE, CAPACITY>) -> Self {
         Self { file }
     }
 
-    fn cursor(&'a self) -> Cursor<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> {
+    fn cursor(&'a self) -> Cursor<'a, BLOCKSIZE, CAPACITY> {
         self.file.cursor()
     }
 
     pub fn save_schema(&self, schema: Schema) {
         for s in schema.serialize() {
-            self.file.insert(&s)
+            self.file.insert(&s);
         }
     }
 
@@ -53,9 +46,8 @@ mod tests {
     fn basic() {
         let disk = disk::Disk::<4096, 819200>::create("schema_basic").unwrap();
         const MEMORY_CAPACITY: usize = 4096 * 32;
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager: BufferManager<4096, 819200, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<4096, 819200> =
+            BufferManager::init(MEMORY_CAPACITY / 4096, &disk);
         let disk_manager = DiskManager::init(&disk);
         let file_system = FileSystem::init(&buffer_manager, &disk_manager).unwrap();
         let table = SchemaTable::new(file_system.create_file("test1").unwrap());
@@ -3,27 +3,74 @@ use super::{
     schema::Schema,
 };
 
-use file_system::unordered_file::{Cursor, File};
+use file_system::unordered_file::{Cursor, File, FileError, RecordId};
 
-pub struct Table<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize> {
-    file: File<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>,
+#[derive(Debug, PartialEq)]
+pub enum TableError {
+    InvalidSchema(InvalidSchema),
+    File(FileError),
+}
+
+impl From<InvalidSchema> for TableError {
+    fn from(err: InvalidSchema) -> Self {
+        Self::InvalidSchema(err)
+    }
+}
+
+impl From<FileError> for TableError {
+    fn from(err: FileError) -> Self {
+        Self::File(err)
+    }
+}
+
+pub struct Table<'a, const BLOCKSIZE: usize, const CAPACITY: usize> {
+    file: File<'a, BLOCKSIZE, CAPACITY>,
     schema: &'a Schema,
 }
 
-impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize, const MEMORY_CAPACITY: usize>
-    Table<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>
-{
-    pub fn new(file: File<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY>, schema: &'a Schema) -> Self {
+impl<'a, const BLOCKSIZE: usize, const CAPACITY: usize> Table<'a, BLOCKSIZE, CAPACITY> {
+    pub fn new(file: File<'a, BLOCKSIZE, CAPACITY>, schema: &'a Schema) -> Self {
         Self { file, schema }
     }
 
-    pub fn insert(&mut self, record: Record) -> Result<(), InvalidSchema> {
+    pub fn insert(&mut self, record: Record) -> Result<RecordId, InvalidSchema> {
         let cell = &record.to_bytes(self.schema)?;
-        self.file.insert(cell);
+        Ok(self.file.insert(cell))
+    }
+
+    pub fn get(&self, rid: RecordId) -> Result<Record, TableError> {
+        let cell = self.file.get(rid)?;
+        Ok(Record::from_bytes(cell, self.schema)?)
+    }
+
+    /// Replace the record of `rid`, which keeps its id even if the record grows
+    pub fn update(&mut self, rid: RecordId, record: Record) -> Result<(), TableError> {
+        let cell = &record.to_bytes(self.schema)?;
+        self.file.update(rid, cell)?;
         Ok(())
     }
 
-    pub fn cursor(&'a self) -> Cursor<'a, BLOCKSIZE, CAPACITY, MEMORY_CAPACITY> {
+    /// Records whose encoded bytes match `predicate`.
+    /// Records are checked in their page, only the matching ones are copied and decoded.
+    pub fn scan<P>(
+        &self,
+        predicate: P,
+    ) -> impl Iterator<Item = Result<(RecordId, Record), InvalidSchema>> + 'a
+    where
+        P: FnMut(&[u8]) -> bool + 'a,
+    {
+        let schema = self.schema;
+        self.file
+            .scan(predicate)
+            .map(move |(rid, bytes)| Ok((rid, Record::from_bytes(bytes, schema)?)))
+    }
+
+    /// Remove every record of the table
+    pub fn truncate(&mut self) {
+        self.file.truncate()
+    }
+
+    pub fn cursor(&'a self) -> Cursor<'a, BLOCKSIZE, CAPACITY> {
         self.file.cursor()
     }
 
@@ -40,19 +87,18 @@ mod tests {
     use file_system::FileSystem;
 
     use crate::table::{
-        record::{Field, Record},
+        record::{Field, InvalidSchema, Record},
         schema::{DataType, Schema},
     };
 
-    use super::Table;
+    use super::{Table, TableError};
 
     #[test]
     fn basic() {
         let disk = disk::Disk::<4096, 819200>::create("table_basic").unwrap();
         const MEMORY_CAPACITY: usize = 4096 * 32;
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager: BufferManager<4096, 819200, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<4096, 819200> =
+            BufferManager::init(MEMORY_CAPACITY / 4096, &disk);
         let disk_manager = DiskManager::init(&disk);
         let file_system = FileSystem::init(&buffer_manager, &disk_manager).unwrap();
         let schema = Schema {
@@ -85,13 +131,63 @@ mod tests {
         assert_eq!(record, record2);
     }
 
+    #[test]
+    fn update() {
+        let disk = disk::Disk::<512, 819200>::create("table_update").unwrap();
+        let buffer_manager: BufferManager<512, 819200> = BufferManager::init(32, &disk);
+        let disk_manager = DiskManager::init(&disk);
+        let file_system = FileSystem::init(&buffer_manager, &disk_manager).unwrap();
+        let schema = Schema {
+            schema: vec![
+                (String::new(), DataType::UInt),
+                (String::new(), DataType::VarChar(255)),
+            ],
+        };
+        let mut table = Table::new(file_system.create_file("test1").unwrap(), &schema);
+        let record = |i: u32, name: &[u8]| Record {
+            data: vec![Field::UInt(Some(i)), Field::VarChar(Some(name.to_vec()))],
+        };
+
+        let rids: Vec<_> = (0..10)
+            .map(|i| table.insert(record(i, b"a")).unwrap())
+            .collect();
+        // Growing records are moved out of their page but keep their id
+        for (i, rid) in rids.iter().enumerate() {
+            table.update(*rid, record(i as u32, &[b'b'; 200])).unwrap();
+        }
+        for (i, rid) in rids.iter().enumerate() {
+            assert_eq!(table.get(*rid).unwrap(), record(i as u32, &[b'b'; 200]));
+        }
+        assert_eq!(table.cursor().count(), 10);
+
+        let invalid = Record {
+            data: vec![Field::Char(Some(b"a".to_vec()))],
+        };
+        assert_eq!(
+            table.update(rids[0], invalid),
+            Err(TableError::InvalidSchema(InvalidSchema))
+        );
+
+        table.insert(record(10, b"a")).unwrap();
+        // Only the records whose name starts with a 'b' are decoded
+        let matches: Vec<_> = table
+            .scan(|bytes| bytes[5] == b'b')
+            .map(|result| result.unwrap().0)
+            .collect();
+        assert_eq!(matches, rids);
+
+        table.truncate();
+        assert_eq!(table.cursor().count(), 0);
+        let rid = table.insert(record(42, b"c")).unwrap();
+        assert_eq!(table.get(rid).unwrap(), record(42, b"c"));
+    }
+
     #[test]
     fn simple_insert() {
         let disk = disk::Disk::<4096, 819200>::create("table_simple_insert").unwrap();
         const MEMORY_CAPACITY: usize = 4096 * 32;
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager: BufferManager<4096, 819200, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<4096, 819200> =
+            BufferManager::init(MEMORY_CAPACITY / 4096, &disk);
         let disk_manager = DiskManager::init(&disk);
         let file_system = FileSystem::init(&buffer_manager, &disk_manager).unwrap();
         let schema = Schema {
@@ -128,9 +224,8 @@ mod tests {
         const CAPACITY: usize = 512 * 4096;
         let disk = Disk::<4096, CAPACITY>::create("table_big_record_insert").unwrap();
         const MEMORY_CAPACITY: usize = 4096 * 32;
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager: BufferManager<4096, CAPACITY, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<4096, CAPACITY> =
+            BufferManager::init(MEMORY_CAPACITY / 4096, &disk);
         let disk_manager = DiskManager::init(&disk);
         let file_system = FileSystem::init(&buffer_manager, &disk_manager).unwrap();
         let schema = Schema {
@@ -183,9 +278,8 @@ mod tests {
         const CAPACITY: usize = 512 * 4096;
         let disk = Disk::<512, CAPACITY>::create("test_table_a_lot_of_insert").unwrap();
         const MEMORY_CAPACITY: usize = 4096 * 32;
-        let memory = [0; MEMORY_CAPACITY];
-        let buffer_manager: BufferManager<512, CAPACITY, MEMORY_CAPACITY> =
-            BufferManager::init(&memory, &disk);
+        let buffer_manager: BufferManager<512, CAPACITY> =
+            BufferManager::init(MEMORY_CAPACITY / 512, &disk);
         let disk_manager = DiskManager::init(&disk);
         let file_system = FileSystem::init(&buffer_manager, &disk_manager).unwrap();
         let schema = Schema {